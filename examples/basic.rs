@@ -41,7 +41,7 @@ async fn main() -> Result<()> {
   let channels = args.channel;
 
   println!("Connecting as {}", credentials.login());
-  let mut client = tmi::Client::builder()
+  let client = tmi::Client::builder()
     .credentials(credentials)
     .connect()
     .await?;
@@ -63,7 +63,7 @@ async fn run(mut client: tmi::Client, channels: Vec<String>) -> Result<()> {
   loop {
     let msg = client.recv().await?;
     match msg.as_typed()? {
-      tmi::Message::Privmsg(msg) => on_msg(&mut client, msg).await?,
+      tmi::Message::Privmsg(msg) => on_msg(&client, msg).await?,
       tmi::Message::Reconnect => {
         client.reconnect().await?;
         client.join_all(&channels).await?;
@@ -74,7 +74,7 @@ async fn run(mut client: tmi::Client, channels: Vec<String>) -> Result<()> {
   }
 }
 
-async fn on_msg(client: &mut tmi::Client, msg: tmi::Privmsg<'_>) -> Result<()> {
+async fn on_msg(client: &tmi::Client, msg: tmi::Privmsg<'_>) -> Result<()> {
   println!("{}: {}", msg.sender().name(), msg.text());
 
   if client.credentials().is_anon() {
@@ -0,0 +1,338 @@
+//! Command-line tool which tails Twitch chat.
+//!
+//! ```text,ignore
+//! $ cargo install tmi --features cli
+//! $ tmi forsen pajlada --format json --command PRIVMSG | jq .message.text
+//! $ tmi forsen --filter "badge = moderator or bits >= 100"
+//! $ echo "yo" | TMI_LOGIN=your_user_name TMI_TOKEN=oauth:... tmi forsen --send
+//! ```
+
+use chrono::{DateTime, Local, Utc};
+use clap::{Parser, ValueEnum};
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::error::Error;
+use std::io::{ErrorKind, Write};
+use tmi::client::read::RecvError;
+use tmi::client::{ClientReader, ClientWriter, Credentials};
+use tmi::filter::Filter;
+use tmi::{Action, IrcMessage, Message};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::select;
+use tokio::signal::ctrl_c;
+
+type Result<T, E = Box<dyn Error + Send + Sync>> = std::result::Result<T, E>;
+
+#[derive(Parser)]
+#[command(author, version, about = "Tail Twitch chat")]
+struct Args {
+  /// Channels to join
+  #[arg(required = true)]
+  channels: Vec<String>,
+
+  /// Login username, connects anonymously if not set
+  #[arg(long, short, env = "TMI_LOGIN", requires = "token")]
+  login: Option<String>,
+
+  /// Login oauth2 token
+  #[arg(
+    long,
+    short,
+    env = "TMI_TOKEN",
+    hide_env_values = true,
+    requires = "login"
+  )]
+  token: Option<String>,
+
+  /// Output format
+  #[arg(long, short, value_enum, default_value_t = Format::Human)]
+  format: Format,
+
+  /// Only print messages with this command, e.g. `PRIVMSG`
+  #[arg(long, short)]
+  command: Vec<String>,
+
+  /// Only print messages sent by this user
+  #[arg(long, short)]
+  user: Vec<String>,
+
+  /// Only print messages sent by users with this badge, e.g. `moderator` or `subscriber/12`
+  #[arg(long, short)]
+  badge: Vec<String>,
+
+  /// Only print messages with text matching this regex
+  #[arg(long = "match", short = 'm')]
+  pattern: Option<Regex>,
+
+  /// Only print messages matching this filter expression, see `tmi::filter`
+  #[arg(long, short = 'F')]
+  filter: Option<Filter>,
+
+  /// Send each line read from stdin as a message
+  ///
+  /// Lines may start with `#channel` to pick one of the joined channels,
+  /// which is required if more than one channel was joined.
+  #[arg(long, short)]
+  send: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+  /// Chat-like output
+  Human,
+  /// The raw IRC message
+  Raw,
+  /// One JSON object per line
+  Json,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+  let mut args = Args::parse();
+  // `--badge` is a shorthand for `--filter "badge = ..."`
+  if let Some(badges) = args.badge.drain(..).map(Filter::badge).reduce(Filter::or) {
+    args.filter = Some(match args.filter.take() {
+      Some(filter) => filter.and(badges),
+      None => badges,
+    });
+  }
+
+  let credentials = match (&args.login, &args.token) {
+    (Some(login), Some(token)) => Credentials::new(login, token),
+    _ => Credentials::anon(),
+  };
+  if args.send && credentials.is_anon() {
+    return Err("--send requires --login and --token".into());
+  }
+  let channels = args
+    .channels
+    .iter()
+    .map(|channel| format!("#{}", channel.trim_start_matches('#').to_lowercase()))
+    .collect::<Vec<_>>();
+
+  let client = tmi::Client::builder()
+    .credentials(credentials)
+    .connect()
+    .await?;
+  let (reader, writer) = client.split();
+  writer.join_all(&channels).await?;
+
+  if args.send {
+    tokio::spawn(send(writer.clone(), channels.clone()));
+  }
+
+  select! {
+    _ = ctrl_c() => Ok(()),
+    res = run(reader, writer, &channels, &args) => res,
+  }
+}
+
+async fn run(
+  mut reader: ClientReader,
+  writer: ClientWriter,
+  channels: &[String],
+  args: &Args,
+) -> Result<()> {
+  let mut out = std::io::stdout().lock();
+  loop {
+    let message = match reader.recv().await {
+      Ok(message) => message,
+      Err(e) if e.is_disconnect() => {
+        eprintln!("disconnected: {e}");
+        reader.reconnect().await?;
+        writer.join_all(channels).await?;
+        continue;
+      }
+      Err(RecvError::Parse(line)) => {
+        eprintln!("received invalid message: {line}");
+        continue;
+      }
+      Err(e) => return Err(e.into()),
+    };
+
+    // messages which fail to parse are still printed, based on the raw message
+    let typed = match message.as_typed() {
+      Ok(typed) => Some(typed),
+      Err(e) => {
+        eprintln!("{e}: {}", message.raw());
+        None
+      }
+    };
+    match &typed {
+      Some(Message::Ping(ping)) => writer.pong(ping).await?,
+      Some(Message::Reconnect) => {
+        reader.reconnect().await?;
+        writer.join_all(channels).await?;
+      }
+      _ => {}
+    }
+
+    if matches(args, &message) {
+      let line = format(args.format, &message, typed.as_ref())?;
+      match writeln!(out, "{line}") {
+        Ok(()) => {}
+        // the output was closed, e.g. by `| head`
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(()),
+        Err(e) => return Err(e.into()),
+      }
+    }
+  }
+}
+
+async fn send(writer: ClientWriter, channels: Vec<String>) {
+  let mut lines = BufReader::new(tokio::io::stdin()).lines();
+  while let Ok(Some(line)) = lines.next_line().await {
+    let prefixed = line.split_once(' ').and_then(|(prefix, text)| {
+      let prefix = prefix.strip_prefix('#')?.to_lowercase();
+      let channel = channels.iter().find(|channel| channel[1..] == prefix)?;
+      Some((channel, text))
+    });
+    let (channel, text) = match prefixed {
+      Some((channel, text)) => (channel, text),
+      None if channels.len() == 1 => (&channels[0], line.as_str()),
+      None => {
+        eprintln!("no joined channel for message, prefix it with `#channel`");
+        continue;
+      }
+    };
+    if let Err(e) = writer.privmsg(channel, text).send().await {
+      eprintln!("{e}");
+    }
+  }
+}
+
+fn matches(args: &Args, message: &IrcMessage) -> bool {
+  let command = message.command();
+  if !args.command.is_empty()
+    && !args
+      .command
+      .iter()
+      .any(|c| c.eq_ignore_ascii_case(command.as_str()))
+  {
+    return false;
+  }
+
+  if !args.user.is_empty() {
+    let login = message
+      .tag(tmi::Tag::Login)
+      .or_else(|| message.prefix().and_then(|prefix| prefix.nick));
+    if !login.is_some_and(|login| args.user.iter().any(|u| u.eq_ignore_ascii_case(login))) {
+      return false;
+    }
+  }
+
+  if let Some(filter) = &args.filter {
+    if !filter.matches(&message.as_ref()) {
+      return false;
+    }
+  }
+
+  if let Some(pattern) = &args.pattern {
+    if !message.text().is_some_and(|text| pattern.is_match(text)) {
+      return false;
+    }
+  }
+
+  true
+}
+
+fn format(format: Format, raw: &IrcMessage, typed: Option<&Message<'_>>) -> Result<String> {
+  Ok(match format {
+    Format::Human => typed
+      .and_then(human)
+      .unwrap_or_else(|| raw.raw().to_owned()),
+    Format::Raw => raw.raw().to_owned(),
+    Format::Json => json(raw, typed)?.to_string(),
+  })
+}
+
+fn human(message: &Message<'_>) -> Option<String> {
+  let (timestamp, line) = match message {
+    Message::Privmsg(msg) if msg.is_action() => (
+      Some(msg.timestamp()),
+      format!("{} * {} {}", msg.channel(), msg.sender().name(), msg.text()),
+    ),
+    Message::Privmsg(msg) => (
+      Some(msg.timestamp()),
+      format!("{} {}: {}", msg.channel(), msg.sender().name(), msg.text()),
+    ),
+    Message::UserNotice(msg) => {
+      let text = match (msg.system_message(), msg.text()) {
+        (Some(system), Some(text)) => format!("{system} {text}"),
+        (Some(system), None) => system.into_owned(),
+        (None, text) => text.unwrap_or_default().to_owned(),
+      };
+      (Some(msg.timestamp()), format!("{} {text}", msg.channel()))
+    }
+    Message::ClearChat(msg) => {
+      let text = match msg.action() {
+        Action::Clear => "chat has been cleared".to_owned(),
+        Action::Ban(ban) => format!("{} has been banned", ban.user()),
+        Action::TimeOut(timeout) => format!(
+          "{} has been timed out for {} seconds",
+          timeout.user(),
+          timeout.duration().as_secs()
+        ),
+      };
+      (Some(msg.timestamp()), format!("{} {text}", msg.channel()))
+    }
+    Message::ClearMsg(msg) => (
+      Some(msg.timestamp()),
+      format!(
+        "{} message from {} deleted: {}",
+        msg.channel(),
+        msg.sender(),
+        msg.text()
+      ),
+    ),
+    Message::Notice(msg) => (
+      None,
+      format!("{} {}", msg.channel().unwrap_or("*"), msg.text()),
+    ),
+    Message::Whisper(msg) => (
+      None,
+      format!("whisper {}: {}", msg.sender().name(), msg.text()),
+    ),
+    Message::Join(msg) => (None, format!("{} {} joined", msg.channel(), msg.user())),
+    Message::Part(msg) => (None, format!("{} {} left", msg.channel(), msg.user())),
+    _ => return None,
+  };
+  let timestamp: DateTime<Local> = timestamp.unwrap_or_else(Utc::now).into();
+  Some(format!("[{}] {line}", timestamp.format("%H:%M:%S")))
+}
+
+fn json(raw: &IrcMessage, typed: Option<&Message<'_>>) -> Result<Value> {
+  let tags = raw
+    .tags()
+    .map(|(key, value)| (key.to_owned(), Value::String(tmi::unescape(value))))
+    .collect::<Map<_, _>>();
+  let typed = match typed {
+    Some(Message::Capability(msg)) => serde_json::to_value(msg)?,
+    Some(Message::ClearChat(msg)) => serde_json::to_value(msg)?,
+    Some(Message::ClearMsg(msg)) => serde_json::to_value(msg)?,
+    Some(Message::EndOfNames(msg)) => serde_json::to_value(msg)?,
+    Some(Message::GlobalUserState(msg)) => serde_json::to_value(msg)?,
+    Some(Message::Join(msg)) => serde_json::to_value(msg)?,
+    Some(Message::Names(msg)) => serde_json::to_value(msg)?,
+    Some(Message::Notice(msg)) => serde_json::to_value(msg)?,
+    Some(Message::Part(msg)) => serde_json::to_value(msg)?,
+    Some(Message::Ping(msg)) => serde_json::to_value(msg)?,
+    Some(Message::Pong(msg)) => serde_json::to_value(msg)?,
+    Some(Message::Privmsg(msg)) => serde_json::to_value(msg)?,
+    Some(Message::RoomState(msg)) => serde_json::to_value(msg)?,
+    Some(Message::UserNotice(msg)) => serde_json::to_value(msg)?,
+    Some(Message::UserState(msg)) => serde_json::to_value(msg)?,
+    Some(Message::Welcome(msg)) => serde_json::to_value(msg)?,
+    Some(Message::Whisper(msg)) => serde_json::to_value(msg)?,
+    Some(Message::Reconnect | Message::Other(_)) | None => Value::Null,
+  };
+  Ok(json!({
+    "command": raw.command().as_str(),
+    "channel": raw.channel(),
+    "prefix": raw.prefix().map(|prefix| prefix.to_string()),
+    "tags": tags,
+    "text": raw.text(),
+    "raw": raw.raw(),
+    "message": typed,
+  }))
+}
@@ -0,0 +1,380 @@
+//! ## Chat buffer
+//!
+//! [`ChatBuffer`] is a bounded scrollback for a single channel, which keeps track of
+//! deleted and cleared messages, so that chat renderers can grey them out or hide them.
+//!
+//! ```rust,no_run
+//! # async fn run(mut client: tmi::Client) -> anyhow::Result<()> {
+//! use tmi::buffer::{ChatBuffer, Status};
+//!
+//! let mut buffer = ChatBuffer::new("#forsen", 500);
+//! loop {
+//!   let message = client.recv().await?;
+//!   if buffer.handle(&message.as_typed()?) {
+//!     for entry in buffer.iter().filter(|entry| entry.status() == Status::Visible) {
+//!       println!("{}", entry.message().text().unwrap_or_default());
+//!     }
+//!   }
+//! }
+//! # }
+//! ```
+
+use crate::msg::{Action, Message, Privmsg, UserNotice};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+
+/// A message which can be stored in a [`ChatBuffer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChatMessage {
+  Privmsg(Privmsg<'static>),
+  UserNotice(UserNotice<'static>),
+}
+
+impl ChatMessage {
+  /// Unique ID of the message.
+  pub fn id(&self) -> &str {
+    match self {
+      ChatMessage::Privmsg(msg) => msg.id(),
+      ChatMessage::UserNotice(msg) => msg.message_id(),
+    }
+  }
+
+  /// Channel in which the message was sent.
+  pub fn channel(&self) -> &str {
+    match self {
+      ChatMessage::Privmsg(msg) => msg.channel(),
+      ChatMessage::UserNotice(msg) => msg.channel(),
+    }
+  }
+
+  /// Login of the sender, which is not available for anonymous user notices.
+  pub fn sender(&self) -> Option<&str> {
+    match self {
+      ChatMessage::Privmsg(msg) => Some(msg.sender().login()),
+      ChatMessage::UserNotice(msg) => msg.sender().map(|sender| sender.login()),
+    }
+  }
+
+  /// Text content of the message.
+  pub fn text(&self) -> Option<&str> {
+    match self {
+      ChatMessage::Privmsg(msg) => Some(msg.text()),
+      ChatMessage::UserNotice(msg) => msg.text(),
+    }
+  }
+
+  /// The time at which the message was sent.
+  pub fn timestamp(&self) -> DateTime<Utc> {
+    match self {
+      ChatMessage::Privmsg(msg) => msg.timestamp(),
+      ChatMessage::UserNotice(msg) => msg.timestamp(),
+    }
+  }
+}
+
+impl From<Privmsg<'_>> for ChatMessage {
+  fn from(msg: Privmsg<'_>) -> Self {
+    ChatMessage::Privmsg(msg.into_owned())
+  }
+}
+
+impl From<UserNotice<'_>> for ChatMessage {
+  fn from(msg: UserNotice<'_>) -> Self {
+    ChatMessage::UserNotice(msg.into_owned())
+  }
+}
+
+/// Whether a message in a [`ChatBuffer`] was removed by a moderator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+  Visible,
+
+  /// The message was deleted on its own, see [`ClearMsg`][crate::ClearMsg].
+  Deleted,
+
+  /// The sender was banned or timed out, or the whole chat was cleared,
+  /// see [`ClearChat`][crate::ClearChat].
+  Cleared,
+}
+
+/// A message stored in a [`ChatBuffer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+  message: ChatMessage,
+  status: Status,
+}
+
+impl Entry {
+  #[inline]
+  pub fn message(&self) -> &ChatMessage {
+    &self.message
+  }
+
+  #[inline]
+  pub fn status(&self) -> Status {
+    self.status
+  }
+}
+
+/// Bounded scrollback for a single channel.
+///
+/// Once full, the oldest message is evicted for every new message.
+/// Messages can be looked up by their ID, and by the login of their sender.
+///
+/// See the [module documentation][self].
+#[derive(Clone, Debug)]
+pub struct ChatBuffer {
+  channel: String,
+  capacity: usize,
+  entries: VecDeque<Entry>,
+  /// Sequence number of the front entry.
+  offset: u64,
+  by_id: HashMap<String, u64>,
+  by_sender: HashMap<String, VecDeque<u64>>,
+}
+
+impl ChatBuffer {
+  /// Create a buffer for `channel` holding up to `capacity` messages.
+  pub fn new(channel: impl AsRef<str>, capacity: usize) -> Self {
+    let channel = channel
+      .as_ref()
+      .trim_start_matches('#')
+      .to_ascii_lowercase();
+    Self {
+      channel: format!("#{channel}"),
+      capacity,
+      entries: VecDeque::with_capacity(capacity),
+      offset: 0,
+      by_id: HashMap::new(),
+      by_sender: HashMap::new(),
+    }
+  }
+
+  /// Process a message.
+  ///
+  /// [`Privmsg`]s and [`UserNotice`]s are added to the buffer,
+  /// [`ClearMsg`][crate::ClearMsg] and [`ClearChat`][crate::ClearChat] update the
+  /// [`Status`] of the affected messages. Other messages, and messages sent to other
+  /// channels, are ignored.
+  ///
+  /// Returns `true` if the buffer changed.
+  pub fn handle(&mut self, message: &Message<'_>) -> bool {
+    match message {
+      Message::Privmsg(msg) if msg.channel() == self.channel => {
+        self.push(msg.clone());
+        true
+      }
+      Message::UserNotice(msg) if msg.channel() == self.channel => {
+        self.push(msg.clone());
+        true
+      }
+      Message::ClearMsg(msg) if msg.channel() == self.channel => {
+        self.delete(msg.target_message_id())
+      }
+      Message::ClearChat(msg) if msg.channel() == self.channel => match msg.action() {
+        Action::Clear => self.clear_all() > 0,
+        Action::Ban(ban) => self.clear_user(ban.user()) > 0,
+        Action::TimeOut(timeout) => self.clear_user(timeout.user()) > 0,
+      },
+      _ => false,
+    }
+  }
+
+  /// Add a message, evicting the oldest one if the buffer is full.
+  ///
+  /// This does not check the channel of the message.
+  pub fn push(&mut self, message: impl Into<ChatMessage>) {
+    if self.capacity == 0 {
+      return;
+    }
+    if self.entries.len() == self.capacity {
+      self.evict();
+    }
+
+    let message = message.into();
+    let seq = self.offset + self.entries.len() as u64;
+    self.by_id.insert(message.id().to_owned(), seq);
+    if let Some(sender) = message.sender() {
+      self
+        .by_sender
+        .entry(sender.to_owned())
+        .or_default()
+        .push_back(seq);
+    }
+    self.entries.push_back(Entry {
+      message,
+      status: Status::Visible,
+    });
+  }
+
+  /// Mark the message with the given `id` as [`Status::Deleted`].
+  ///
+  /// Returns `false` if the message is not in the buffer.
+  pub fn delete(&mut self, id: &str) -> bool {
+    let Some(&seq) = self.by_id.get(id) else {
+      return false;
+    };
+    let index = (seq - self.offset) as usize;
+    self.entries[index].status = Status::Deleted;
+    true
+  }
+
+  /// Mark all messages sent by the user with the given `login` as [`Status::Cleared`].
+  ///
+  /// Returns the number of affected messages.
+  pub fn clear_user(&mut self, login: &str) -> usize {
+    let Some(seqs) = self.by_sender.get(login) else {
+      return 0;
+    };
+    for &seq in seqs {
+      self.entries[(seq - self.offset) as usize].status = Status::Cleared;
+    }
+    seqs.len()
+  }
+
+  /// Mark all messages as [`Status::Cleared`].
+  ///
+  /// Returns the number of affected messages.
+  pub fn clear_all(&mut self) -> usize {
+    for entry in &mut self.entries {
+      entry.status = Status::Cleared;
+    }
+    self.entries.len()
+  }
+
+  /// Get the message with the given `id`.
+  pub fn get(&self, id: &str) -> Option<&Entry> {
+    let seq = self.by_id.get(id)?;
+    self.entries.get((seq - self.offset) as usize)
+  }
+
+  /// Iterator over the messages sent by the user with the given `login`, oldest first.
+  pub fn by_sender<'a>(&'a self, login: &str) -> impl DoubleEndedIterator<Item = &'a Entry> + 'a {
+    self
+      .by_sender
+      .get(login)
+      .into_iter()
+      .flatten()
+      .map(|seq| &self.entries[(seq - self.offset) as usize])
+  }
+
+  /// Iterator over all messages, oldest first.
+  pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Entry> + ExactSizeIterator {
+    self.entries.iter()
+  }
+
+  /// Name of the channel, prefixed with `#`.
+  #[inline]
+  pub fn channel(&self) -> &str {
+    &self.channel
+  }
+
+  #[inline]
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  fn evict(&mut self) {
+    let Some(entry) = self.entries.pop_front() else {
+      return;
+    };
+    let seq = self.offset;
+    self.offset += 1;
+    // A newer message with the same ID may have replaced this one in the index.
+    if self.by_id.get(entry.message.id()) == Some(&seq) {
+      self.by_id.remove(entry.message.id());
+    }
+    if let Some(sender) = entry.message.sender() {
+      if let Some(seqs) = self.by_sender.get_mut(sender) {
+        seqs.pop_front();
+        if seqs.is_empty() {
+          self.by_sender.remove(sender);
+        }
+      }
+    }
+  }
+}
+
+static_assert_send!(ChatBuffer);
+static_assert_sync!(ChatBuffer);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn privmsg(id: &str, login: &str) -> String {
+    format!("@badge-info=;badges=;color=;display-name={login};emotes=;flags=;id={id};mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=1;user-type= :{login}!{login}@{login}.tmi.twitch.tv PRIVMSG #pajlada :{id}")
+  }
+
+  fn handle(buffer: &mut ChatBuffer, line: &str) -> bool {
+    buffer.handle(&Message::parse(line).unwrap())
+  }
+
+  fn statuses(buffer: &ChatBuffer) -> Vec<Status> {
+    buffer.iter().map(Entry::status).collect()
+  }
+
+  #[test]
+  fn deletion_and_clear() {
+    let mut buffer = ChatBuffer::new("Pajlada", 10);
+    for (id, login) in [("1", "a"), ("2", "b"), ("3", "a")] {
+      assert!(handle(&mut buffer, &privmsg(id, login)));
+    }
+    assert!(handle(&mut buffer, "@badge-info=;badges=;color=;display-name=c;emotes=;flags=;id=4;login=c;mod=0;msg-id=sub;msg-param-cumulative-months=1;msg-param-months=0;msg-param-should-share-streak=0;msg-param-sub-plan-name=Sub;msg-param-sub-plan=1000;room-id=11148817;subscriber=1;system-msg=c\\ssubscribed.;tmi-sent-ts=1594545155039;user-id=3;user-type= :tmi.twitch.tv USERNOTICE #pajlada"));
+    assert!(!handle(
+      &mut buffer,
+      &privmsg("5", "a").replace("#pajlada", "#forsen")
+    ));
+    assert_eq!(buffer.len(), 4);
+    assert_eq!(buffer.get("4").unwrap().message().sender(), Some("c"));
+
+    assert!(handle(&mut buffer, "@login=b;room-id=;target-msg-id=2;tmi-sent-ts=1594561955611 :tmi.twitch.tv CLEARMSG #pajlada :2"));
+    assert!(!handle(&mut buffer, "@login=b;room-id=;target-msg-id=x;tmi-sent-ts=1594561955611 :tmi.twitch.tv CLEARMSG #pajlada :x"));
+    assert_eq!(buffer.get("2").unwrap().status(), Status::Deleted);
+
+    assert!(handle(&mut buffer, "@ban-duration=600;room-id=11148817;target-user-id=1;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada :a"));
+    assert_eq!(
+      statuses(&buffer),
+      [
+        Status::Cleared,
+        Status::Deleted,
+        Status::Cleared,
+        Status::Visible
+      ]
+    );
+
+    assert!(handle(
+      &mut buffer,
+      "@room-id=11148817;tmi-sent-ts=1594561392337 :tmi.twitch.tv CLEARCHAT #pajlada"
+    ));
+    assert!(statuses(&buffer).iter().all(|s| *s == Status::Cleared));
+  }
+
+  #[test]
+  fn eviction() {
+    let mut buffer = ChatBuffer::new("#pajlada", 2);
+    for (id, login) in [("1", "a"), ("2", "b"), ("3", "a"), ("4", "a")] {
+      handle(&mut buffer, &privmsg(id, login));
+    }
+    let ids = buffer
+      .iter()
+      .map(|entry| entry.message().id())
+      .collect::<Vec<_>>();
+    assert_eq!(ids, ["3", "4"]);
+    assert!(buffer.get("1").is_none());
+    assert_eq!(buffer.by_sender("a").count(), 2);
+    assert_eq!(buffer.by_sender("b").count(), 0);
+    assert_eq!(buffer.clear_user("a"), 2);
+    assert!(!buffer.delete("2"));
+  }
+}
@@ -31,8 +31,8 @@
 //! to see how you can generate one. [twitch_oauth2](https://crates.io/crates/twitch_oauth2) may be used to automate most of it.
 //!
 //! ⚠ Note: [`Client`] is a fairly low-level interface! It does not automatically handle:
-//! - Rate limiting (both for JOINs and PRIVMSGs)
-//! - Same message bypass
+//! - Rate limiting (both for JOINs and PRIVMSGs), unless enabled with [`ClientBuilder::queue`]
+//! - Same message bypass, unless enabled with [`ClientBuilder::same_message_bypass`]
 //! - `RECONNECT` commands
 //! - Rejoining channels
 //! - Latency measurement
@@ -44,18 +44,25 @@
 //! - A polling interface for receiving messages
 //! - Sending commands (PRIVMSG, JOIN, PONG, etc.)
 
-#[macro_use]
-mod macros;
-
+pub mod commands;
 pub mod conn;
+pub mod dispatch;
+pub mod pool;
 pub mod read;
+pub mod record;
 pub mod util;
+pub mod validate;
 pub mod write;
 
-use self::conn::{OpenStreamError, TlsConfig, TlsConfigError};
+use self::conn::{Endpoint, OpenStreamError, Proxy, Target, TlsConfig, TlsConfigError};
 use self::read::{ReadStream, RecvError};
-use self::write::WriteStream;
-use crate::irc::Command;
+use self::validate::Validation;
+use self::write::{QueueConfig, WriteStream};
+
+pub use self::read::ClientReader;
+pub use self::write::ClientWriter;
+use crate::common::JoinIter;
+use crate::msg::{FromIrc, Notice, Welcome};
 use crate::IrcMessage;
 use futures_util::StreamExt;
 use rand::{thread_rng, Rng};
@@ -65,7 +72,6 @@ use std::io;
 use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio_rustls::rustls::client::InvalidDnsNameError;
-use tokio_rustls::rustls::ServerName;
 use tokio_stream::wrappers::LinesStream;
 use util::Timeout;
 
@@ -169,25 +175,111 @@ impl Default for Backoff {
   }
 }
 
+/// A Twitch IRC capability, which may be requested during the handshake.
+///
+/// Not to be confused with the [`Capability`][crate::msg::Capability] message,
+/// which is the server's reply to a request.
+///
+/// See <https://dev.twitch.tv/docs/irc/capabilities/>.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CapabilityRequest {
+  /// `twitch.tv/commands`
+  ///
+  /// Enables Twitch-specific commands, such as `CLEARCHAT`, `NOTICE`, `USERNOTICE`, etc.
+  Commands,
+
+  /// `twitch.tv/tags`
+  ///
+  /// Adds tags to messages, such as the user's badges and display name.
+  Tags,
+
+  /// `twitch.tv/membership`
+  ///
+  /// Enables `JOIN`, `PART`, and `NAMES` messages for other users.
+  Membership,
+}
+
+impl CapabilityRequest {
+  /// All capabilities supported by Twitch IRC.
+  pub const ALL: &'static [CapabilityRequest] = &[
+    CapabilityRequest::Commands,
+    CapabilityRequest::Tags,
+    CapabilityRequest::Membership,
+  ];
+
+  /// Get the string value of the [`CapabilityRequest`].
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      CapabilityRequest::Commands => "twitch.tv/commands",
+      CapabilityRequest::Tags => "twitch.tv/tags",
+      CapabilityRequest::Membership => "twitch.tv/membership",
+    }
+  }
+
+  fn parse(s: &str) -> Option<Self> {
+    Self::ALL.iter().copied().find(|cap| cap.as_str() == s)
+  }
+}
+
+impl Display for CapabilityRequest {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
 /// Client configuration.
 #[derive(Clone, Debug)]
 pub struct Config {
   /// Credentials to use when logging in to Twitch IRC.
   pub credentials: Credentials,
 
+  /// Capabilities to request during the handshake.
+  pub capabilities: Vec<CapabilityRequest>,
+
+  /// Where to connect to.
+  pub endpoint: Endpoint,
+
+  /// TLS configuration, used when connecting over TLS.
+  ///
+  /// If `None`, the default root certificates are used, see [`TlsConfig`].
+  pub tls: Option<TlsConfig>,
+
+  /// Proxy to open connections through.
+  ///
+  /// Not used by [`Endpoint::Custom`].
+  pub proxy: Option<Proxy>,
+
   /// Connect and reconnect timeout.
   pub timeout: Duration,
 
   /// Reconnect backoff.
   pub backoff: Backoff,
+
+  /// Automatically bypass the same-message slow mode requirement.
+  ///
+  /// See [`ClientBuilder::same_message_bypass`].
+  pub same_message_bypass: bool,
+
+  /// How outgoing messages are validated.
+  pub validation: Validation,
+
+  /// How outgoing messages are queued.
+  pub queue: QueueConfig,
 }
 
 impl Default for Config {
   fn default() -> Self {
     Self {
       credentials: Default::default(),
+      capabilities: CapabilityRequest::ALL.to_vec(),
+      endpoint: Endpoint::default(),
+      tls: None,
+      proxy: None,
       timeout: DEFAULT_TIMEOUT,
       backoff: Default::default(),
+      same_message_bypass: false,
+      validation: Validation::default(),
+      queue: QueueConfig::default(),
     }
   }
 }
@@ -204,6 +296,70 @@ impl ClientBuilder {
     self
   }
 
+  /// Set the capabilities to request during the handshake.
+  ///
+  /// By default, all of [`CapabilityRequest::ALL`] are requested.
+  ///
+  /// ```rust
+  /// # async fn run() -> anyhow::Result<()> {
+  /// use tmi::client::CapabilityRequest;
+  ///
+  /// // skip `JOIN`/`PART` messages for other users
+  /// let client = tmi::Client::builder()
+  ///   .capabilities([CapabilityRequest::Commands, CapabilityRequest::Tags])
+  ///   .connect()
+  ///   .await?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn capabilities(mut self, capabilities: impl IntoIterator<Item = CapabilityRequest>) -> Self {
+    self.config.capabilities = capabilities.into_iter().collect();
+    self
+  }
+
+  /// Set the endpoint to connect to.
+  ///
+  /// By default, the client connects to Twitch IRC using TLS.
+  ///
+  /// See [`Endpoint`] for the available options.
+  pub fn endpoint(mut self, endpoint: Endpoint) -> Self {
+    self.config.endpoint = endpoint;
+    self
+  }
+
+  /// Set the TLS configuration.
+  ///
+  /// Accepts a [`TlsConfig`], a [`rustls::ClientConfig`][conn::rustls::ClientConfig],
+  /// or a [`rustls::RootCertStore`][conn::rustls::RootCertStore].
+  ///
+  /// ```rust,no_run
+  /// # async fn run() -> anyhow::Result<()> {
+  /// use tmi::client::conn::TlsConfig;
+  ///
+  /// let bundle = std::fs::read("/path/to/ca-bundle.pem")?;
+  /// let client = tmi::Client::builder()
+  ///   .tls_config(TlsConfig::from_pem(&bundle[..])?)
+  ///   .connect()
+  ///   .await?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn tls_config(mut self, tls: impl Into<TlsConfig>) -> Self {
+    self.config.tls = Some(tls.into());
+    self
+  }
+
+  /// Open connections through a proxy.
+  ///
+  /// The proxy is used for the initial connection and every reconnect.
+  /// It is not used by [`Endpoint::Custom`].
+  ///
+  /// See [`Proxy`] for the supported protocols.
+  pub fn proxy(mut self, proxy: Proxy) -> Self {
+    self.config.proxy = Some(proxy);
+    self
+  }
+
   /// Set the timeout used on various operations, such as connecting and reconnecting.
   pub fn timeout(mut self, timeout: Duration) -> Self {
     self.config.timeout = timeout;
@@ -216,6 +372,51 @@ impl ClientBuilder {
     self
   }
 
+  /// Automatically bypass the same-message slow mode requirement.
+  ///
+  /// Twitch rejects a `PRIVMSG` with a `msg_duplicate` notice if it is identical to
+  /// the previous message sent to the same channel within the last 30 seconds.
+  /// When enabled, the client tracks the last message sent to each channel,
+  /// and appends an invisible character to duplicates.
+  ///
+  /// Disabled by default.
+  pub fn same_message_bypass(mut self, enabled: bool) -> Self {
+    self.config.same_message_bypass = enabled;
+    self
+  }
+
+  /// Set how outgoing messages are validated.
+  ///
+  /// See [`Validation`] for the defaults.
+  pub fn validation(mut self, validation: Validation) -> Self {
+    self.config.validation = validation;
+    self
+  }
+
+  /// Set how outgoing messages are queued.
+  ///
+  /// By default, the queue is unbounded and not rate limited.
+  ///
+  /// ```rust,no_run
+  /// # async fn run() -> anyhow::Result<()> {
+  /// use tmi::client::write::{Overflow, QueueConfig, RateLimit};
+  ///
+  /// let client = tmi::Client::builder()
+  ///   .queue(QueueConfig {
+  ///     max_len: Some(100),
+  ///     overflow: Overflow::DropOldest,
+  ///     rate_limit: Some(RateLimit::USER),
+  ///   })
+  ///   .connect()
+  ///   .await?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn queue(mut self, queue: QueueConfig) -> Self {
+    self.config.queue = queue;
+    self
+  }
+
   /// Attempts to connect to Twitch IRC using this configuration.
   pub fn connect(self) -> impl Future<Output = Result<Client, ConnectError>> {
     Client::connect(self.config)
@@ -232,17 +433,15 @@ impl ClientBuilder {
 /// - Receiving and sending messages
 ///
 /// It is a low-level interface, which means it does not automatically handle:
-/// - Rate limiting
-/// - Same message bypass
+/// - Rate limiting, unless enabled with [`ClientBuilder::queue`]
+/// - Same message bypass, unless enabled with [`ClientBuilder::same_message_bypass`]
 /// - Reconnects / rejoining channels
 /// - Latency measurement
+///
+/// Use [`Client::split`] to receive and send messages from different tasks.
 pub struct Client {
-  reader: ReadStream,
-  writer: WriteStream,
-
-  scratch: String,
-  tls: TlsConfig,
-  config: Config,
+  reader: ClientReader,
+  writer: ClientWriter,
 }
 
 impl Client {
@@ -261,23 +460,97 @@ impl Client {
   /// Attempts to connect with the provided `config` and `timeout`.
   async fn connect(config: Config) -> Result<Client, ConnectError> {
     trace!("connecting");
-    let tls = TlsConfig::load(ServerName::try_from(conn::HOST)?)?;
-    trace!("opening connection to twitch");
+    let target = Target::load(&config)?;
+    trace!(endpoint = ?config.endpoint, "opening connection");
     let timeout = config.timeout;
-    let stream = conn::open(tls.clone()).timeout(timeout).await??;
-    let (reader, writer) = split(stream);
-    let mut client = Client {
-      reader,
+    let stream = target.open().timeout(timeout).await??;
+    let (mut reader, mut writer) = split(stream);
+    let capabilities = handshake(&config, &mut reader, &mut writer)
+      .timeout(timeout)
+      .await??;
+    let writer = ClientWriter::spawn(writer, &config);
+    Ok(Client {
+      reader: ClientReader {
+        stream: reader,
+        writer: writer.clone(),
+        target,
+        config,
+        capabilities,
+        connection_id: 0,
+      },
       writer,
-      scratch: String::with_capacity(1024),
-      tls,
-      config,
-    };
-    client.handshake().timeout(timeout).await??;
-    Ok(client)
+    })
+  }
+
+  /// Split the client into a [`ClientReader`] and a [`ClientWriter`].
+  ///
+  /// The writer may be cloned and moved to other tasks,
+  /// while the reader keeps receiving messages.
+  ///
+  /// ```rust,no_run
+  /// # async fn run() -> anyhow::Result<()> {
+  /// let client = tmi::Client::anonymous().await?;
+  /// let (mut reader, writer) = client.split();
+  /// writer.join("#forsen").await?;
+  /// loop {
+  ///   let msg = reader.recv().await?;
+  ///   if let tmi::Command::Ping = msg.command() {
+  ///     let writer = writer.clone();
+  ///     tokio::spawn(async move { writer.send_raw("PONG :tmi.twitch.tv\r\n").await });
+  ///   }
+  /// }
+  /// # }
+  /// ```
+  pub fn split(self) -> (ClientReader, ClientWriter) {
+    (self.reader, self.writer)
+  }
+
+  /// Attempt to reconnect to Twitch IRC.
+  ///
+  /// See [`ClientReader::reconnect`].
+  pub async fn reconnect(&mut self) -> Result<(), ReconnectError> {
+    self.reader.reconnect().await
+  }
+
+  /// Read a single [`IrcMessage`] from the underlying stream.
+  pub async fn recv(&mut self) -> Result<IrcMessage, RecvError> {
+    self.reader.recv().await
+  }
+
+  /// The writer half of this client.
+  #[inline]
+  pub fn writer(&self) -> &ClientWriter {
+    &self.writer
+  }
+
+  #[inline]
+  pub fn config(&self) -> &Config {
+    self.reader.config()
+  }
+
+  #[inline]
+  pub fn credentials(&self) -> &Credentials {
+    self.reader.credentials()
+  }
+
+  /// Capabilities which were acknowledged by Twitch during the last handshake.
+  #[inline]
+  pub fn capabilities(&self) -> &[CapabilityRequest] {
+    self.reader.capabilities()
+  }
+
+  /// See [`ClientReader::connection_id`].
+  #[inline]
+  pub fn connection_id(&self) -> u64 {
+    self.reader.connection_id()
   }
+}
 
+impl ClientReader {
   /// Attempt to reconnect to Twitch IRC.
+  ///
+  /// Once the handshake succeeds, the [`ClientWriter`] handles
+  /// created from the same client switch to the new connection.
   pub async fn reconnect(&mut self) -> Result<(), ReconnectError> {
     trace!("reconnecting");
 
@@ -293,114 +566,137 @@ impl Client {
       }
       delay = std::cmp::min(backoff.max_delay, delay * backoff.delay_multiplier);
 
-      trace!("opening connection to twitch");
-      let stream = match conn::open(self.tls.clone()).timeout(timeout).await? {
+      trace!(endpoint = ?self.config.endpoint, "opening connection");
+      let stream = match self.target.open().timeout(timeout).await? {
         Ok(stream) => stream,
-        Err(e @ OpenStreamError::Io(_)) => {
-          cause = e.into();
-          continue;
-        }
+        Err(e) => match ConnectError::from(e) {
+          e if e.should_retry() => {
+            cause = e;
+            continue;
+          }
+          e => return Err(e.into()),
+        },
       };
 
-      (self.reader, self.writer) = split(stream);
-
-      if let Err(e) = self.handshake().timeout(timeout).await? {
-        if e.should_retry() {
+      let (mut reader, mut writer) = split(stream);
+
+      match handshake(&self.config, &mut reader, &mut writer)
+        .timeout(timeout)
+        .await?
+      {
+        Ok(capabilities) => {
+          self.stream = reader;
+          self.writer.swap(writer);
+          self.capabilities = capabilities;
+          self.connection_id += 1;
+          return Ok(());
+        }
+        Err(e) if e.should_retry() => {
           cause = e;
           continue;
         }
-        return Err(e.into());
-      };
-
-      return Ok(());
+        Err(e) => return Err(e.into()),
+      }
     }
 
     Err(ReconnectError { cause })
   }
+}
 
-  async fn handshake(&mut self) -> Result<(), ConnectError> {
-    trace!("performing handshake");
-
-    let credentials = &self.config.credentials;
-    const CAP: &str = "twitch.tv/commands twitch.tv/tags twitch.tv/membership";
-    trace!("CAP REQ {CAP:?}; NICK {:?}; PASS ***", credentials.login);
-    write!(&mut self.scratch, "CAP REQ :{CAP}\r\n").unwrap();
-
-    let login = credentials.login.as_str();
-    let token = match credentials.token.as_ref() {
-      Some(token) => token.as_str(),
-      None => "just_a_lil_guy",
-    };
-    let oauth = if token.starts_with("oauth:") {
-      ""
-    } else {
-      "oauth:"
-    };
-    write!(&mut self.scratch, "PASS {oauth}{token}\r\n").unwrap();
-    write!(&mut self.scratch, "NICK {login}\r\n").unwrap();
-
-    self.writer.write_all(self.scratch.as_bytes()).await?;
-    self.writer.flush().await?;
-    self.scratch.clear();
-
-    trace!("waiting for CAP * ACK");
-    let message = self.recv().timeout(Duration::from_secs(5)).await??;
-    trace!(?message, "received message");
-
-    match message.command() {
-      Command::Capability => {
-        if message.params().is_some_and(|v| v.starts_with("* ACK")) {
-          trace!("received CAP * ACK")
-        } else {
-          return Err(ConnectError::Auth);
-        }
-      }
-      _ => {
-        trace!("unexpected message");
-        return Err(ConnectError::Welcome(message));
-      }
+/// Authenticates and negotiates capabilities on a freshly opened connection.
+///
+/// Returns the acknowledged capabilities.
+async fn handshake(
+  config: &Config,
+  reader: &mut ReadStream,
+  writer: &mut WriteStream,
+) -> Result<Vec<CapabilityRequest>, ConnectError> {
+  trace!("performing handshake");
+
+  let credentials = &config.credentials;
+  // each capability is only acknowledged once, no matter how often it is requested
+  let mut requested = Vec::with_capacity(config.capabilities.len());
+  for cap in &config.capabilities {
+    if !requested.contains(cap) {
+      requested.push(*cap);
     }
+  }
+  trace!(
+    ?requested,
+    "CAP REQ; NICK {:?}; PASS ***",
+    credentials.login
+  );
+  let mut scratch = String::with_capacity(256);
+  let num_requested = requested.len();
+  if num_requested > 0 {
+    let caps = requested.iter().join(' ');
+    write!(&mut scratch, "CAP REQ :{caps}\r\n").unwrap();
+  }
 
-    trace!("waiting for NOTICE 001");
-    let message = self.recv().timeout(Duration::from_secs(5)).await??;
+  let login = credentials.login.as_str();
+  let token = match credentials.token.as_ref() {
+    Some(token) => token.as_str(),
+    None => "just_a_lil_guy",
+  };
+  let oauth = if token.starts_with("oauth:") {
+    ""
+  } else {
+    "oauth:"
+  };
+  write!(&mut scratch, "PASS {oauth}{token}\r\n").unwrap();
+  write!(&mut scratch, "NICK {login}\r\n").unwrap();
+
+  writer.write_all(scratch.as_bytes()).await?;
+  writer.flush().await?;
+
+  let mut acknowledged = Vec::with_capacity(num_requested);
+  let mut rejected = Vec::new();
+  let mut num_received = 0;
+  while num_received < num_requested {
+    trace!("waiting for CAP * ACK");
+    let message = read::recv(reader).timeout(Duration::from_secs(5)).await??;
     trace!(?message, "received message");
 
-    match message.command() {
-      Command::RplWelcome => {
-        trace!("connected");
-      }
-      Command::Notice => {
-        if message
-          .params()
-          .map(|v| v.contains("authentication failed"))
-          .unwrap_or(false)
-        {
-          trace!("invalid credentials");
-          return Err(ConnectError::Auth);
-        }
-
-        trace!("unrecognized error");
-        return Err(ConnectError::Notice(message));
-      }
-      _ => {
-        trace!("first message not recognized");
-        return Err(ConnectError::Welcome(message));
-      }
+    let Ok(reply) = crate::msg::Capability::from_irc(message.as_ref()) else {
+      trace!("unexpected message");
+      return Err(ConnectError::Welcome(message));
+    };
+    num_received += reply.capabilities().len();
+    let caps = reply.capabilities().filter_map(CapabilityRequest::parse);
+    if reply.is_ack() {
+      trace!("received CAP * ACK");
+      acknowledged.extend(caps);
+    } else {
+      trace!("received CAP * NAK");
+      rejected.extend(caps);
     }
-
-    Ok(())
   }
-}
+  if !rejected.is_empty() {
+    return Err(ConnectError::Capabilities(rejected));
+  }
 
-impl Client {
-  #[inline]
-  pub fn config(&self) -> &Config {
-    &self.config
+  trace!("waiting for NOTICE 001");
+  let message = read::recv(reader).timeout(Duration::from_secs(5)).await??;
+  trace!(?message, "received message");
+
+  if let Ok(welcome) = Welcome::from_irc(message.as_ref()) {
+    trace!(user = welcome.user(), "connected");
+    return Ok(acknowledged);
   }
 
-  #[inline]
-  pub fn credentials(&self) -> &Credentials {
-    &self.config.credentials
+  match Notice::from_irc(message.as_ref()).map(|v| v.text().contains("authentication failed")) {
+    Ok(true) => {
+      trace!("invalid credentials");
+      Err(ConnectError::Auth)
+    }
+    Ok(false) => {
+      trace!("unrecognized error");
+      Err(ConnectError::Notice(message))
+    }
+    Err(_) => {
+      trace!("first message not recognized");
+      Err(ConnectError::Welcome(message))
+    }
   }
 }
 
@@ -450,6 +746,7 @@ impl<T: Into<ConnectError>> From<T> for ReconnectError {
 
 /// An error which occurred while attempting to connect to Twitch IRC.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ConnectError {
   /// Failed to read from the stream.
   Read(RecvError),
@@ -477,11 +774,22 @@ pub enum ConnectError {
 
   /// Twitch sent a notice that we didn't expect during the handshake.
   Notice(IrcMessage),
+
+  /// Twitch rejected some of the requested capabilities.
+  Capabilities(Vec<CapabilityRequest>),
+
+  /// The endpoint URL is invalid.
+  #[cfg(feature = "websocket")]
+  Url(String),
 }
 
 impl ConnectError {
   fn should_retry(&self) -> bool {
-    matches!(self, Self::Open(OpenStreamError::Io(_)) | Self::Io(_))
+    match self {
+      Self::Open(OpenStreamError::Io(_)) | Self::Io(_) => true,
+      Self::Open(OpenStreamError::Proxy(e)) => e.is_transient(),
+      _ => false,
+    }
   }
 }
 
@@ -539,6 +847,13 @@ impl Display for ConnectError {
         f,
         "failed to connect: received unrecognized notice: {msg:?}"
       ),
+      ConnectError::Capabilities(rejected) => write!(
+        f,
+        "failed to connect: capabilities were rejected: {}",
+        rejected.iter().join(", ")
+      ),
+      #[cfg(feature = "websocket")]
+      ConnectError::Url(url) => write!(f, "failed to connect: invalid url `{url}`"),
     }
   }
 }
@@ -547,3 +862,289 @@ impl std::error::Error for ConnectError {}
 
 static_assert_send!(Client);
 static_assert_sync!(Client);
+static_assert_send!(ClientReader);
+static_assert_sync!(ClientReader);
+static_assert_send!(ClientWriter);
+static_assert_sync!(ClientWriter);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream,
+  };
+
+  /// Answers the handshake with `cap_reply`, then sends `lines`.
+  async fn serve(stream: DuplexStream, cap_reply: &'static str, lines: &'static [&'static str]) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader).lines();
+    while let Some(line) = reader.next_line().await.unwrap() {
+      if line.starts_with("NICK") {
+        break;
+      }
+    }
+    writer.write_all(cap_reply.as_bytes()).await.unwrap();
+    writer
+      .write_all(b":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!\r\n")
+      .await
+      .unwrap();
+    for line in lines {
+      writer.write_all(line.as_bytes()).await.unwrap();
+    }
+    // keep the connection open until the client hangs up
+    while let Ok(Some(_)) = reader.next_line().await {}
+  }
+
+  fn endpoint(cap_reply: &'static str, lines: &'static [&'static str]) -> Endpoint {
+    Endpoint::custom(move || {
+      let (client, server) = tokio::io::duplex(1024);
+      tokio::spawn(serve(server, cap_reply, lines));
+      async move { Ok(client) }
+    })
+  }
+
+  #[tokio::test]
+  async fn connect_custom_endpoint() {
+    let mut client = Client::builder()
+      .endpoint(endpoint(
+        ":tmi.twitch.tv CAP * ACK :twitch.tv/commands twitch.tv/tags\r\n",
+        &[":tmi.twitch.tv PING :tmi.twitch.tv\r\n"],
+      ))
+      .capabilities([CapabilityRequest::Commands, CapabilityRequest::Tags])
+      .connect()
+      .await
+      .unwrap();
+
+    assert_eq!(
+      client.capabilities(),
+      [CapabilityRequest::Commands, CapabilityRequest::Tags]
+    );
+    let message = client.recv().await.unwrap();
+    assert_eq!(message.command(), crate::Command::Ping);
+  }
+
+  #[test]
+  fn retry_transient_proxy_errors() {
+    use conn::ProxyError;
+
+    let proxy = |e| ConnectError::Open(OpenStreamError::Proxy(e));
+    assert!(proxy(ProxyError::Http(502)).should_retry());
+    assert!(proxy(ProxyError::Socks5(4)).should_retry());
+    assert!(!proxy(ProxyError::Http(407)).should_retry());
+    assert!(!proxy(ProxyError::Auth).should_retry());
+  }
+
+  #[tokio::test]
+  async fn connect_duplicate_capabilities() {
+    let client = Client::builder()
+      .endpoint(endpoint(
+        ":tmi.twitch.tv CAP * ACK :twitch.tv/commands twitch.tv/tags\r\n",
+        &[],
+      ))
+      .capabilities([
+        CapabilityRequest::Commands,
+        CapabilityRequest::Tags,
+        CapabilityRequest::Commands,
+      ])
+      .connect()
+      .await
+      .unwrap();
+
+    assert_eq!(
+      client.capabilities(),
+      [CapabilityRequest::Commands, CapabilityRequest::Tags]
+    );
+  }
+
+  #[tokio::test]
+  async fn connect_rejected_capabilities() {
+    let result = Client::builder()
+      .endpoint(endpoint(
+        ":tmi.twitch.tv CAP * NAK :twitch.tv/membership\r\n",
+        &[],
+      ))
+      .capabilities([CapabilityRequest::Membership])
+      .connect()
+      .await;
+
+    match result {
+      Err(ConnectError::Capabilities(rejected)) => {
+        assert_eq!(rejected, [CapabilityRequest::Membership]);
+      }
+      Err(e) => panic!("unexpected error: {e}"),
+      Ok(_) => panic!("expected connection to fail"),
+    }
+  }
+
+  /// Performs the handshake, then echoes every line back, tagged with `conn`.
+  async fn echo(stream: impl AsyncRead + AsyncWrite, conn: usize) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader).lines();
+    while let Some(line) = reader.next_line().await.unwrap() {
+      if line.starts_with("NICK") {
+        break;
+      }
+    }
+    writer
+      .write_all(b":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!\r\n")
+      .await
+      .unwrap();
+    while let Ok(Some(line)) = reader.next_line().await {
+      let line = format!("@conn={conn} {line}\r\n");
+      if writer.write_all(line.as_bytes()).await.is_err() {
+        break;
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn split_reconnect() {
+    let connections = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let endpoint = Endpoint::custom(move || {
+      let conn = connections.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+      let (client, server) = tokio::io::duplex(1024);
+      tokio::spawn(echo(server, conn));
+      async move { Ok(client) }
+    });
+    let client = Client::builder()
+      .endpoint(endpoint)
+      .capabilities([])
+      .backoff(Backoff {
+        initial_delay: Duration::from_millis(1),
+        ..Default::default()
+      })
+      .connect()
+      .await
+      .unwrap();
+    let (mut reader, writer) = client.split();
+
+    let task = tokio::spawn({
+      let writer = writer.clone();
+      async move { writer.privmsg("#a", "before").send().await }
+    });
+    task.await.unwrap().unwrap();
+    let message = reader.recv().await.unwrap();
+    assert_eq!(message.tag("conn"), Some("0"));
+    assert_eq!(message.text(), Some("before"));
+
+    reader.reconnect().await.unwrap();
+
+    writer.privmsg("#a", "after").send().await.unwrap();
+    let message = reader.recv().await.unwrap();
+    assert_eq!(message.tag("conn"), Some("1"));
+    assert_eq!(message.text(), Some("after"));
+  }
+
+  #[tokio::test]
+  async fn reconnect_through_flaky_proxy() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+      for conn in 0.. {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+          request.push(stream.read_u8().await.unwrap());
+        }
+        if conn == 1 {
+          let _ = stream
+            .write_all(b"HTTP/1.1 503 Service Unavailable\r\n\r\n")
+            .await;
+          continue;
+        }
+        stream
+          .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+          .await
+          .unwrap();
+        tokio::spawn(echo(stream, conn));
+      }
+    });
+
+    let client = Client::builder()
+      .endpoint(Endpoint::tcp("irc.example.com", 6667))
+      .proxy(Proxy::http("127.0.0.1", port))
+      .capabilities([])
+      .backoff(Backoff {
+        initial_delay: Duration::from_millis(1),
+        ..Default::default()
+      })
+      .connect()
+      .await
+      .unwrap();
+    let (mut reader, writer) = client.split();
+
+    reader.reconnect().await.unwrap();
+
+    writer.privmsg("#a", "after").send().await.unwrap();
+    let message = reader.recv().await.unwrap();
+    assert_eq!(message.tag("conn"), Some("2"));
+  }
+
+  #[tokio::test]
+  async fn stream_messages() {
+    use futures_util::StreamExt;
+
+    let mut client = Client::builder()
+      .endpoint(endpoint(
+        ":tmi.twitch.tv CAP * ACK :twitch.tv/commands\r\n",
+        &[
+          ":tmi.twitch.tv PING :tmi.twitch.tv\r\n",
+          "@emote-only=0 :tmi.twitch.tv ROOMSTATE #pajlada\r\n",
+          ":tmi.twitch.tv RECONNECT\r\n",
+        ],
+      ))
+      .capabilities([CapabilityRequest::Commands])
+      .connect()
+      .await
+      .unwrap();
+
+    let message = client.next().await.unwrap().unwrap();
+    assert_eq!(message.command(), crate::Command::Ping);
+
+    let mut typed = client.typed().skip(1);
+    let message = typed.next().await.unwrap().unwrap();
+    assert!(matches!(message.typed(), crate::Message::Reconnect));
+  }
+
+  #[tokio::test]
+  async fn privmsg_split_and_validate() {
+    let client = Client::builder()
+      .endpoint(Endpoint::custom(|| {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(echo(server, 0));
+        async move { Ok(client) }
+      }))
+      .capabilities([])
+      .connect()
+      .await
+      .unwrap();
+    let (mut reader, writer) = client.split();
+
+    let text = format!("{} {}", "a".repeat(300), "b".repeat(300));
+    writer.privmsg("#a", &text).send().await.unwrap();
+    assert_eq!(reader.recv().await.unwrap().text(), Some(&*"a".repeat(300)));
+    assert_eq!(reader.recv().await.unwrap().text(), Some(&*"b".repeat(300)));
+
+    let result = writer.privmsg("#a", "a\r\nJOIN #b").send().await;
+    assert!(matches!(result, Err(write::SendError::LineBreak)));
+
+    // line breaks are rejected outside of the text too
+    let result = writer.privmsg("#a\r\nJOIN #b", "a").send().await;
+    assert!(matches!(result, Err(write::SendError::InvalidChannel(_))));
+    let result = writer
+      .privmsg("#a", "a")
+      .reply_to("\r\nJOIN #b")
+      .send()
+      .await;
+    assert!(matches!(result, Err(write::SendError::LineBreak)));
+    let result = writer
+      .privmsg("#a", "a")
+      .client_nonce("\nJOIN #b")
+      .send()
+      .await;
+    assert!(matches!(result, Err(write::SendError::LineBreak)));
+
+    let result = writer.privmsg("#a", &" ".repeat(600)).send().await;
+    assert!(matches!(result, Err(write::SendError::Blank(600))));
+  }
+}
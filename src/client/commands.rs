@@ -0,0 +1,491 @@
+//! ## Chat commands
+//!
+//! A [`Router`] parses `!command args` out of [`Privmsg`]s and invokes the matching [`Command`].
+//!
+//! ```rust,no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use std::time::Duration;
+//! use tmi::client::commands::{Command, Permission, Router};
+//! use tmi::client::dispatch::Dispatcher;
+//!
+//! let router = Router::new()
+//!   .prefix("!")
+//!   .command(
+//!     Command::new("yo", |_, cmd| async move {
+//!       cmd.reply("yo").await?;
+//!       anyhow::Ok(())
+//!     })
+//!     .alias("hi")
+//!     .user_cooldown(Duration::from_secs(10)),
+//!   )
+//!   .command(
+//!     Command::new("echo", |_, cmd| async move {
+//!       cmd.reply(&cmd.args().join(" ")).await?;
+//!       anyhow::Ok(())
+//!     })
+//!     .permission(Permission::Moderator),
+//!   );
+//!
+//! let client = tmi::Client::anonymous().await?;
+//! Dispatcher::new(())
+//!   .join(["#forsen"])
+//!   .commands(router)
+//!   .run(client)
+//!   .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::dispatch::{Context, Dispatcher, HandlerError};
+use super::write::SendError;
+use super::ClientWriter;
+use crate::msg::{Badge, Privmsg};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Who may invoke a [`Command`], based on the sender's [`Badge`]s.
+///
+/// Each level includes all of the levels above it in this list,
+/// e.g. a moderator may invoke [`Permission::Vip`] commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Permission {
+  /// Anyone.
+  Everyone,
+  /// Subscribers, including founders.
+  Subscriber,
+  /// VIPs.
+  Vip,
+  /// Moderators.
+  Moderator,
+  /// The broadcaster.
+  Broadcaster,
+}
+
+impl Permission {
+  /// The highest permission level granted by the badges on `message`.
+  pub fn of(message: &Privmsg<'_>) -> Permission {
+    message
+      .badges()
+      .map(|badge| match badge {
+        Badge::Broadcaster => Permission::Broadcaster,
+        Badge::Moderator => Permission::Moderator,
+        Badge::Subscriber(_) => Permission::Subscriber,
+        Badge::Other(badge) if badge.name() == "vip" => Permission::Vip,
+        Badge::Other(badge) if badge.name() == "founder" => Permission::Subscriber,
+        _ => Permission::Everyone,
+      })
+      .max()
+      .unwrap_or(Permission::Everyone)
+  }
+}
+
+type CommandFn<S> =
+  dyn Fn(Context<S>, Invocation) -> BoxFuture<'static, Result<(), HandlerError>> + Send + Sync;
+
+/// A chat command.
+pub struct Command<S> {
+  name: String,
+  aliases: Vec<String>,
+  permission: Permission,
+  user_cooldown: Option<Duration>,
+  channel_cooldown: Option<Duration>,
+  handler: Arc<CommandFn<S>>,
+}
+
+impl<S> Command<S> {
+  /// Create a command called `name`, which invokes `handler`.
+  pub fn new<F, Fut, E>(name: impl Into<String>, handler: F) -> Self
+  where
+    F: Fn(Context<S>, Invocation) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), E>> + Send + 'static,
+    E: Into<HandlerError>,
+  {
+    Self {
+      name: name.into(),
+      aliases: Vec::new(),
+      permission: Permission::Everyone,
+      user_cooldown: None,
+      channel_cooldown: None,
+      handler: Arc::new(move |ctx, invocation| {
+        handler(ctx, invocation)
+          .map(|r| r.map_err(Into::into))
+          .boxed()
+      }),
+    }
+  }
+
+  /// Add another name for this command.
+  pub fn alias(mut self, alias: impl Into<String>) -> Self {
+    self.aliases.push(alias.into());
+    self
+  }
+
+  /// Set the minimum permission level required to invoke this command.
+  ///
+  /// Defaults to [`Permission::Everyone`].
+  pub fn permission(mut self, permission: Permission) -> Self {
+    self.permission = permission;
+    self
+  }
+
+  /// Set how long each user has to wait before invoking this command again.
+  pub fn user_cooldown(mut self, cooldown: Duration) -> Self {
+    self.user_cooldown = Some(cooldown);
+    self
+  }
+
+  /// Set how long anyone in the same channel has to wait before invoking this command again.
+  pub fn channel_cooldown(mut self, cooldown: Duration) -> Self {
+    self.channel_cooldown = Some(cooldown);
+    self
+  }
+}
+
+/// A parsed command invocation, passed to the [`Command`] handler.
+pub struct Invocation {
+  writer: ClientWriter,
+  message: Privmsg<'static>,
+  prefix: String,
+  name: String,
+  args: Vec<String>,
+}
+
+impl Invocation {
+  /// The message which invoked the command.
+  #[inline]
+  pub fn message(&self) -> &Privmsg<'static> {
+    &self.message
+  }
+
+  /// The prefix used to invoke the command, e.g. `!`.
+  #[inline]
+  pub fn prefix(&self) -> &str {
+    &self.prefix
+  }
+
+  /// The name used to invoke the command, which may be one of its aliases.
+  #[inline]
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// The arguments passed to the command.
+  ///
+  /// See [`split_args`].
+  #[inline]
+  pub fn args(&self) -> &[String] {
+    &self.args
+  }
+
+  /// The argument at `index`.
+  #[inline]
+  pub fn arg(&self, index: usize) -> Option<&str> {
+    self.args.get(index).map(String::as_str)
+  }
+
+  /// Reply to the message which invoked the command.
+  pub async fn reply(&self, text: &str) -> Result<(), SendError> {
+    self
+      .writer
+      .privmsg(self.message.channel(), text)
+      .reply_to(self.message.id())
+      .send()
+      .await
+  }
+}
+
+/// The result of passing a message to [`Router::handle`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+  /// The message does not start with any of the prefixes.
+  NotACommand,
+  /// No command with the given name exists.
+  Unknown,
+  /// The sender is not allowed to invoke the command.
+  Denied,
+  /// The command is on cooldown for the given duration.
+  Cooldown(Duration),
+  /// The command handler ran.
+  Invoked,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum CooldownKey {
+  User(usize, String),
+  Channel(usize, String),
+}
+
+/// Routes chat messages to [`Command`]s.
+pub struct Router<S> {
+  prefixes: Vec<String>,
+  commands: Vec<Command<S>>,
+  names: HashMap<String, usize>,
+  cooldowns: Mutex<HashMap<CooldownKey, Instant>>,
+}
+
+impl<S> Default for Router<S> {
+  fn default() -> Self {
+    Self {
+      prefixes: Vec::new(),
+      commands: Vec::new(),
+      names: HashMap::new(),
+      cooldowns: Mutex::new(HashMap::new()),
+    }
+  }
+}
+
+impl<S: Send + Sync + 'static> Router<S> {
+  /// Create a router without any prefixes or commands.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a prefix which commands may be invoked with, e.g. `!`.
+  pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+    self.prefixes.push(prefix.into());
+    // try longer prefixes first, so that `!!` takes precedence over `!`
+    self
+      .prefixes
+      .sort_by_key(|prefix| std::cmp::Reverse(prefix.len()));
+    self
+  }
+
+  /// Add a command.
+  ///
+  /// Command names and aliases are case-insensitive.
+  /// If a name is already taken, the command added last wins.
+  pub fn command(mut self, command: Command<S>) -> Self {
+    let index = self.commands.len();
+    for name in std::iter::once(&command.name).chain(&command.aliases) {
+      self.names.insert(name.to_lowercase(), index);
+    }
+    self.commands.push(command);
+    self
+  }
+
+  /// Parse `message`, and invoke the matching command.
+  ///
+  /// Returns an error if the command handler fails.
+  pub async fn handle(
+    &self,
+    ctx: &Context<S>,
+    message: Privmsg<'static>,
+  ) -> Result<Outcome, HandlerError> {
+    let text = message
+      .text()
+      .trim_end_matches(['\u{E0000}', '\u{2800}', ' ']);
+    let Some((prefix, rest)) = self
+      .prefixes
+      .iter()
+      .find_map(|prefix| Some((prefix, text.strip_prefix(prefix.as_str())?)))
+    else {
+      return Ok(Outcome::NotACommand);
+    };
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let Some(&index) = self.names.get(&name.to_lowercase()) else {
+      return Ok(Outcome::Unknown);
+    };
+    let command = &self.commands[index];
+
+    if Permission::of(&message) < command.permission {
+      return Ok(Outcome::Denied);
+    }
+
+    let user = CooldownKey::User(index, message.sender().id().to_owned());
+    let channel = CooldownKey::Channel(index, message.channel_id().to_owned());
+    {
+      let now = Instant::now();
+      let mut cooldowns = self.cooldowns.lock().unwrap();
+      let remaining = [&user, &channel]
+        .into_iter()
+        .filter_map(|key| cooldowns.get(key))
+        .filter_map(|until| until.checked_duration_since(now))
+        .max();
+      if let Some(remaining) = remaining {
+        return Ok(Outcome::Cooldown(remaining));
+      }
+      if let Some(cooldown) = command.user_cooldown {
+        cooldowns.insert(user, now + cooldown);
+      }
+      if let Some(cooldown) = command.channel_cooldown {
+        cooldowns.insert(channel, now + cooldown);
+      }
+      cooldowns.retain(|_, until| *until > now);
+    }
+
+    let invocation = Invocation {
+      writer: ctx.writer().clone(),
+      prefix: prefix.clone(),
+      name: name.to_owned(),
+      args: split_args(args),
+      message,
+    };
+    (command.handler)(ctx.clone(), invocation).await?;
+    Ok(Outcome::Invoked)
+  }
+}
+
+impl<S: Send + Sync + 'static> Dispatcher<S> {
+  /// Route [`Privmsg`]s through `router`.
+  pub fn commands(self, router: Router<S>) -> Self {
+    let router = Arc::new(router);
+    self.on_privmsg(move |ctx, msg| {
+      let router = router.clone();
+      async move { router.handle(&ctx, msg).await.map(|_| ()) }
+    })
+  }
+}
+
+/// Split `args` on whitespace.
+///
+/// Arguments may be wrapped in double quotes to include whitespace,
+/// and `\"` may be used to include a literal quote.
+///
+/// ```rust
+/// use tmi::client::commands::split_args;
+///
+/// assert_eq!(split_args(r#"a "b c" d\"e"#), ["a", "b c", "d\"e"]);
+/// ```
+pub fn split_args(args: &str) -> Vec<String> {
+  let mut out = Vec::new();
+  let mut current = String::new();
+  let mut in_arg = false;
+  let mut quoted = false;
+  let mut chars = args.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' if matches!(chars.clone().next(), Some('"')) => {
+        current.extend(chars.next());
+        in_arg = true;
+      }
+      '"' => {
+        quoted = !quoted;
+        in_arg = true;
+      }
+      c if c.is_whitespace() && !quoted => {
+        if in_arg {
+          out.push(std::mem::take(&mut current));
+          in_arg = false;
+        }
+      }
+      c => {
+        current.push(c);
+        in_arg = true;
+      }
+    }
+  }
+  if in_arg {
+    out.push(current);
+  }
+  out
+}
+
+static_assert_send!(Router<()>);
+static_assert_sync!(Router<()>);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::client::conn::Stream;
+  use crate::msg::FromIrc;
+  use crate::IrcMessageRef;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  fn privmsg(badges: &str, user_id: &str, text: &str) -> Privmsg<'static> {
+    let line = format!(
+      "@badge-info=;badges={badges};color=;display-name=a;emotes=;flags=;id=1;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id={user_id};user-type= :a!a@a.tmi.twitch.tv PRIVMSG #pajlada :{text}"
+    );
+    Privmsg::from_irc(IrcMessageRef::parse(&line).unwrap())
+      .unwrap()
+      .into_owned()
+  }
+
+  fn context() -> Context<AtomicUsize> {
+    let (stream, _) = tokio::io::duplex(1024);
+    let (_, writer) = tokio::io::split(Box::new(stream) as Stream);
+    Context::new(
+      Arc::new(AtomicUsize::new(0)),
+      ClientWriter::spawn(writer, &Default::default()),
+    )
+  }
+
+  fn router() -> Router<AtomicUsize> {
+    Router::new()
+      .prefix("!")
+      .prefix("$")
+      .command(
+        Command::new("count", |ctx: Context<AtomicUsize>, cmd| async move {
+          let n = cmd.arg(0).map_or(Ok(1), str::parse)?;
+          ctx.state().fetch_add(n, Ordering::SeqCst);
+          Ok::<_, HandlerError>(())
+        })
+        .alias("c")
+        .user_cooldown(Duration::from_secs(60)),
+      )
+      .command(
+        Command::new("ban", |_, _| async { Ok::<_, HandlerError>(()) })
+          .permission(Permission::Moderator),
+      )
+  }
+
+  #[test]
+  fn split() {
+    assert_eq!(split_args(""), Vec::<String>::new());
+    assert_eq!(split_args("  a   b "), ["a", "b"]);
+    assert_eq!(split_args(r#""a b" c"#), ["a b", "c"]);
+    assert_eq!(split_args(r#"a"b c"d"#), ["ab cd"]);
+    assert_eq!(split_args(r#""" a"#), ["", "a"]);
+    assert_eq!(split_args(r#"\"a\""#), ["\"a\""]);
+    assert_eq!(split_args(r#""unterminated quote"#), ["unterminated quote"]);
+  }
+
+  #[test]
+  fn permission() {
+    assert_eq!(Permission::of(&privmsg("", "1", "")), Permission::Everyone);
+    assert_eq!(
+      Permission::of(&privmsg("vip/1,subscriber/12", "1", "")),
+      Permission::Vip
+    );
+    assert_eq!(
+      Permission::of(&privmsg("founder/0", "1", "")),
+      Permission::Subscriber
+    );
+    assert_eq!(
+      Permission::of(&privmsg("broadcaster/1,subscriber/0", "1", "")),
+      Permission::Broadcaster
+    );
+  }
+
+  #[tokio::test]
+  async fn route() {
+    let ctx = context();
+    let router = router();
+
+    let handle = |badges, user_id, text| router.handle(&ctx, privmsg(badges, user_id, text));
+    assert_eq!(handle("", "1", "yo").await.unwrap(), Outcome::NotACommand);
+    assert_eq!(handle("", "1", "!yo").await.unwrap(), Outcome::Unknown);
+    assert_eq!(handle("", "1", "!ban a").await.unwrap(), Outcome::Denied);
+    assert_eq!(
+      handle("moderator/1", "1", "!ban a").await.unwrap(),
+      Outcome::Invoked
+    );
+
+    assert_eq!(handle("", "1", "$C 2").await.unwrap(), Outcome::Invoked);
+    assert_eq!(ctx.state().load(Ordering::SeqCst), 2);
+    assert!(matches!(
+      handle("", "1", "!count").await.unwrap(),
+      Outcome::Cooldown(_)
+    ));
+    assert_eq!(
+      handle("", "2", "!count \u{E0000}").await.unwrap(),
+      Outcome::Invoked
+    );
+    assert_eq!(ctx.state().load(Ordering::SeqCst), 3);
+
+    assert!(handle("", "3", "!count nan").await.is_err());
+  }
+}
@@ -1,33 +1,295 @@
+use super::{Config, ConnectError};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
 use std::fmt::Display;
+use std::future::Future;
 use std::io;
 use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
-use tokio_rustls::client::TlsStream;
 use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerName};
-use tokio_rustls::{rustls, TlsConnector};
+use tokio_rustls::TlsConnector;
+
+pub use tokio_rustls::rustls;
+
+mod proxy;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+pub use proxy::{Proxy, ProxyError, ProxyKind};
+
+#[cfg(feature = "websocket")]
+pub use websocket::WS_URL;
 
 pub const HOST: &str = "irc.chat.twitch.tv";
 pub const PORT: u16 = 6697;
+/// Port used by Twitch IRC for plain TCP connections, without TLS.
+pub const PLAIN_PORT: u16 = 6667;
 
-pub type Stream = TlsStream<TcpStream>;
+/// A bidirectional byte stream which carries IRC messages.
+///
+/// This is implemented for anything that implements [`AsyncRead`] + [`AsyncWrite`].
+pub trait Transport: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
 
-pub async fn open(config: TlsConfig) -> Result<Stream, OpenStreamError> {
-  trace!(?config, "opening tls stream to twitch");
-  Ok(
-    TlsConnector::from(config.client())
-      .connect(
-        config.server_name(),
-        TcpStream::connect((HOST, PORT)).await?,
-      )
-      .await?,
-  )
+impl<T> Transport for T where T: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+pub type Stream = Box<dyn Transport>;
+
+/// Where the [`Client`][super::Client] should connect to.
+///
+/// The [`Default`] impl connects to Twitch IRC using TLS.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Endpoint {
+  /// TLS over TCP.
+  Tls { host: String, port: u16 },
+
+  /// Plain TCP, without TLS.
+  ///
+  /// ⚠ The connection is not encrypted, which includes your oauth token!
+  Tcp { host: String, port: u16 },
+
+  /// IRC over WebSocket, `ws://` or `wss://`.
+  #[cfg(feature = "websocket")]
+  WebSocket { url: String },
+
+  /// A custom transport.
+  Custom(Connector),
 }
 
-/// Failed to open a TLS stream.
+impl Endpoint {
+  /// Connect to `host:port` using TLS.
+  pub fn tls(host: impl Into<String>, port: u16) -> Self {
+    Self::Tls {
+      host: host.into(),
+      port,
+    }
+  }
+
+  /// Connect to `host:port` using plain TCP, without TLS.
+  ///
+  /// ⚠ The connection is not encrypted, which includes your oauth token!
+  pub fn tcp(host: impl Into<String>, port: u16) -> Self {
+    Self::Tcp {
+      host: host.into(),
+      port,
+    }
+  }
+
+  /// Connect to Twitch IRC using plain TCP, without TLS.
+  ///
+  /// ⚠ The connection is not encrypted, which includes your oauth token!
+  pub fn plain() -> Self {
+    Self::tcp(HOST, PLAIN_PORT)
+  }
+
+  /// Connect to `url` using WebSocket.
+  ///
+  /// `wss://` URLs are secured using TLS, `ws://` URLs are not.
+  #[cfg(feature = "websocket")]
+  pub fn websocket(url: impl Into<String>) -> Self {
+    Self::WebSocket { url: url.into() }
+  }
+
+  /// Connect to Twitch IRC using WebSocket, at [`WS_URL`].
+  #[cfg(feature = "websocket")]
+  pub fn ws() -> Self {
+    Self::websocket(WS_URL)
+  }
+
+  /// Connect using a custom transport.
+  ///
+  /// `connect` is called every time the client (re)connects,
+  /// and should return a fresh [`Transport`].
+  ///
+  /// ```rust
+  /// # async fn run() -> anyhow::Result<()> {
+  /// use tmi::client::conn::Endpoint;
+  ///
+  /// let endpoint = Endpoint::custom(|| tokio::net::TcpStream::connect("127.0.0.1:6667"));
+  /// let client = tmi::Client::builder().endpoint(endpoint).connect().await?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn custom<F, Fut, T>(connect: F) -> Self
+  where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = io::Result<T>> + Send + 'static,
+    T: Transport,
+  {
+    Self::Custom(Connector(Arc::new(move || {
+      connect()
+        .map(|result| result.map(|transport| Box::new(transport) as Stream))
+        .boxed()
+    })))
+  }
+}
+
+impl Default for Endpoint {
+  fn default() -> Self {
+    Self::tls(HOST, PORT)
+  }
+}
+
+type ConnectFn = dyn Fn() -> BoxFuture<'static, io::Result<Stream>> + Send + Sync;
+
+/// Opens a custom [`Transport`].
+///
+/// See [`Endpoint::custom`].
+#[derive(Clone)]
+pub struct Connector(Arc<ConnectFn>);
+
+impl Connector {
+  pub(crate) fn connect(&self) -> BoxFuture<'static, io::Result<Stream>> {
+    (self.0)()
+  }
+}
+
+impl std::fmt::Debug for Connector {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_tuple("Connector").finish_non_exhaustive()
+  }
+}
+
+/// An [`Endpoint`] with its TLS config already loaded.
+#[derive(Clone)]
+pub(super) enum Target {
+  Tls {
+    host: String,
+    port: u16,
+    proxy: Option<Proxy>,
+    server_name: ServerName,
+    tls: TlsConfig,
+  },
+  Tcp {
+    host: String,
+    port: u16,
+    proxy: Option<Proxy>,
+  },
+  #[cfg(feature = "websocket")]
+  WebSocket {
+    url: String,
+    host: String,
+    port: u16,
+    proxy: Option<Proxy>,
+    tls: Option<(ServerName, TlsConfig)>,
+  },
+  Custom(Connector),
+}
+
+impl Target {
+  pub(super) fn load(config: &Config) -> Result<Self, ConnectError> {
+    let tls = config.tls.as_ref();
+    let proxy = config.proxy.clone();
+    Ok(match &config.endpoint {
+      Endpoint::Tls { host, port } => Target::Tls {
+        host: host.clone(),
+        port: *port,
+        proxy,
+        server_name: ServerName::try_from(host.as_str())?,
+        tls: match tls {
+          Some(tls) => tls.clone(),
+          None => TlsConfig::load_default()?,
+        },
+      },
+      Endpoint::Tcp { host, port } => Target::Tcp {
+        host: host.clone(),
+        port: *port,
+        proxy,
+      },
+      #[cfg(feature = "websocket")]
+      Endpoint::WebSocket { url } => {
+        use tokio_tungstenite::tungstenite::http::Uri;
+
+        let invalid = || ConnectError::Url(url.clone());
+        let uri = url.parse::<Uri>().map_err(|_| invalid())?;
+        let host = uri.host().ok_or_else(invalid)?.to_owned();
+        let (port, tls) = match uri.scheme_str() {
+          Some("wss") => {
+            let tls = match tls {
+              Some(tls) => tls.clone(),
+              None => TlsConfig::load_default()?,
+            };
+            (443, Some((ServerName::try_from(host.as_str())?, tls)))
+          }
+          Some("ws") => (80, None),
+          _ => return Err(invalid()),
+        };
+        Target::WebSocket {
+          url: url.clone(),
+          port: uri.port_u16().unwrap_or(port),
+          host,
+          proxy,
+          tls,
+        }
+      }
+      Endpoint::Custom(connector) => Target::Custom(connector.clone()),
+    })
+  }
+
+  pub(super) async fn open(&self) -> Result<Stream, OpenStreamError> {
+    match self {
+      Target::Tls {
+        host,
+        port,
+        proxy,
+        server_name,
+        tls,
+      } => {
+        trace!(host, port, "opening tls stream");
+        let stream = dial(host, *port, proxy.as_ref()).await?;
+        Ok(Box::new(tls.connect(server_name, stream).await?))
+      }
+      Target::Tcp { host, port, proxy } => {
+        trace!(host, port, "opening tcp stream");
+        Ok(Box::new(dial(host, *port, proxy.as_ref()).await?))
+      }
+      #[cfg(feature = "websocket")]
+      Target::WebSocket {
+        url,
+        host,
+        port,
+        proxy,
+        tls,
+      } => {
+        trace!(url, "opening websocket stream");
+        let stream = dial(host, *port, proxy.as_ref()).await?;
+        let stream: Stream = match tls {
+          Some((server_name, tls)) => Box::new(tls.connect(server_name, stream).await?),
+          None => Box::new(stream),
+        };
+        let (ws, _) = tokio_tungstenite::client_async(url.as_str(), stream).await?;
+        Ok(Box::new(websocket::WebSocketTransport::new(ws)))
+      }
+      Target::Custom(connector) => {
+        trace!("opening custom stream");
+        Ok(connector.connect().await?)
+      }
+    }
+  }
+}
+
+/// Open a TCP connection to `host:port`, through `proxy` if set.
+async fn dial(host: &str, port: u16, proxy: Option<&Proxy>) -> Result<TcpStream, OpenStreamError> {
+  match proxy {
+    Some(proxy) => proxy.connect(host, port).await,
+    None => Ok(TcpStream::connect((host, port)).await?),
+  }
+}
+
+/// Failed to open a stream.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum OpenStreamError {
   /// The underlying I/O operation failed.
   Io(io::Error),
+
+  /// The proxy refused to open a connection.
+  Proxy(ProxyError),
+
+  /// The WebSocket handshake failed.
+  #[cfg(feature = "websocket")]
+  WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
 }
 
 impl From<io::Error> for OpenStreamError {
@@ -36,56 +298,192 @@ impl From<io::Error> for OpenStreamError {
   }
 }
 
+#[cfg(feature = "websocket")]
+impl From<tokio_tungstenite::tungstenite::Error> for OpenStreamError {
+  fn from(value: tokio_tungstenite::tungstenite::Error) -> Self {
+    match value {
+      tokio_tungstenite::tungstenite::Error::Io(e) => Self::Io(e),
+      e => Self::WebSocket(Box::new(e)),
+    }
+  }
+}
+
 impl Display for OpenStreamError {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
-      OpenStreamError::Io(e) => write!(f, "failed to open tls stream: {e}"),
+      OpenStreamError::Io(e) => write!(f, "failed to open stream: {e}"),
+      OpenStreamError::Proxy(e) => write!(f, "failed to open stream: {e}"),
+      #[cfg(feature = "websocket")]
+      OpenStreamError::WebSocket(e) => write!(f, "failed to open stream: {e}"),
     }
   }
 }
 
 impl std::error::Error for OpenStreamError {}
 
+/// TLS configuration used by [`Endpoint::Tls`].
+///
+/// By default, the platform's native root certificates are trusted.
+/// With the `webpki-roots` feature enabled, the bundled Mozilla root certificates
+/// are used if no native root certificates can be loaded, which is useful in environments
+/// without a system certificate store. Use `TlsConfig::webpki` to always use them.
 #[derive(Debug, Clone)]
 pub struct TlsConfig {
   config: Arc<ClientConfig>,
-  server_name: ServerName,
+  /// Only used by the deprecated [`TlsConfig::server_name`].
+  server_name: Option<ServerName>,
 }
 
 impl TlsConfig {
-  pub fn load(server_name: ServerName) -> Result<Self, TlsConfigError> {
-    trace!("loading native certificates");
+  /// Trust the platform's native root certificates.
+  pub fn native() -> Result<Self, TlsConfigError> {
+    Ok(Self::from_root_store(native_root_store()?))
+  }
+
+  /// Trust the Mozilla root certificates bundled with [`webpki-roots`](https://docs.rs/webpki-roots).
+  #[cfg(feature = "webpki-roots")]
+  pub fn webpki() -> Self {
     let mut root_store = RootCertStore::empty();
-    let native_certs = rustls_native_certs::load_native_certs()?;
-    for cert in native_certs {
-      root_store.add(&rustls::Certificate(cert.0))?;
+    root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+      rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+        ta.subject,
+        ta.spki,
+        ta.name_constraints,
+      )
+    }));
+    Self::from_root_store(root_store)
+  }
+
+  /// Trust the certificates in a PEM-encoded bundle.
+  ///
+  /// ```rust,no_run
+  /// # fn run() -> anyhow::Result<()> {
+  /// use tmi::client::conn::TlsConfig;
+  ///
+  /// let bundle = std::fs::read("/path/to/ca-bundle.pem")?;
+  /// let tls = TlsConfig::from_pem(&bundle[..])?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn from_pem(mut reader: impl io::BufRead) -> Result<Self, TlsConfigError> {
+    let mut root_store = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut reader)? {
+      root_store.add(&rustls::Certificate(cert))?;
+    }
+    if root_store.is_empty() {
+      return Err(TlsConfigError::NoCertificates);
     }
+    Ok(Self::from_root_store(root_store))
+  }
+
+  /// Trust the certificates in `root_store`.
+  pub fn from_root_store(root_store: RootCertStore) -> Self {
     let config = rustls::ClientConfig::builder()
       .with_safe_defaults()
       .with_root_certificates(root_store)
       .with_no_client_auth();
+    Self::from(config)
+  }
+
+  /// The default config, see [`TlsConfig`].
+  pub(super) fn load_default() -> Result<Self, TlsConfigError> {
+    #[cfg(feature = "webpki-roots")]
+    {
+      match native_root_store() {
+        Ok(root_store) if !root_store.is_empty() => Ok(Self::from_root_store(root_store)),
+        _ => {
+          trace!("no native certificates, falling back to webpki roots");
+          Ok(Self::webpki())
+        }
+      }
+    }
+    #[cfg(not(feature = "webpki-roots"))]
+    {
+      Self::native()
+    }
+  }
+
+  async fn connect(
+    &self,
+    server_name: &ServerName,
+    stream: TcpStream,
+  ) -> io::Result<tokio_rustls::client::TlsStream<TcpStream>> {
+    TlsConnector::from(self.client_config())
+      .connect(server_name.clone(), stream)
+      .await
+  }
+
+  /// The underlying `rustls` config.
+  pub fn client_config(&self) -> Arc<ClientConfig> {
+    self.config.clone()
+  }
+
+  /// Trust the platform's native root certificates, and connect to `server_name`.
+  #[deprecated = "use `TlsConfig::native`, the server name is taken from the `Endpoint`"]
+  pub fn load(server_name: ServerName) -> Result<Self, TlsConfigError> {
     Ok(Self {
-      config: Arc::new(config),
-      server_name,
+      server_name: Some(server_name),
+      ..Self::native()?
     })
   }
 
+  /// The underlying `rustls` config.
+  #[deprecated = "use `TlsConfig::client_config`"]
   pub fn client(&self) -> Arc<ClientConfig> {
-    self.config.clone()
+    self.client_config()
   }
 
+  /// The server name passed to [`TlsConfig::load`], or Twitch IRC's host.
+  #[deprecated = "the server name is taken from the `Endpoint`"]
   pub fn server_name(&self) -> ServerName {
-    self.server_name.clone()
+    match &self.server_name {
+      Some(server_name) => server_name.clone(),
+      None => ServerName::try_from(HOST).unwrap(),
+    }
+  }
+}
+
+fn native_root_store() -> Result<RootCertStore, TlsConfigError> {
+  trace!("loading native certificates");
+  let mut root_store = RootCertStore::empty();
+  let native_certs = rustls_native_certs::load_native_certs()?;
+  for cert in native_certs {
+    root_store.add(&rustls::Certificate(cert.0))?;
+  }
+  Ok(root_store)
+}
+
+impl From<ClientConfig> for TlsConfig {
+  fn from(config: ClientConfig) -> Self {
+    Self::from(Arc::new(config))
+  }
+}
+
+impl From<Arc<ClientConfig>> for TlsConfig {
+  fn from(config: Arc<ClientConfig>) -> Self {
+    Self {
+      config,
+      server_name: None,
+    }
+  }
+}
+
+impl From<RootCertStore> for TlsConfig {
+  fn from(root_store: RootCertStore) -> Self {
+    Self::from_root_store(root_store)
   }
 }
 
 /// Failed to load the TLS config.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum TlsConfigError {
   /// The underlying I/O operation failed.
   Io(io::Error),
   /// Failed to load certificates.
   Tls(rustls::Error),
+  /// The certificate bundle did not contain any certificates.
+  NoCertificates,
 }
 
 impl From<io::Error> for TlsConfigError {
@@ -105,8 +503,57 @@ impl Display for TlsConfigError {
     match self {
       TlsConfigError::Io(e) => write!(f, "tls config error: {e}"),
       TlsConfigError::Tls(e) => write!(f, "tls config error: {e}"),
+      TlsConfigError::NoCertificates => write!(f, "tls config error: no certificates found"),
     }
   }
 }
 
 impl std::error::Error for TlsConfigError {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn tls_config_from_pem() {
+    assert!(matches!(
+      TlsConfig::from_pem(&b""[..]),
+      Err(TlsConfigError::NoCertificates)
+    ));
+    assert!(matches!(
+      TlsConfig::from_pem(&b"-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n"[..]),
+      Err(TlsConfigError::Tls(_))
+    ));
+  }
+
+  #[test]
+  fn target_uses_tls_config() {
+    let tls = TlsConfig::from_root_store(RootCertStore::empty());
+    let config = Config {
+      tls: Some(tls.clone()),
+      ..Default::default()
+    };
+    let Ok(Target::Tls { tls: loaded, .. }) = Target::load(&config) else {
+      panic!("expected tls target");
+    };
+    assert!(Arc::ptr_eq(&tls.client_config(), &loaded.client_config()));
+  }
+
+  #[test]
+  #[allow(deprecated)]
+  fn deprecated_tls_config() {
+    let tls = TlsConfig::from_root_store(RootCertStore::empty());
+    assert!(Arc::ptr_eq(&tls.client(), &tls.client_config()));
+    assert_eq!(tls.server_name(), ServerName::try_from(HOST).unwrap());
+    let server_name = ServerName::try_from("localhost").unwrap();
+    if let Ok(tls) = TlsConfig::load(server_name.clone()) {
+      assert_eq!(tls.server_name(), server_name);
+    }
+  }
+
+  #[cfg(feature = "webpki-roots")]
+  #[test]
+  fn target_default_webpki() {
+    assert!(Target::load(&Config::default()).is_ok());
+  }
+}
@@ -0,0 +1,491 @@
+use super::OpenStreamError;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv6Addr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// A proxy to open connections through.
+///
+/// ```rust,no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// use tmi::client::conn::Proxy;
+///
+/// let client = tmi::Client::builder()
+///   .proxy(Proxy::socks5("localhost", 1080).with_auth("user", "hunter2"))
+///   .connect()
+///   .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Proxy {
+  kind: ProxyKind,
+  host: String,
+  port: u16,
+  auth: Option<ProxyAuth>,
+}
+
+/// The protocol used to talk to a [`Proxy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProxyKind {
+  /// An HTTP proxy which supports the `CONNECT` method.
+  Http,
+  /// A SOCKS5 proxy.
+  Socks5,
+}
+
+#[derive(Clone)]
+struct ProxyAuth {
+  username: String,
+  password: String,
+}
+
+impl std::fmt::Debug for ProxyAuth {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ProxyAuth")
+      .field("username", &self.username)
+      .finish_non_exhaustive()
+  }
+}
+
+impl Proxy {
+  /// An HTTP proxy at `host:port`, used via `CONNECT`.
+  pub fn http(host: impl Into<String>, port: u16) -> Self {
+    Self::new(ProxyKind::Http, host.into(), port)
+  }
+
+  /// A SOCKS5 proxy at `host:port`.
+  pub fn socks5(host: impl Into<String>, port: u16) -> Self {
+    Self::new(ProxyKind::Socks5, host.into(), port)
+  }
+
+  fn new(kind: ProxyKind, host: String, port: u16) -> Self {
+    Self {
+      kind,
+      host,
+      port,
+      auth: None,
+    }
+  }
+
+  /// Authenticate with the proxy.
+  ///
+  /// HTTP proxies receive the credentials using `Basic` authentication,
+  /// SOCKS5 proxies using username/password authentication.
+  pub fn with_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+    self.auth = Some(ProxyAuth {
+      username: username.into(),
+      password: password.into(),
+    });
+    self
+  }
+
+  /// The protocol used to talk to the proxy.
+  pub fn kind(&self) -> ProxyKind {
+    self.kind
+  }
+
+  /// The proxy's host.
+  pub fn host(&self) -> &str {
+    &self.host
+  }
+
+  /// The proxy's port.
+  pub fn port(&self) -> u16 {
+    self.port
+  }
+
+  /// Open a TCP connection to `host:port` through this proxy.
+  pub(super) async fn connect(&self, host: &str, port: u16) -> Result<TcpStream, OpenStreamError> {
+    trace!(proxy.host = self.host, proxy.port = self.port, ?self.kind, "connecting to proxy");
+    let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+    match self.kind {
+      ProxyKind::Http => self.http_connect(&mut stream, host, port).await?,
+      ProxyKind::Socks5 => self.socks5_connect(&mut stream, host, port).await?,
+    }
+    Ok(stream)
+  }
+
+  async fn http_connect(
+    &self,
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+  ) -> Result<(), OpenStreamError> {
+    // IPv6 literals must be bracketed, so that the port can be told apart from the address
+    let authority = match host.parse::<Ipv6Addr>() {
+      Ok(_) => format!("[{host}]:{port}"),
+      Err(_) => format!("{host}:{port}"),
+    };
+    let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+    if let Some(auth) = &self.auth {
+      let credentials = base64(format!("{}:{}", auth.username, auth.password).as_bytes());
+      request.push_str(&format!("Proxy-Authorization: Basic {credentials}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte, so that nothing past the end of the response is consumed.
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+      if response.len() >= 8192 {
+        return Err(ProxyError::InvalidResponse.into());
+      }
+      response.push(stream.read_u8().await?);
+    }
+
+    let status_line = response
+      .split(|&b| b == b'\r')
+      .next()
+      .and_then(|line| std::str::from_utf8(line).ok())
+      .ok_or(ProxyError::InvalidResponse)?;
+    let status = status_line
+      .strip_prefix("HTTP/1.")
+      .and_then(|rest| rest.get(2..5))
+      .and_then(|code| code.parse::<u16>().ok())
+      .ok_or(ProxyError::InvalidResponse)?;
+    match status {
+      200..=299 => Ok(()),
+      _ => Err(ProxyError::Http(status).into()),
+    }
+  }
+
+  async fn socks5_connect(
+    &self,
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+  ) -> Result<(), OpenStreamError> {
+    const VERSION: u8 = 5;
+    const NO_AUTH: u8 = 0;
+    const USERNAME_PASSWORD: u8 = 2;
+    const NO_ACCEPTABLE_METHODS: u8 = 0xFF;
+
+    match &self.auth {
+      Some(_) => {
+        stream
+          .write_all(&[VERSION, 2, NO_AUTH, USERNAME_PASSWORD])
+          .await?
+      }
+      None => stream.write_all(&[VERSION, 1, NO_AUTH]).await?,
+    }
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+      return Err(ProxyError::InvalidResponse.into());
+    }
+    match (reply[1], &self.auth) {
+      (NO_AUTH, _) => {}
+      (USERNAME_PASSWORD, Some(auth)) => {
+        // username/password authentication has its own version number
+        let mut request = vec![1];
+        for field in [&auth.username, &auth.password] {
+          let len = u8::try_from(field.len()).map_err(|_| ProxyError::Auth)?;
+          request.push(len);
+          request.extend_from_slice(field.as_bytes());
+        }
+        stream.write_all(&request).await?;
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != 1 {
+          return Err(ProxyError::InvalidResponse.into());
+        }
+        if reply[1] != 0 {
+          return Err(ProxyError::Auth.into());
+        }
+      }
+      (NO_ACCEPTABLE_METHODS, _) | (USERNAME_PASSWORD, None) => {
+        return Err(ProxyError::Auth.into());
+      }
+      _ => return Err(ProxyError::InvalidResponse.into()),
+    }
+
+    let mut request = vec![VERSION, 1, 0];
+    match host.parse::<IpAddr>() {
+      Ok(IpAddr::V4(ip)) => {
+        request.push(1);
+        request.extend_from_slice(&ip.octets());
+      }
+      Ok(IpAddr::V6(ip)) => {
+        request.push(4);
+        request.extend_from_slice(&ip.octets());
+      }
+      Err(_) => {
+        let len = u8::try_from(host.len()).map_err(|_| ProxyError::InvalidHost)?;
+        request.push(3);
+        request.push(len);
+        request.extend_from_slice(host.as_bytes());
+      }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+      return Err(ProxyError::InvalidResponse.into());
+    }
+    if reply[1] != 0 {
+      return Err(ProxyError::Socks5(reply[1]).into());
+    }
+    // skip the bound address and port
+    let addr_len = match reply[3] {
+      1 => 4,
+      4 => 16,
+      3 => stream.read_u8().await? as usize,
+      _ => return Err(ProxyError::InvalidResponse.into()),
+    };
+    let mut addr = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut addr).await?;
+
+    Ok(())
+  }
+}
+
+fn base64(data: &[u8]) -> String {
+  const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+  let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+  for chunk in data.chunks(3) {
+    let b = [
+      chunk[0],
+      *chunk.get(1).unwrap_or(&0),
+      *chunk.get(2).unwrap_or(&0),
+    ];
+    let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+    for i in 0..4 {
+      if i <= chunk.len() {
+        out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+      } else {
+        out.push('=');
+      }
+    }
+  }
+  out
+}
+
+/// The proxy refused to open a connection.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ProxyError {
+  /// The HTTP proxy responded with a non-success status code.
+  Http(u16),
+  /// The SOCKS5 proxy responded with a non-success reply code.
+  Socks5(u8),
+  /// The proxy rejected the credentials, or required credentials which were not provided.
+  Auth,
+  /// The target host name is too long for SOCKS5.
+  InvalidHost,
+  /// The proxy sent a malformed response.
+  InvalidResponse,
+}
+
+impl ProxyError {
+  /// Whether the proxy failed to reach the target host,
+  /// which may succeed when tried again later.
+  pub fn is_transient(&self) -> bool {
+    match self {
+      // bad gateway, service unavailable, gateway timeout
+      ProxyError::Http(status) => matches!(status, 502..=504),
+      // general failure, network or host unreachable, connection refused, TTL expired
+      ProxyError::Socks5(code) => matches!(code, 1 | 3..=6),
+      _ => false,
+    }
+  }
+}
+
+impl Display for ProxyError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ProxyError::Http(status) => write!(f, "proxy error: http status {status}"),
+      ProxyError::Socks5(code) => write!(f, "proxy error: socks5 reply code {code}"),
+      ProxyError::Auth => write!(f, "proxy error: authentication failed"),
+      ProxyError::InvalidHost => write!(f, "proxy error: invalid host"),
+      ProxyError::InvalidResponse => write!(f, "proxy error: invalid response"),
+    }
+  }
+}
+
+impl std::error::Error for ProxyError {}
+
+impl From<ProxyError> for OpenStreamError {
+  fn from(value: ProxyError) -> Self {
+    Self::Proxy(value)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::net::TcpListener;
+
+  async fn listen() -> (TcpListener, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    (listener, port)
+  }
+
+  #[test]
+  fn base64_encode() {
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"f"), "Zg==");
+    assert_eq!(base64(b"fo"), "Zm8=");
+    assert_eq!(base64(b"foo"), "Zm9v");
+    assert_eq!(base64(b"user:hunter2"), "dXNlcjpodW50ZXIy");
+  }
+
+  #[tokio::test]
+  async fn http_connect() {
+    let (listener, port) = listen().await;
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let mut request = Vec::new();
+      while !request.ends_with(b"\r\n\r\n") {
+        request.push(stream.read_u8().await.unwrap());
+      }
+      stream
+        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\nhello")
+        .await
+        .unwrap();
+      String::from_utf8(request).unwrap()
+    });
+
+    let proxy = Proxy::http("127.0.0.1", port).with_auth("user", "hunter2");
+    let mut stream = proxy.connect("irc.chat.twitch.tv", 6697).await.unwrap();
+    let mut data = [0u8; 5];
+    stream.read_exact(&mut data).await.unwrap();
+    assert_eq!(&data, b"hello");
+
+    assert_eq!(
+      server.await.unwrap(),
+      "CONNECT irc.chat.twitch.tv:6697 HTTP/1.1\r\n\
+       Host: irc.chat.twitch.tv:6697\r\n\
+       Proxy-Authorization: Basic dXNlcjpodW50ZXIy\r\n\r\n"
+    );
+  }
+
+  #[tokio::test]
+  async fn http_connect_refused() {
+    let (listener, port) = listen().await;
+    tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      stream
+        .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+        .await
+        .unwrap();
+    });
+
+    let result = Proxy::http("127.0.0.1", port)
+      .connect("irc.chat.twitch.tv", 6697)
+      .await;
+    assert!(matches!(
+      result,
+      Err(OpenStreamError::Proxy(ProxyError::Http(407)))
+    ));
+  }
+
+  #[tokio::test]
+  async fn socks5_connect() {
+    let (listener, port) = listen().await;
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let mut greeting = [0u8; 4];
+      stream.read_exact(&mut greeting).await.unwrap();
+      assert_eq!(greeting, [5, 2, 0, 2]);
+      stream.write_all(&[5, 2]).await.unwrap();
+
+      let mut auth = [0u8; 1 + 1 + 4 + 1 + 7];
+      stream.read_exact(&mut auth).await.unwrap();
+      assert_eq!(&auth, b"\x01\x04user\x07hunter2");
+      stream.write_all(&[1, 0]).await.unwrap();
+
+      let host = b"irc.chat.twitch.tv";
+      let mut request = vec![0u8; 5 + host.len() + 2];
+      stream.read_exact(&mut request).await.unwrap();
+      assert_eq!(&request[..5], &[5, 1, 0, 3, host.len() as u8]);
+      assert_eq!(&request[5..5 + host.len()], host);
+      assert_eq!(&request[5 + host.len()..], &6697u16.to_be_bytes());
+      stream
+        .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])
+        .await
+        .unwrap();
+      stream.write_all(b"hello").await.unwrap();
+    });
+
+    let proxy = Proxy::socks5("127.0.0.1", port).with_auth("user", "hunter2");
+    let mut stream = proxy.connect("irc.chat.twitch.tv", 6697).await.unwrap();
+    let mut data = [0u8; 5];
+    stream.read_exact(&mut data).await.unwrap();
+    assert_eq!(&data, b"hello");
+    server.await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn socks5_auth_required() {
+    let (listener, port) = listen().await;
+    tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let mut greeting = [0u8; 3];
+      stream.read_exact(&mut greeting).await.unwrap();
+      stream.write_all(&[5, 0xFF]).await.unwrap();
+    });
+
+    let result = Proxy::socks5("127.0.0.1", port)
+      .connect("irc.chat.twitch.tv", 6697)
+      .await;
+    assert!(matches!(
+      result,
+      Err(OpenStreamError::Proxy(ProxyError::Auth))
+    ));
+  }
+
+  #[tokio::test]
+  async fn http_connect_ipv6() {
+    let (listener, port) = listen().await;
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let mut request = Vec::new();
+      while !request.ends_with(b"\r\n\r\n") {
+        request.push(stream.read_u8().await.unwrap());
+      }
+      stream
+        .write_all(b"HTTP/1.1 503 Service Unavailable\r\n\r\n")
+        .await
+        .unwrap();
+      String::from_utf8(request).unwrap()
+    });
+
+    let result = Proxy::http("127.0.0.1", port).connect("::1", 6697).await;
+    match result {
+      Err(OpenStreamError::Proxy(e)) => assert!(e.is_transient()),
+      _ => panic!("expected a proxy error"),
+    }
+    assert_eq!(
+      server.await.unwrap(),
+      "CONNECT [::1]:6697 HTTP/1.1\r\nHost: [::1]:6697\r\n\r\n"
+    );
+  }
+
+  #[tokio::test]
+  async fn socks5_invalid_auth_version() {
+    let (listener, port) = listen().await;
+    tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let mut greeting = [0u8; 4];
+      stream.read_exact(&mut greeting).await.unwrap();
+      stream.write_all(&[5, 2]).await.unwrap();
+      let mut auth = [0u8; 1 + 1 + 4 + 1 + 7];
+      stream.read_exact(&mut auth).await.unwrap();
+      stream.write_all(&[5, 0]).await.unwrap();
+    });
+
+    let result = Proxy::socks5("127.0.0.1", port)
+      .with_auth("user", "hunter2")
+      .connect("irc.chat.twitch.tv", 6697)
+      .await;
+    assert!(matches!(
+      result,
+      Err(OpenStreamError::Proxy(ProxyError::InvalidResponse))
+    ));
+  }
+}
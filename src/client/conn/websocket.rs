@@ -0,0 +1,223 @@
+use super::Stream;
+use futures_util::{ready, Sink, Stream as _};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::WebSocketStream;
+
+/// Twitch IRC over WebSocket.
+pub const WS_URL: &str = "wss://irc-ws.chat.twitch.tv:443";
+
+/// Maximum number of bytes buffered by [`WebSocketTransport::poll_write`].
+const MAX_WRITE_BUFFER: usize = 64 * 1024;
+
+/// Adapts a [`WebSocketStream`] to a byte stream of IRC lines.
+///
+/// Every text frame is read as one or more lines, and every line
+/// written is sent as a separate text frame once the stream is flushed,
+/// or once [`MAX_WRITE_BUFFER`] bytes are buffered.
+pub(super) struct WebSocketTransport {
+  ws: WebSocketStream<Stream>,
+  read_buf: Vec<u8>,
+  read_pos: usize,
+  write_buf: Vec<u8>,
+}
+
+impl WebSocketTransport {
+  pub(super) fn new(ws: WebSocketStream<Stream>) -> Self {
+    Self {
+      ws,
+      read_buf: Vec::new(),
+      read_pos: 0,
+      write_buf: Vec::new(),
+    }
+  }
+}
+
+impl AsyncRead for WebSocketTransport {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    loop {
+      if self.read_pos < self.read_buf.len() {
+        let remaining = &self.read_buf[self.read_pos..];
+        let n = remaining.len().min(buf.remaining());
+        buf.put_slice(&remaining[..n]);
+        self.read_pos += n;
+        return Poll::Ready(Ok(()));
+      }
+
+      let mut data = match ready!(Pin::new(&mut self.ws).poll_next(cx)) {
+        Some(Ok(Message::Text(text))) => text.into_bytes(),
+        Some(Ok(Message::Binary(data))) => data,
+        Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+        Some(Ok(_)) => continue,
+        Some(Err(e)) if is_closed(&e) => return Poll::Ready(Ok(())),
+        Some(Err(e)) => return Poll::Ready(Err(into_io(e))),
+      };
+      // frames don't have to be terminated, but the line reader relies on it
+      if !data.ends_with(b"\n") {
+        data.extend_from_slice(b"\r\n");
+      }
+      self.read_buf = data;
+      self.read_pos = 0;
+    }
+  }
+}
+
+impl WebSocketTransport {
+  /// Start sending every complete line in the write buffer.
+  fn poll_send_lines(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    while let Some(end) = self.write_buf.windows(2).position(|w| w == b"\r\n") {
+      ready!(Pin::new(&mut self.ws).poll_ready(cx)).map_err(into_io)?;
+      let line = self
+        .write_buf
+        .drain(..end + 2)
+        .take(end)
+        .collect::<Vec<_>>();
+      let line =
+        String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+      Pin::new(&mut self.ws)
+        .start_send(Message::Text(line))
+        .map_err(into_io)?;
+    }
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl AsyncWrite for WebSocketTransport {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    if self.write_buf.len() >= MAX_WRITE_BUFFER {
+      ready!(self.poll_send_lines(cx))?;
+      if self.write_buf.len() >= MAX_WRITE_BUFFER {
+        return Poll::Ready(Err(io::Error::new(
+          io::ErrorKind::InvalidData,
+          "line is too long for a websocket frame",
+        )));
+      }
+    }
+    let n = buf.len().min(MAX_WRITE_BUFFER - self.write_buf.len());
+    self.write_buf.extend_from_slice(&buf[..n]);
+    Poll::Ready(Ok(n))
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    ready!(self.poll_send_lines(cx))?;
+    Pin::new(&mut self.ws).poll_flush(cx).map_err(into_io)
+  }
+
+  fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    ready!(self.as_mut().poll_flush(cx))?;
+    Pin::new(&mut self.ws).poll_close(cx).map_err(into_io)
+  }
+}
+
+fn is_closed(e: &tungstenite::Error) -> bool {
+  matches!(
+    e,
+    tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed
+  )
+}
+
+fn into_io(e: tungstenite::Error) -> io::Error {
+  match e {
+    tungstenite::Error::Io(e) => e,
+    e if is_closed(&e) => io::Error::new(io::ErrorKind::ConnectionAborted, e),
+    e => io::Error::other(e),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use futures_util::{SinkExt, StreamExt};
+  use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+  #[tokio::test]
+  async fn lines_over_frames() {
+    let (client, server) = tokio::io::duplex(1024);
+    let server = tokio::spawn(async move {
+      let mut ws = tokio_tungstenite::accept_async(server).await.unwrap();
+      ws.send(Message::Text(
+        ":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!\r\n:tmi.twitch.tv PING :tmi.twitch.tv\r\n"
+          .into(),
+      ))
+      .await
+      .unwrap();
+      ws.send(Message::Text(":tmi.twitch.tv RECONNECT".into()))
+        .await
+        .unwrap();
+      let mut received = vec![];
+      while let Some(Ok(Message::Text(text))) = ws.next().await {
+        received.push(text);
+      }
+      received
+    });
+
+    let (ws, _) = tokio_tungstenite::client_async("ws://localhost/", Box::new(client) as Stream)
+      .await
+      .unwrap();
+    let mut transport = WebSocketTransport::new(ws);
+    transport
+      .write_all(b"PASS oauth:test\r\nNICK test\r\n")
+      .await
+      .unwrap();
+    transport.flush().await.unwrap();
+
+    let (reader, mut writer) = tokio::io::split(transport);
+    let mut lines = BufReader::new(reader).lines();
+    assert_eq!(
+      lines.next_line().await.unwrap().unwrap(),
+      ":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!"
+    );
+    assert_eq!(
+      lines.next_line().await.unwrap().unwrap(),
+      ":tmi.twitch.tv PING :tmi.twitch.tv"
+    );
+    assert_eq!(
+      lines.next_line().await.unwrap().unwrap(),
+      ":tmi.twitch.tv RECONNECT"
+    );
+    writer.shutdown().await.unwrap();
+
+    assert_eq!(server.await.unwrap(), ["PASS oauth:test", "NICK test"]);
+  }
+
+  #[tokio::test]
+  async fn bounded_write_buffer() {
+    let (client, server) = tokio::io::duplex(1024);
+    let server = tokio::spawn(async move {
+      let mut ws = tokio_tungstenite::accept_async(server).await.unwrap();
+      let mut received = 0;
+      while let Some(Ok(Message::Text(_))) = ws.next().await {
+        received += 1;
+      }
+      received
+    });
+
+    let (ws, _) = tokio_tungstenite::client_async("ws://localhost/", Box::new(client) as Stream)
+      .await
+      .unwrap();
+    let mut transport = WebSocketTransport::new(ws);
+    let line = format!("PRIVMSG #a :{}\r\n", "a".repeat(100));
+    let lines = line.repeat(2000);
+    transport.write_all(lines.as_bytes()).await.unwrap();
+    assert!(transport.write_buf.len() <= MAX_WRITE_BUFFER);
+
+    let unterminated = vec![b'a'; MAX_WRITE_BUFFER + 1];
+    let e = transport.write_all(&unterminated).await.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    transport.write_buf.clear();
+    transport.shutdown().await.unwrap();
+
+    assert_eq!(server.await.unwrap(), 2000);
+  }
+}
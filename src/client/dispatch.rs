@@ -0,0 +1,511 @@
+//! ## Event dispatcher
+//!
+//! An event-driven layer on top of [`Client`].
+//!
+//! Register async handlers per message kind on a [`Dispatcher`], then hand it a [`Client`]
+//! using [`Dispatcher::run`]. The dispatcher responds to `PING`s, reconnects when asked to
+//! or when the connection drops, and re-joins its channels after every reconnect.
+//!
+//! Every handler runs in its own task, so a handler which fails or panics
+//! does not affect the other handlers or the dispatcher itself.
+//!
+//! ```rust,no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use tmi::client::dispatch::Dispatcher;
+//!
+//! #[derive(Default)]
+//! struct State {
+//!   messages: AtomicUsize,
+//! }
+//!
+//! let client = tmi::Client::anonymous().await?;
+//! Dispatcher::new(State::default())
+//!   .join(["#forsen"])
+//!   .on_privmsg(|ctx, msg| async move {
+//!     let n = ctx.state().messages.fetch_add(1, Ordering::Relaxed);
+//!     println!("#{n} {}: {}", msg.sender().name(), msg.text());
+//!     anyhow::Ok(())
+//!   })
+//!   .run(client)
+//!   .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::read::RecvError;
+use super::write::SendError;
+use super::{Client, ClientReader, ClientWriter, ReconnectError};
+use crate::msg::{
+  ClearChat, ClearMsg, GlobalUserState, Join, Message, Notice, Part, Privmsg, RoomState,
+  UserNotice, UserState, Whisper,
+};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::Arc;
+
+/// The error type returned by handlers.
+pub type HandlerError = Box<dyn std::error::Error + Send + Sync>;
+
+type Handler<S, T> =
+  Arc<dyn Fn(Context<S>, T) -> BoxFuture<'static, Result<(), HandlerError>> + Send + Sync>;
+
+fn handler<S, T, F, Fut, E>(f: F) -> Handler<S, T>
+where
+  F: Fn(Context<S>, T) -> Fut + Send + Sync + 'static,
+  Fut: Future<Output = Result<(), E>> + Send + 'static,
+  E: Into<HandlerError>,
+{
+  Arc::new(move |ctx, msg| f(ctx, msg).map(|r| r.map_err(Into::into)).boxed())
+}
+
+fn spawn<S, T>(handler: &Handler<S, T>, ctx: &Context<S>, msg: T, kind: &'static str)
+where
+  S: Send + Sync + 'static,
+{
+  let future = handler(ctx.clone(), msg);
+  tokio::spawn(async move {
+    if let Err(e) = future.await {
+      error!(kind, error = %e, "handler failed");
+    }
+  });
+}
+
+/// Passed to every handler invocation.
+pub struct Context<S> {
+  state: Arc<S>,
+  writer: ClientWriter,
+}
+
+impl<S> Context<S> {
+  pub(crate) fn new(state: Arc<S>, writer: ClientWriter) -> Self {
+    Self { state, writer }
+  }
+
+  /// The state shared by all handlers.
+  #[inline]
+  pub fn state(&self) -> &S {
+    &self.state
+  }
+
+  /// A writer for sending messages, e.g. replies.
+  #[inline]
+  pub fn writer(&self) -> &ClientWriter {
+    &self.writer
+  }
+}
+
+impl<S> Clone for Context<S> {
+  fn clone(&self) -> Self {
+    Self {
+      state: self.state.clone(),
+      writer: self.writer.clone(),
+    }
+  }
+}
+
+/// Dispatches incoming messages to registered handlers.
+///
+/// See the [module documentation][self].
+pub struct Dispatcher<S> {
+  state: Arc<S>,
+  channels: Vec<String>,
+  handlers: Handlers<S>,
+}
+
+macro_rules! handlers {
+  ($($(#[$doc:meta])* $method:ident => $variant:ident),* $(,)?) => {
+    struct Handlers<S> {
+      message: Vec<Handler<S, Message<'static>>>,
+      $($method: Vec<Handler<S, $variant<'static>>>,)*
+    }
+
+    impl<S> Default for Handlers<S> {
+      fn default() -> Self {
+        Self {
+          message: Vec::new(),
+          $($method: Vec::new(),)*
+        }
+      }
+    }
+
+    impl<S: Send + Sync + 'static> Handlers<S> {
+      fn dispatch(&self, ctx: &Context<S>, message: &Message<'static>) {
+        for handler in &self.message {
+          spawn(handler, ctx, message.clone(), "message");
+        }
+        match message {
+          $(
+            Message::$variant(msg) => {
+              for handler in &self.$method {
+                spawn(handler, ctx, msg.clone(), stringify!($variant));
+              }
+            }
+          )*
+          _ => {}
+        }
+      }
+    }
+
+    impl<S: Send + Sync + 'static> Dispatcher<S> {
+      $(
+        $(#[$doc])*
+        pub fn $method<F, Fut, E>(mut self, f: F) -> Self
+        where
+          F: Fn(Context<S>, $variant<'static>) -> Fut + Send + Sync + 'static,
+          Fut: Future<Output = Result<(), E>> + Send + 'static,
+          E: Into<HandlerError>,
+        {
+          self.handlers.$method.push(handler(f));
+          self
+        }
+      )*
+    }
+  };
+}
+
+handlers! {
+  /// Handle [`Privmsg`] messages.
+  on_privmsg => Privmsg,
+  /// Handle [`UserNotice`] messages.
+  on_user_notice => UserNotice,
+  /// Handle [`ClearChat`] messages.
+  on_clear_chat => ClearChat,
+  /// Handle [`ClearMsg`] messages.
+  on_clear_msg => ClearMsg,
+  /// Handle [`Notice`] messages.
+  on_notice => Notice,
+  /// Handle [`RoomState`] messages.
+  on_room_state => RoomState,
+  /// Handle [`UserState`] messages.
+  on_user_state => UserState,
+  /// Handle [`GlobalUserState`] messages.
+  on_global_user_state => GlobalUserState,
+  /// Handle [`Whisper`] messages.
+  on_whisper => Whisper,
+  /// Handle [`Join`] messages.
+  on_join => Join,
+  /// Handle [`Part`] messages.
+  on_part => Part,
+}
+
+impl<S: Send + Sync + 'static> Dispatcher<S> {
+  /// Create a dispatcher with `state` shared by all handlers.
+  pub fn new(state: S) -> Self {
+    Self::with_shared_state(Arc::new(state))
+  }
+
+  /// Create a dispatcher with `state` shared by all handlers.
+  pub fn with_shared_state(state: Arc<S>) -> Self {
+    Self {
+      state,
+      channels: Vec::new(),
+      handlers: Handlers::default(),
+    }
+  }
+
+  /// Join `channels` once running, and again after every reconnect.
+  pub fn join<I, C>(mut self, channels: I) -> Self
+  where
+    I: IntoIterator<Item = C>,
+    C: Into<String>,
+  {
+    self.channels.extend(channels.into_iter().map(Into::into));
+    self
+  }
+
+  /// Handle every message, regardless of its kind.
+  ///
+  /// These handlers run in addition to the kind-specific ones.
+  pub fn on_message<F, Fut, E>(mut self, f: F) -> Self
+  where
+    F: Fn(Context<S>, Message<'static>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), E>> + Send + 'static,
+    E: Into<HandlerError>,
+  {
+    self.handlers.message.push(handler(f));
+    self
+  }
+
+  /// Receive and dispatch messages until an unrecoverable error occurs.
+  pub async fn run(self, client: Client) -> Result<(), DispatchError> {
+    let (mut reader, writer) = client.split();
+    let ctx = Context::new(self.state.clone(), writer.clone());
+    self.join_channels(&writer).await?;
+
+    loop {
+      let message = match reader.recv().await {
+        Ok(message) => message,
+        Err(RecvError::Parse(line)) => {
+          warn!(line, "received invalid message");
+          continue;
+        }
+        // any I/O error, such as a reset connection, is treated like a disconnect
+        Err(e) => {
+          trace!(error = %e, "disconnected");
+          self.reconnect(&mut reader).await?;
+          continue;
+        }
+      };
+      let message = match message.as_typed() {
+        Ok(message) => message.into_owned(),
+        Err(e) => {
+          warn!(error = %e, raw = message.raw(), "failed to parse message");
+          continue;
+        }
+      };
+
+      match &message {
+        Message::Ping(ping) => {
+          if let Err(e) = writer.pong(ping).await {
+            warn!(error = %e, "failed to respond to ping");
+          }
+        }
+        Message::Reconnect => self.reconnect(&mut reader).await?,
+        _ => {}
+      }
+
+      self.handlers.dispatch(&ctx, &message);
+    }
+  }
+
+  async fn reconnect(&self, reader: &mut ClientReader) -> Result<(), DispatchError> {
+    reader.reconnect().await?;
+    self.join_channels(reader.writer()).await
+  }
+
+  async fn join_channels(&self, writer: &ClientWriter) -> Result<(), DispatchError> {
+    if !self.channels.is_empty() {
+      writer.join_all(&self.channels).await?;
+    }
+    Ok(())
+  }
+}
+
+/// The dispatcher stopped due to an unrecoverable error.
+#[derive(Debug)]
+pub enum DispatchError {
+  /// Failed to receive a message.
+  Recv(RecvError),
+
+  /// Failed to send a message, such as a `JOIN` after reconnecting.
+  Send(SendError),
+
+  /// Failed to reconnect.
+  Reconnect(ReconnectError),
+}
+
+impl From<RecvError> for DispatchError {
+  fn from(value: RecvError) -> Self {
+    Self::Recv(value)
+  }
+}
+
+impl From<SendError> for DispatchError {
+  fn from(value: SendError) -> Self {
+    Self::Send(value)
+  }
+}
+
+impl From<ReconnectError> for DispatchError {
+  fn from(value: ReconnectError) -> Self {
+    Self::Reconnect(value)
+  }
+}
+
+impl Display for DispatchError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      DispatchError::Recv(e) => write!(f, "dispatcher stopped: {e}"),
+      DispatchError::Send(e) => write!(f, "dispatcher stopped: {e}"),
+      DispatchError::Reconnect(e) => write!(f, "dispatcher stopped: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for DispatchError {}
+
+static_assert_send!(Dispatcher<()>);
+static_assert_sync!(Dispatcher<()>);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::client::conn::Endpoint;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::time::Duration;
+  use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+  use tokio::sync::mpsc;
+
+  struct State {
+    received: mpsc::UnboundedSender<String>,
+    failures: AtomicUsize,
+  }
+
+  const PRIVMSG: &str = "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv";
+
+  fn lines() -> Vec<String> {
+    vec![
+      ":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!\r\n".into(),
+      ":tmi.twitch.tv PING :tmi.twitch.tv\r\n".into(),
+      format!("{PRIVMSG} PRIVMSG #pajlada :first\r\n"),
+      format!("{PRIVMSG} PRIVMSG #pajlada :second\r\n"),
+      "@login=jun1orrrr;room-id=11148817;target-msg-id=e9d998c3-36f1-430f-89ec-6b887c28af36;tmi-sent-ts=1594545155039 :tmi.twitch.tv CLEARMSG #pajlada :first\r\n".into(),
+    ]
+  }
+
+  #[tokio::test]
+  async fn dispatch() {
+    let (pong_tx, mut pongs) = mpsc::unbounded_channel();
+    let endpoint = Endpoint::custom(move || {
+      let pong_tx = pong_tx.clone();
+      let (client, server) = tokio::io::duplex(1024);
+      tokio::spawn(async move {
+        let (reader, mut writer) = tokio::io::split(server);
+        let mut reader = BufReader::new(reader).lines();
+        while let Some(line) = reader.next_line().await.unwrap() {
+          if line.starts_with("NICK") {
+            break;
+          }
+        }
+        for line in lines() {
+          writer.write_all(line.as_bytes()).await.unwrap();
+        }
+        while let Ok(Some(line)) = reader.next_line().await {
+          let _ = pong_tx.send(line);
+        }
+      });
+      async move { Ok(client) }
+    });
+    let client = Client::builder()
+      .endpoint(endpoint)
+      .capabilities([])
+      .connect()
+      .await
+      .unwrap();
+
+    let (tx, mut received) = mpsc::unbounded_channel();
+    let state = Arc::new(State {
+      received: tx,
+      failures: AtomicUsize::new(0),
+    });
+    let dispatcher = Dispatcher::with_shared_state(state.clone())
+      .on_privmsg(|ctx, msg| async move {
+        if msg.text() == "first" {
+          ctx.state().failures.fetch_add(1, Ordering::SeqCst);
+          return Err("failed".into());
+        }
+        Ok::<_, HandlerError>(())
+      })
+      .on_privmsg(|ctx, msg| async move {
+        ctx.state().received.send(msg.text().to_owned())?;
+        Ok::<_, HandlerError>(())
+      })
+      .on_clear_msg(|ctx, msg| async move {
+        ctx.state().received.send(format!("clear {}", msg.text()))?;
+        Ok::<_, HandlerError>(())
+      });
+    let task = tokio::spawn(dispatcher.run(client));
+
+    let mut messages = Vec::new();
+    for _ in 0..3 {
+      let message = tokio::time::timeout(Duration::from_secs(1), received.recv());
+      messages.push(message.await.unwrap().unwrap());
+    }
+    messages.sort();
+    assert_eq!(messages, ["clear first", "first", "second"]);
+    assert_eq!(state.failures.load(Ordering::SeqCst), 1);
+    assert_eq!(pongs.recv().await.unwrap(), "PONG :tmi.twitch.tv");
+
+    task.abort();
+  }
+
+  /// Turns the end of the stream into a [`io::ErrorKind::ConnectionReset`] error.
+  #[cfg(feature = "testing")]
+  struct Reset(crate::client::conn::Stream);
+
+  #[cfg(feature = "testing")]
+  impl tokio::io::AsyncRead for Reset {
+    fn poll_read(
+      mut self: std::pin::Pin<&mut Self>,
+      cx: &mut std::task::Context<'_>,
+      buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+      let filled = buf.filled().len();
+      futures_util::ready!(std::pin::Pin::new(&mut self.0).poll_read(cx, buf))?;
+      if buf.filled().len() == filled && buf.remaining() > 0 {
+        return std::task::Poll::Ready(Err(std::io::ErrorKind::ConnectionReset.into()));
+      }
+      std::task::Poll::Ready(Ok(()))
+    }
+  }
+
+  #[cfg(feature = "testing")]
+  impl tokio::io::AsyncWrite for Reset {
+    fn poll_write(
+      mut self: std::pin::Pin<&mut Self>,
+      cx: &mut std::task::Context<'_>,
+      buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+      std::pin::Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+      mut self: std::pin::Pin<&mut Self>,
+      cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+      std::pin::Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+      mut self: std::pin::Pin<&mut Self>,
+      cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+      std::pin::Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+  }
+
+  #[cfg(feature = "testing")]
+  #[tokio::test]
+  async fn reconnect_on_io_error() {
+    use crate::testing::FakeServer;
+
+    let mut server = FakeServer::new();
+    let Endpoint::Custom(connector) = server.endpoint() else {
+      unreachable!("fake server endpoints are custom");
+    };
+    let client = server
+      .builder()
+      .endpoint(Endpoint::custom(move || {
+        let connect = connector.connect();
+        async move { Ok(Reset(connect.await?)) }
+      }))
+      .connect()
+      .await
+      .unwrap();
+
+    let (tx, mut received) = mpsc::unbounded_channel();
+    let dispatcher = Dispatcher::new(State {
+      received: tx,
+      failures: AtomicUsize::new(0),
+    })
+    .join(["#pajlada"])
+    .on_privmsg(|ctx, msg| async move {
+      ctx.state().received.send(msg.text().to_owned())?;
+      Ok::<_, HandlerError>(())
+    });
+    let task = tokio::spawn(dispatcher.run(client));
+    assert_eq!(server.expect("JOIN").await.connection, 0);
+
+    server.disconnect();
+    assert_eq!(server.expect("JOIN").await.connection, 1);
+    server.send(format!("{PRIVMSG} PRIVMSG #pajlada :after reset"));
+    let message = tokio::time::timeout(Duration::from_secs(1), received.recv());
+    assert_eq!(message.await.unwrap().unwrap(), "after reset");
+    assert!(!task.is_finished());
+
+    task.abort();
+  }
+}
@@ -0,0 +1,665 @@
+//! ## Connection pool
+//!
+//! A single connection to Twitch IRC can only keep up with a limited number of channels.
+//! A [`ClientPool`] opens as many [`Client`] connections as it needs, and assigns each
+//! joined channel to one of them.
+//!
+//! - Messages received on every connection are merged into a single stream.
+//! - Messages sent to a channel go through the connection which joined it.
+//! - Each connection responds to its own `PING`s.
+//! - When a connection fails or receives a `RECONNECT`, it is closed,
+//!   and its channels are distributed across the remaining connections,
+//!   opening new ones as needed.
+//!
+//! ```rust,no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use futures_util::StreamExt;
+//! use tmi::client::pool::{ClientPool, PoolConfig};
+//!
+//! let mut pool = ClientPool::new(PoolConfig {
+//!   channels_per_connection: 50,
+//!   ..Default::default()
+//! });
+//! pool.join_all(["#forsen", "#pajlada", "#xqc"]).await?;
+//!
+//! while let Some(msg) = pool.next().await {
+//!   println!("[{}] {}", msg.connection, msg.message.raw());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::read::RecvError;
+use super::write::SendError;
+use super::{Client, ClientReader, ClientWriter, Config, ConnectError};
+use crate::irc::{Command, IrcMessage};
+use crate::msg::Message;
+use futures_util::Stream;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+
+/// The default for [`PoolConfig::channels_per_connection`].
+pub const DEFAULT_CHANNELS_PER_CONNECTION: usize = 100;
+
+/// Identifies a connection in a [`ClientPool`].
+///
+/// Ids are never reused, so a connection which replaces a failed one has a new id.
+pub type ConnectionId = usize;
+
+/// Connection pool configuration.
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+  /// Configuration used to open each connection.
+  pub client: Config,
+
+  /// Maximum number of channels joined on a single connection.
+  pub channels_per_connection: usize,
+}
+
+impl Default for PoolConfig {
+  fn default() -> Self {
+    Self {
+      client: Config::default(),
+      channels_per_connection: DEFAULT_CHANNELS_PER_CONNECTION,
+    }
+  }
+}
+
+/// A message received by one of the connections in a [`ClientPool`].
+#[derive(Clone, Debug)]
+pub struct PoolMessage {
+  /// The connection which received the message.
+  pub connection: ConnectionId,
+
+  /// The message.
+  pub message: IrcMessage,
+}
+
+/// A pool of connections, which shards channels across them.
+///
+/// See the [module documentation][self].
+pub struct ClientPool {
+  shared: Arc<Shared>,
+  messages: mpsc::UnboundedReceiver<PoolMessage>,
+}
+
+struct Shared {
+  config: PoolConfig,
+  state: Mutex<State>,
+  messages: mpsc::UnboundedSender<PoolMessage>,
+}
+
+#[derive(Default)]
+struct State {
+  connections: BTreeMap<ConnectionId, Connection>,
+  owners: HashMap<String, ConnectionId>,
+  /// Channels of failed connections, which have not been assigned to another one yet.
+  rebalancing: HashSet<String>,
+  next_id: ConnectionId,
+}
+
+struct Connection {
+  writer: ClientWriter,
+  channels: HashSet<String>,
+  task: JoinHandle<()>,
+}
+
+impl ClientPool {
+  /// Create an empty pool.
+  ///
+  /// Connections are opened once channels are joined.
+  pub fn new(config: PoolConfig) -> Self {
+    assert!(
+      config.channels_per_connection > 0,
+      "channels_per_connection must be greater than zero"
+    );
+    let (tx, messages) = mpsc::unbounded_channel();
+    Self {
+      shared: Arc::new(Shared {
+        config,
+        state: Mutex::new(State::default()),
+        messages: tx,
+      }),
+      messages,
+    }
+  }
+
+  /// Receive the next message from any of the connections.
+  pub async fn recv(&mut self) -> PoolMessage {
+    // the pool holds a sender, so the channel is never closed
+    self.messages.recv().await.unwrap()
+  }
+
+  /// Join `channel` on the connection with the fewest channels.
+  ///
+  /// Opens a new connection if every connection is full.
+  /// Joining a channel which is already joined does nothing.
+  pub async fn join(&self, channel: impl AsRef<str>) -> Result<(), PoolError> {
+    self.join_all([channel]).await
+  }
+
+  /// Join each channel in `channels`, see [`ClientPool::join`].
+  pub async fn join_all<I, C>(&self, channels: I) -> Result<(), PoolError>
+  where
+    I: IntoIterator<Item = C>,
+    C: AsRef<str>,
+  {
+    let channels = channels
+      .into_iter()
+      .map(|c| normalize(c.as_ref()))
+      .collect::<Vec<_>>();
+    self.shared.assign(channels, false).await
+  }
+
+  /// Leave `channel`.
+  ///
+  /// A connection is closed once its last channel is parted.
+  pub async fn part(&self, channel: impl AsRef<str>) -> Result<(), PoolError> {
+    let channel = normalize(channel.as_ref());
+    let mut state = self.shared.state.lock().await;
+    if state.rebalancing.remove(&channel) {
+      return Ok(());
+    }
+    let Some(id) = state.owners.remove(&channel) else {
+      return Err(PoolError::NotJoined(channel));
+    };
+    let connection = state.connections.get_mut(&id).unwrap();
+    connection.channels.remove(&channel);
+    if connection.channels.is_empty() {
+      let connection = state.connections.remove(&id).unwrap();
+      connection.task.abort();
+      trace!(connection = id, "closed idle connection");
+      return Ok(());
+    }
+    let writer = connection.writer.clone();
+    drop(state);
+
+    writer
+      .send_raw(format!("PART {channel}\r\n").as_str())
+      .await?;
+    Ok(())
+  }
+
+  /// Send a `PRIVMSG` to `channel`, through the connection which joined it.
+  pub async fn privmsg(&self, channel: &str, text: &str) -> Result<(), PoolError> {
+    let writer = self
+      .writer(channel)
+      .await
+      .ok_or_else(|| PoolError::NotJoined(normalize(channel)))?;
+    writer.privmsg(channel, text).send().await?;
+    Ok(())
+  }
+
+  /// The writer of the connection which joined `channel`.
+  ///
+  /// The writer is only valid until the connection fails,
+  /// after which the channel may be assigned to a different connection.
+  pub async fn writer(&self, channel: &str) -> Option<ClientWriter> {
+    let state = self.shared.state.lock().await;
+    let id = state.owners.get(&normalize(channel))?;
+    Some(state.connections[id].writer.clone())
+  }
+
+  /// The connection which joined `channel`.
+  pub async fn connection_of(&self, channel: &str) -> Option<ConnectionId> {
+    let state = self.shared.state.lock().await;
+    state.owners.get(&normalize(channel)).copied()
+  }
+
+  /// The number of open connections.
+  pub async fn connections(&self) -> usize {
+    self.shared.state.lock().await.connections.len()
+  }
+
+  /// All joined channels, sorted by name.
+  pub async fn channels(&self) -> Vec<String> {
+    let state = self.shared.state.lock().await;
+    let mut channels = state.owners.keys().cloned().collect::<Vec<_>>();
+    channels.sort();
+    channels
+  }
+
+  #[inline]
+  pub fn config(&self) -> &PoolConfig {
+    &self.shared.config
+  }
+}
+
+impl Stream for ClientPool {
+  type Item = PoolMessage;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    self.messages.poll_recv(cx)
+  }
+}
+
+impl Shared {
+  /// Assign each of `channels` to a connection, and join it there.
+  ///
+  /// The lock is only held while updating the state, never while connecting or joining.
+  /// If `rebalancing` is set, channels which were parted in the meantime are skipped.
+  async fn assign(
+    self: &Arc<Self>,
+    channels: Vec<String>,
+    rebalancing: bool,
+  ) -> Result<(), PoolError> {
+    let mut joins = BTreeMap::<ConnectionId, (ClientWriter, Vec<String>)>::new();
+    let mut result = Ok(());
+    let skip = |state: &State, channel: &String| {
+      state.owners.contains_key(channel) || (rebalancing && !state.rebalancing.contains(channel))
+    };
+    for channel in channels {
+      let mut state = self.state.lock().await;
+      if skip(&state, &channel) {
+        continue;
+      }
+      let id = match self.least_loaded(&state) {
+        Some(id) => id,
+        None => {
+          drop(state);
+          let client = match Client::connect(self.config.client.clone()).await {
+            Ok(client) => client,
+            Err(e) => {
+              result = Err(e.into());
+              break;
+            }
+          };
+          state = self.state.lock().await;
+          // the state may have changed while connecting
+          if skip(&state, &channel) {
+            continue;
+          }
+          self.install(&mut state, client)
+        }
+      };
+
+      state.rebalancing.remove(&channel);
+      state.owners.insert(channel.clone(), id);
+      let connection = state.connections.get_mut(&id).unwrap();
+      connection.channels.insert(channel.clone());
+      joins
+        .entry(id)
+        .or_insert_with(|| (connection.writer.clone(), Vec::new()))
+        .1
+        .push(channel);
+    }
+
+    for (id, (writer, channels)) in joins {
+      trace!(connection = id, ?channels, "joining channels");
+      writer.join_all(&channels).await?;
+    }
+    result
+  }
+
+  /// The connection with the fewest channels, if any of them has room for another one.
+  fn least_loaded(&self, state: &State) -> Option<ConnectionId> {
+    state
+      .connections
+      .iter()
+      .filter(|(_, c)| c.channels.len() < self.config.channels_per_connection)
+      .min_by_key(|(_, c)| c.channels.len())
+      .map(|(id, _)| *id)
+  }
+
+  /// Add a newly opened connection to the pool.
+  fn install(self: &Arc<Self>, state: &mut State, client: Client) -> ConnectionId {
+    let (reader, writer) = client.split();
+    let id = state.next_id;
+    state.next_id += 1;
+    trace!(connection = id, "opened connection");
+
+    let task = tokio::spawn(run_connection(
+      id,
+      reader,
+      Arc::downgrade(self),
+      self.messages.clone(),
+    ));
+    state.connections.insert(
+      id,
+      Connection {
+        writer,
+        channels: HashSet::new(),
+        task,
+      },
+    );
+    id
+  }
+
+  /// Close connection `id`, and move its channels to other connections.
+  ///
+  /// The pool stays usable while this backs off between attempts.
+  async fn rebalance(self: &Arc<Self>, id: ConnectionId) {
+    let channels = {
+      let mut state = self.state.lock().await;
+      let Some(connection) = state.connections.remove(&id) else {
+        return;
+      };
+      let mut channels = connection.channels.into_iter().collect::<Vec<_>>();
+      channels.sort();
+      for channel in &channels {
+        state.owners.remove(channel);
+        state.rebalancing.insert(channel.clone());
+      }
+      channels
+    };
+    trace!(connection = id, ?channels, "rebalancing channels");
+
+    let backoff = self.config.client.backoff;
+    let mut tries = backoff.max_tries;
+    let mut delay = backoff.initial_delay;
+    loop {
+      match self.assign(channels.clone(), true).await {
+        Ok(()) => return,
+        Err(e) => {
+          warn!(connection = id, error = %e, "failed to rebalance channels");
+        }
+      }
+      if let Some(tries) = &mut tries {
+        *tries = tries.saturating_sub(1);
+        if *tries == 0 {
+          break;
+        }
+      }
+      tokio::time::sleep(delay).await;
+      delay = std::cmp::min(backoff.max_delay, delay * backoff.delay_multiplier);
+    }
+
+    let mut state = self.state.lock().await;
+    let lost = channels
+      .iter()
+      .filter(|c| state.rebalancing.remove(*c))
+      .collect::<Vec<_>>();
+    error!(connection = id, channels = ?lost, "gave up rebalancing channels");
+  }
+}
+
+impl Drop for Shared {
+  fn drop(&mut self) {
+    for connection in self.state.get_mut().connections.values() {
+      connection.task.abort();
+    }
+  }
+}
+
+/// Forward messages from a connection until it fails, then rebalance its channels.
+async fn run_connection(
+  id: ConnectionId,
+  mut reader: ClientReader,
+  shared: Weak<Shared>,
+  messages: mpsc::UnboundedSender<PoolMessage>,
+) {
+  loop {
+    let message = match reader.recv().await {
+      Ok(message) => message,
+      Err(RecvError::Parse(line)) => {
+        warn!(connection = id, line, "received invalid message");
+        continue;
+      }
+      Err(e) => {
+        warn!(connection = id, error = %e, "connection failed");
+        break;
+      }
+    };
+
+    let reconnect = match message.command() {
+      Command::Ping => {
+        if let Ok(Message::Ping(ping)) = message.as_typed() {
+          if let Err(e) = reader.writer().pong(&ping).await {
+            warn!(connection = id, error = %e, "failed to respond to ping");
+          }
+        }
+        false
+      }
+      Command::Reconnect => true,
+      _ => false,
+    };
+
+    let message = PoolMessage {
+      connection: id,
+      message,
+    };
+    if messages.send(message).is_err() {
+      // the pool was dropped
+      return;
+    }
+    if reconnect {
+      trace!(connection = id, "server requested reconnect");
+      break;
+    }
+  }
+
+  if let Some(shared) = shared.upgrade() {
+    shared.rebalance(id).await;
+  }
+}
+
+/// `#` followed by the lowercase channel name.
+fn normalize(channel: &str) -> String {
+  format!("#{}", channel.trim_start_matches('#').to_ascii_lowercase())
+}
+
+/// Failed to perform an operation on a [`ClientPool`].
+#[derive(Debug)]
+pub enum PoolError {
+  /// Failed to open a new connection.
+  Connect(ConnectError),
+
+  /// Failed to send a message.
+  Send(SendError),
+
+  /// The channel is not joined by any connection.
+  NotJoined(String),
+}
+
+impl From<ConnectError> for PoolError {
+  fn from(value: ConnectError) -> Self {
+    Self::Connect(value)
+  }
+}
+
+impl From<SendError> for PoolError {
+  fn from(value: SendError) -> Self {
+    Self::Send(value)
+  }
+}
+
+impl Display for PoolError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      PoolError::Connect(e) => write!(f, "failed to open pool connection: {e}"),
+      PoolError::Send(e) => write!(f, "{e}"),
+      PoolError::NotJoined(channel) => write!(f, "channel `{channel}` is not joined"),
+    }
+  }
+}
+
+impl std::error::Error for PoolError {}
+
+static_assert_send!(ClientPool);
+static_assert_sync!(ClientPool);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::client::conn::Endpoint;
+  use crate::client::Backoff;
+  use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+  use std::time::Duration;
+  use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+  use tokio::sync::watch;
+
+  /// Performs the handshake, then echoes every line back, tagged with `conn`.
+  ///
+  /// Hangs up once `kill` is set to `conn`.
+  async fn echo(stream: DuplexStream, conn: usize, mut kill: watch::Receiver<Option<usize>>) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader).lines();
+    while let Some(line) = reader.next_line().await.unwrap() {
+      if line.starts_with("NICK") {
+        break;
+      }
+    }
+    writer
+      .write_all(b":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!\r\n")
+      .await
+      .unwrap();
+    loop {
+      tokio::select! {
+        line = reader.next_line() => {
+          let Ok(Some(line)) = line else { break };
+          let line = format!("@conn={conn} {line}\r\n");
+          if writer.write_all(line.as_bytes()).await.is_err() {
+            break;
+          }
+        }
+        _ = async { kill.wait_for(|k| *k == Some(conn)).await.map(|_| ()) } => break,
+      }
+    }
+  }
+
+  struct Harness {
+    /// Hangs up the connection with this id.
+    kill: watch::Sender<Option<usize>>,
+    /// Makes new connections fail while set.
+    refuse: Arc<AtomicBool>,
+    /// Number of attempted connections.
+    attempts: Arc<AtomicUsize>,
+  }
+
+  fn pool(initial_delay: Duration) -> (ClientPool, Harness) {
+    let (kill, rx) = watch::channel(None);
+    let refuse = Arc::new(AtomicBool::new(false));
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let endpoint = Endpoint::custom({
+      let refuse = refuse.clone();
+      let attempts = attempts.clone();
+      move || {
+        let conn = attempts.fetch_add(1, Ordering::SeqCst);
+        let result = if refuse.load(Ordering::SeqCst) {
+          Err(std::io::Error::other("refused"))
+        } else {
+          let (client, server) = tokio::io::duplex(1024);
+          tokio::spawn(echo(server, conn, rx.clone()));
+          Ok(client)
+        };
+        async move { result }
+      }
+    });
+    let pool = ClientPool::new(PoolConfig {
+      client: Config {
+        endpoint,
+        capabilities: vec![],
+        backoff: Backoff {
+          initial_delay,
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+      channels_per_connection: 2,
+    });
+    let harness = Harness {
+      kill,
+      refuse,
+      attempts,
+    };
+    (pool, harness)
+  }
+
+  #[tokio::test]
+  async fn shard_and_route() {
+    let (mut pool, _harness) = pool(Duration::from_millis(1));
+    pool.join_all(["#a", "#B", "c"]).await.unwrap();
+    // already joined
+    pool.join("#b").await.unwrap();
+
+    assert_eq!(pool.connections().await, 2);
+    assert_eq!(pool.channels().await, ["#a", "#b", "#c"]);
+    assert_eq!(pool.connection_of("#a").await, Some(0));
+    assert_eq!(pool.connection_of("#b").await, Some(0));
+    assert_eq!(pool.connection_of("#c").await, Some(1));
+
+    // the echoed JOINs
+    let mut joins = [pool.recv().await, pool.recv().await];
+    joins.sort_by_key(|m| m.connection);
+    assert_eq!(joins[0].message.raw(), "@conn=0 JOIN #a,#b");
+    assert_eq!(joins[1].message.raw(), "@conn=1 JOIN #c");
+
+    pool.privmsg("#c", "yo").await.unwrap();
+    let msg = pool.recv().await;
+    assert_eq!(msg.connection, 1);
+    assert_eq!(msg.message.tag("conn"), Some("1"));
+    assert_eq!(msg.message.text(), Some("yo"));
+
+    assert!(matches!(
+      pool.privmsg("#d", "yo").await,
+      Err(PoolError::NotJoined(channel)) if channel == "#d"
+    ));
+
+    // a parted channel frees up room on its connection
+    pool.part("#b").await.unwrap();
+    assert_eq!(pool.recv().await.message.raw(), "@conn=0 PART #b");
+    pool.join("#d").await.unwrap();
+    assert_eq!(pool.connection_of("#d").await, Some(0));
+    assert_eq!(pool.connections().await, 2);
+
+    // parting the last channel of a connection closes it
+    pool.part("#c").await.unwrap();
+    assert_eq!(pool.connections().await, 1);
+    assert_eq!(pool.connection_of("#c").await, None);
+  }
+
+  #[tokio::test]
+  async fn rebalance_on_failure() {
+    let (mut pool, harness) = pool(Duration::from_millis(1));
+    pool.join_all(["#a", "#b", "#c"]).await.unwrap();
+    pool.recv().await;
+    pool.recv().await;
+
+    harness.kill.send(Some(0)).unwrap();
+
+    // `#c` fills up connection 1, so `#b` needs a new connection
+    let mut joins = [pool.recv().await, pool.recv().await];
+    joins.sort_by_key(|m| m.connection);
+    assert_eq!(joins[0].message.raw(), "@conn=1 JOIN #a");
+    assert_eq!(joins[1].message.raw(), "@conn=2 JOIN #b");
+
+    assert_eq!(pool.connections().await, 2);
+    assert_eq!(pool.connection_of("#a").await, Some(1));
+    assert_eq!(pool.connection_of("#b").await, Some(2));
+    assert_eq!(pool.connection_of("#c").await, Some(1));
+
+    pool.privmsg("#b", "yo").await.unwrap();
+    assert_eq!(pool.recv().await.message.tag("conn"), Some("2"));
+  }
+
+  #[tokio::test]
+  async fn usable_while_rebalancing() {
+    let (mut pool, harness) = pool(Duration::from_secs(3600));
+    pool.join_all(["#a", "#b", "#c"]).await.unwrap();
+    pool.recv().await;
+    pool.recv().await;
+
+    harness.refuse.store(true, Ordering::SeqCst);
+    harness.kill.send(Some(1)).unwrap();
+    // the first attempt to reconnect `#c` fails, after which the rebalance backs off
+    while harness.attempts.load(Ordering::SeqCst) < 3 {
+      tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+
+    let healthy = async {
+      pool.privmsg("#a", "yo").await.unwrap();
+      assert_eq!(pool.connections().await, 1);
+      assert_eq!(pool.connection_of("#c").await, None);
+      pool.recv().await
+    };
+    let msg = tokio::time::timeout(Duration::from_secs(5), healthy)
+      .await
+      .expect("pool is blocked by the rebalance");
+    assert_eq!(msg.message.raw(), "@conn=0 PRIVMSG #a :yo");
+  }
+}
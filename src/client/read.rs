@@ -1,23 +1,180 @@
-use super::{conn, Client};
+use super::conn::{self, Target};
+use super::{CapabilityRequest, Client, ClientWriter, Config, Credentials};
 use crate::irc::IrcMessage;
-use futures_util::stream::Fuse;
+use crate::msg::OwnedMessage;
+use futures_util::stream::{Fuse, Stream};
+use futures_util::{ready, StreamExt};
 use std::fmt::Display;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use tokio::io;
 use tokio::io::{BufReader, ReadHalf};
 use tokio_stream::wrappers::LinesStream;
-use tokio_stream::StreamExt;
 
 pub type ReadStream = Fuse<LinesStream<BufReader<ReadHalf<conn::Stream>>>>;
 
-impl Client {
+/// The receiving half of a [`Client`][super::Client].
+///
+/// Created by [`Client::split`][super::Client::split].
+pub struct ClientReader {
+  pub(super) stream: ReadStream,
+  pub(super) writer: ClientWriter,
+  pub(super) target: Target,
+  pub(super) config: Config,
+  pub(super) capabilities: Vec<CapabilityRequest>,
+  pub(super) connection_id: u64,
+}
+
+impl ClientReader {
   /// Read a single [`IrcMessage`] from the underlying stream.
   pub async fn recv(&mut self) -> Result<IrcMessage, RecvError> {
-    if let Some(message) = self.reader.next().await {
-      let message = message?;
-      Ok(IrcMessage::parse(&message).ok_or(RecvError::Parse(message))?)
-    } else {
-      Err(RecvError::StreamClosed)
-    }
+    recv(&mut self.stream).await
+  }
+
+  /// Adapts this reader into a stream of typed messages.
+  ///
+  /// See [`Typed`].
+  pub fn typed(&mut self) -> Typed<&mut Self> {
+    Typed::new(self)
+  }
+
+  /// The writer which sends messages over the same connection as this reader.
+  #[inline]
+  pub fn writer(&self) -> &ClientWriter {
+    &self.writer
+  }
+
+  #[inline]
+  pub fn config(&self) -> &Config {
+    &self.config
+  }
+
+  #[inline]
+  pub fn credentials(&self) -> &Credentials {
+    &self.config.credentials
+  }
+
+  /// Capabilities which were acknowledged by Twitch during the last handshake.
+  #[inline]
+  pub fn capabilities(&self) -> &[CapabilityRequest] {
+    &self.capabilities
+  }
+
+  /// Identifies the current connection.
+  ///
+  /// Starts at `0`, and is incremented by every successful [`reconnect`][ClientReader::reconnect].
+  #[inline]
+  pub fn connection_id(&self) -> u64 {
+    self.connection_id
+  }
+}
+
+pub(super) async fn recv(stream: &mut ReadStream) -> Result<IrcMessage, RecvError> {
+  if let Some(message) = stream.next().await {
+    parse(message)
+  } else {
+    Err(RecvError::StreamClosed)
+  }
+}
+
+fn parse(line: io::Result<String>) -> Result<IrcMessage, RecvError> {
+  let line = line?;
+  IrcMessage::parse(&line).ok_or(RecvError::Parse(line))
+}
+
+/// Yields messages until the stream is closed.
+///
+/// ```rust,no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// use futures_util::StreamExt;
+///
+/// let mut client = tmi::Client::anonymous().await?;
+/// while let Some(msg) = client.next().await {
+///   println!("{}", msg?.raw());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+impl Stream for ClientReader {
+  type Item = Result<IrcMessage, RecvError>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let line = ready!(self.stream.poll_next_unpin(cx));
+    Poll::Ready(line.map(parse))
+  }
+}
+
+/// See [`Stream for ClientReader`][ClientReader#impl-Stream-for-ClientReader].
+impl Stream for Client {
+  type Item = Result<IrcMessage, RecvError>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    self.reader.poll_next_unpin(cx)
+  }
+}
+
+impl Client {
+  /// Adapts this client into a stream of typed messages.
+  ///
+  /// See [`Typed`].
+  pub fn typed(&mut self) -> Typed<&mut Self> {
+    Typed::new(self)
+  }
+}
+
+/// A stream of typed [`OwnedMessage`]s.
+///
+/// Wraps a stream of [`IrcMessage`]s, such as a [`Client`] or [`ClientReader`].
+/// Messages which fail to parse are yielded as [`RecvError::Parse`].
+///
+/// ```rust,no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// use futures_util::StreamExt;
+///
+/// let mut client = tmi::Client::anonymous().await?;
+/// let mut messages = client.typed();
+/// while let Some(msg) = messages.next().await {
+///   let msg = msg?;
+///   if let tmi::Message::Privmsg(msg) = msg.typed() {
+///     println!("{}: {}", msg.sender().name(), msg.text());
+///   }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Typed<S> {
+  inner: S,
+}
+
+impl<S> Typed<S> {
+  pub fn new(inner: S) -> Self {
+    Self { inner }
+  }
+
+  pub fn get_ref(&self) -> &S {
+    &self.inner
+  }
+
+  pub fn get_mut(&mut self) -> &mut S {
+    &mut self.inner
+  }
+
+  pub fn into_inner(self) -> S {
+    self.inner
+  }
+}
+
+impl<S> Stream for Typed<S>
+where
+  S: Stream<Item = Result<IrcMessage, RecvError>> + Unpin,
+{
+  type Item = Result<OwnedMessage, RecvError>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let message = ready!(self.inner.poll_next_unpin(cx));
+    Poll::Ready(message.map(|message| {
+      OwnedMessage::try_parse(message?).map_err(|(_, raw)| RecvError::Parse(raw.raw().to_owned()))
+    }))
   }
 }
 
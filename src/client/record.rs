@@ -0,0 +1,413 @@
+//! ## Record and replay
+//!
+//! A [`Recorder`] wraps a [`Client`], and writes every line it receives to a recording.
+//! A [`Replayer`] reads a recording back, and yields the same messages as the client did,
+//! with the original timing, scaled timing, or as fast as possible.
+//!
+//! Each line of a recording consists of the receive timestamp in Unix milliseconds,
+//! the [connection id][Client::connection_id], and the raw IRC message, separated by spaces:
+//!
+//! ```text
+//! 1594545155039 0 :tmi.twitch.tv PING :tmi.twitch.tv
+//! ```
+//!
+//! ```rust,no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use tmi::client::record::{Recorder, Replayer, Speed};
+//!
+//! // record
+//! let client = tmi::Client::anonymous().await?;
+//! let file = tokio::fs::File::create("session.log").await?;
+//! let mut recorder = Recorder::new(client, file);
+//! recorder.client().join("#forsen").await?;
+//! for _ in 0..100 {
+//!   let msg = recorder.recv().await?;
+//!   println!("{}", msg.raw());
+//! }
+//!
+//! // replay
+//! let file = tokio::fs::File::open("session.log").await?;
+//! let mut replayer = Replayer::new(file).speed(Speed::Scaled(10.0));
+//! while let Ok(msg) = replayer.recv().await {
+//!   println!("{}", msg.raw());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::read::{RecvError, Typed};
+use super::Client;
+use crate::irc::IrcMessage;
+use chrono::{DateTime, TimeZone, Utc};
+use futures_util::{ready, Stream, StreamExt};
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{self, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::time::{Instant, Sleep};
+use tokio_stream::wrappers::LinesStream;
+
+/// A single line of a recording.
+#[derive(Clone, Debug)]
+pub struct Entry {
+  /// When the message was received.
+  pub received_at: DateTime<Utc>,
+
+  /// The [connection id][Client::connection_id] of the connection which received the message.
+  pub connection_id: u64,
+
+  /// The raw message, without the trailing `\r\n`.
+  pub raw: String,
+}
+
+impl Entry {
+  /// Parse a line of a recording.
+  pub fn parse(line: &str) -> Option<Entry> {
+    let (timestamp, rest) = line.split_once(' ')?;
+    let (connection_id, raw) = rest.split_once(' ')?;
+    Some(Entry {
+      received_at: Utc.timestamp_millis_opt(timestamp.parse().ok()?).single()?,
+      connection_id: connection_id.parse().ok()?,
+      raw: raw.to_owned(),
+    })
+  }
+
+  /// Parse the raw message.
+  pub fn message(&self) -> Option<IrcMessage> {
+    IrcMessage::parse(&self.raw)
+  }
+}
+
+impl Display for Entry {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let timestamp = self.received_at.timestamp_millis();
+    write!(f, "{timestamp} {} {}", self.connection_id, self.raw)
+  }
+}
+
+/// Wraps a [`Client`], and records every line it receives.
+///
+/// Lines which fail to parse are recorded too.
+/// Every line is written to `out` before it is returned.
+pub struct Recorder<W> {
+  client: Client,
+  out: W,
+}
+
+impl<W: AsyncWrite + Unpin> Recorder<W> {
+  /// Record the messages received by `client` to `out`.
+  pub fn new(client: Client, out: W) -> Self {
+    Self { client, out }
+  }
+
+  /// Receive a message from the client, and record it.
+  ///
+  /// See [`Client::recv`].
+  pub async fn recv(&mut self) -> Result<IrcMessage, RecordError> {
+    let result = self.client.recv().await;
+    let raw = match &result {
+      Ok(message) => message.raw(),
+      Err(RecvError::Parse(line)) => line.as_str(),
+      Err(_) => return result.map_err(Into::into),
+    };
+    let entry = Entry {
+      received_at: Utc::now(),
+      connection_id: self.client.connection_id(),
+      raw: raw.to_owned(),
+    };
+    self.out.write_all(format!("{entry}\n").as_bytes()).await?;
+    self.out.flush().await?;
+    result.map_err(Into::into)
+  }
+
+  /// The recorded client.
+  #[inline]
+  pub fn client(&self) -> &Client {
+    &self.client
+  }
+
+  /// The recorded client.
+  ///
+  /// Messages received directly through the client are not recorded.
+  #[inline]
+  pub fn client_mut(&mut self) -> &mut Client {
+    &mut self.client
+  }
+
+  /// Stop recording, and return the client and the output.
+  pub fn into_inner(self) -> (Client, W) {
+    (self.client, self.out)
+  }
+}
+
+/// Failed to receive or record a message.
+#[derive(Debug)]
+pub enum RecordError {
+  /// Failed to receive the message.
+  Recv(RecvError),
+
+  /// Failed to write the message to the recording.
+  Io(io::Error),
+}
+
+impl From<RecvError> for RecordError {
+  fn from(value: RecvError) -> Self {
+    Self::Recv(value)
+  }
+}
+
+impl From<io::Error> for RecordError {
+  fn from(value: io::Error) -> Self {
+    Self::Io(value)
+  }
+}
+
+impl Display for RecordError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      RecordError::Recv(e) => write!(f, "{e}"),
+      RecordError::Io(e) => write!(f, "failed to record message: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for RecordError {}
+
+/// How fast a [`Replayer`] yields messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Speed {
+  /// With the same delays between messages as when they were recorded.
+  #[default]
+  Original,
+
+  /// With the recorded delays divided by the given factor,
+  /// so `Scaled(2.0)` replays twice as fast.
+  ///
+  /// The factor must be finite and greater than zero.
+  Scaled(f64),
+
+  /// As fast as possible.
+  Unlimited,
+}
+
+/// Reads a recording, and yields its messages like a [`Client`].
+///
+/// Implements [`Stream`] with the same item type as [`Client`],
+/// so it can be used wherever a client's messages are consumed, for example with [`Typed`].
+pub struct Replayer<R> {
+  lines: LinesStream<BufReader<R>>,
+  speed: Speed,
+  /// When the first entry was yielded, and when it was received.
+  start: Option<(Instant, DateTime<Utc>)>,
+  delay: Option<(Pin<Box<Sleep>>, Entry)>,
+}
+
+impl<R: AsyncRead + Unpin> Replayer<R> {
+  /// Replay the recording read from `reader` at [`Speed::Original`].
+  pub fn new(reader: R) -> Self {
+    Self {
+      lines: LinesStream::new(tokio::io::AsyncBufReadExt::lines(BufReader::new(reader))),
+      speed: Speed::Original,
+      start: None,
+      delay: None,
+    }
+  }
+
+  /// Set the replay speed.
+  ///
+  /// Panics if the factor of [`Speed::Scaled`] is not finite, or not greater than zero.
+  pub fn speed(mut self, speed: Speed) -> Self {
+    if let Speed::Scaled(factor) = speed {
+      assert!(
+        factor.is_finite() && factor > 0.0,
+        "speed factor must be finite and greater than zero, got {factor}"
+      );
+    }
+    self.speed = speed;
+    self
+  }
+
+  /// Read the next entry, waiting until it is due.
+  ///
+  /// Returns `None` at the end of the recording.
+  pub async fn next_entry(&mut self) -> Option<Result<Entry, RecvError>> {
+    std::future::poll_fn(|cx| self.poll_entry(cx)).await
+  }
+
+  /// Read the next message, waiting until it is due.
+  ///
+  /// Fails with [`RecvError::StreamClosed`] at the end of the recording.
+  pub async fn recv(&mut self) -> Result<IrcMessage, RecvError> {
+    self.next().await.unwrap_or(Err(RecvError::StreamClosed))
+  }
+
+  /// Adapts this replayer into a stream of typed messages.
+  ///
+  /// See [`Typed`].
+  pub fn typed(&mut self) -> Typed<&mut Self> {
+    Typed::new(self)
+  }
+
+  fn poll_entry(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Entry, RecvError>>> {
+    if let Some((sleep, _)) = &mut self.delay {
+      ready!(sleep.as_mut().poll(cx));
+      let (_, entry) = self.delay.take().unwrap();
+      return Poll::Ready(Some(Ok(entry)));
+    }
+
+    let Some(line) = ready!(self.lines.poll_next_unpin(cx)) else {
+      return Poll::Ready(None);
+    };
+    let line = match line {
+      Ok(line) => line,
+      Err(e) => return Poll::Ready(Some(Err(e.into()))),
+    };
+    let Some(entry) = Entry::parse(&line) else {
+      return Poll::Ready(Some(Err(RecvError::Parse(line))));
+    };
+
+    let factor = match self.speed {
+      Speed::Original => 1.0,
+      Speed::Scaled(factor) => factor,
+      Speed::Unlimited => return Poll::Ready(Some(Ok(entry))),
+    };
+    let (started_at, first) = *self
+      .start
+      .get_or_insert_with(|| (Instant::now(), entry.received_at));
+    let offset = (entry.received_at - first).to_std().unwrap_or_default();
+    let deadline = started_at + Duration::from_secs_f64(offset.as_secs_f64() / factor);
+    if deadline <= Instant::now() {
+      return Poll::Ready(Some(Ok(entry)));
+    }
+    self.delay = Some((Box::pin(tokio::time::sleep_until(deadline)), entry));
+    self.poll_entry(cx)
+  }
+}
+
+impl<R: AsyncRead + Unpin> Stream for Replayer<R> {
+  type Item = Result<IrcMessage, RecvError>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let entry = ready!(self.poll_entry(cx));
+    Poll::Ready(entry.map(|entry| {
+      let entry = entry?;
+      entry.message().ok_or(RecvError::Parse(entry.raw))
+    }))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::client::conn::Endpoint;
+
+  const LINES: &[&str] = &[
+    ":tmi.twitch.tv PING :tmi.twitch.tv",
+    "@emote-only=0;room-id=11148817 :tmi.twitch.tv ROOMSTATE #pajlada",
+    // missing command
+    ":tmi.twitch.tv ",
+  ];
+
+  async fn client() -> Client {
+    let endpoint = Endpoint::custom(|| {
+      let (client, server) = tokio::io::duplex(1024);
+      tokio::spawn(async move {
+        let (reader, mut writer) = tokio::io::split(server);
+        let mut reader = tokio::io::AsyncBufReadExt::lines(BufReader::new(reader));
+        while let Some(line) = reader.next_line().await.unwrap() {
+          if line.starts_with("NICK") {
+            break;
+          }
+        }
+        writer
+          .write_all(b":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!\r\n")
+          .await
+          .unwrap();
+        for line in LINES {
+          writer
+            .write_all(format!("{line}\r\n").as_bytes())
+            .await
+            .unwrap();
+        }
+        while let Ok(Some(_)) = reader.next_line().await {}
+      });
+      async move { Ok(client) }
+    });
+    Client::builder()
+      .endpoint(endpoint)
+      .capabilities([])
+      .connect()
+      .await
+      .unwrap()
+  }
+
+  #[tokio::test]
+  async fn record_and_replay() {
+    let mut recorder = Recorder::new(client().await, Vec::new());
+    for _ in 0..2 {
+      recorder.recv().await.unwrap();
+    }
+    assert!(matches!(
+      recorder.recv().await,
+      Err(RecordError::Recv(RecvError::Parse(_)))
+    ));
+    let (_, recording) = recorder.into_inner();
+
+    let recording = String::from_utf8(recording).unwrap();
+    let entries = recording
+      .lines()
+      .map(|line| Entry::parse(line).unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      entries.iter().map(|e| e.raw.as_str()).collect::<Vec<_>>(),
+      LINES
+    );
+    assert!(entries.iter().all(|e| e.connection_id == 0));
+
+    let mut replayer = Replayer::new(recording.as_bytes()).speed(Speed::Unlimited);
+    assert_eq!(replayer.recv().await.unwrap().raw(), LINES[0]);
+    let mut typed = replayer.typed();
+    let message = typed.next().await.unwrap().unwrap();
+    assert!(matches!(message.typed(), crate::Message::RoomState(_)));
+    assert!(matches!(
+      typed.next().await,
+      Some(Err(RecvError::Parse(raw))) if raw == LINES[2]
+    ));
+    assert!(typed.next().await.is_none());
+  }
+
+  #[tokio::test]
+  async fn replay_speed() {
+    let recording = [
+      "1000 0 :tmi.twitch.tv PING :a",
+      "1100 0 :tmi.twitch.tv PING :b",
+      "1200 1 :tmi.twitch.tv PING :c",
+    ]
+    .join("\n");
+
+    let start = Instant::now();
+    let mut replayer = Replayer::new(recording.as_bytes()).speed(Speed::Scaled(2.0));
+    let mut connections = Vec::new();
+    while let Some(entry) = replayer.next_entry().await {
+      connections.push(entry.unwrap().connection_id);
+    }
+    assert_eq!(connections, [0, 0, 1]);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    let start = Instant::now();
+    let replayer = Replayer::new(recording.as_bytes()).speed(Speed::Unlimited);
+    assert_eq!(replayer.count().await, 3);
+    assert!(start.elapsed() < Duration::from_millis(100));
+  }
+
+  #[test]
+  fn invalid_speed() {
+    for factor in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+      let result =
+        std::panic::catch_unwind(|| Replayer::new(&b""[..]).speed(Speed::Scaled(factor)));
+      assert!(result.is_err(), "accepted factor {factor}");
+    }
+  }
+}
@@ -0,0 +1,228 @@
+//! Validation of outgoing messages.
+//!
+//! See [`Validation`].
+
+use super::write::SendError;
+use std::borrow::Cow;
+
+/// The maximum number of characters in a single `PRIVMSG`.
+pub const MAX_MESSAGE_LENGTH: usize = 500;
+
+/// How outgoing messages are checked before they are sent.
+///
+/// The [`Default`] impl rejects line breaks, splits long messages,
+/// and does not validate channel names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Validation {
+  /// What to do with `\r` and `\n` in message text.
+  pub line_breaks: LineBreaks,
+
+  /// Split messages longer than [`MAX_MESSAGE_LENGTH`] into several messages.
+  ///
+  /// If `false`, such messages are rejected with [`SendError::TooLong`].
+  pub split_long_messages: bool,
+
+  /// Reject channel names which are not valid Twitch logins
+  /// with [`SendError::InvalidChannel`].
+  ///
+  /// Channel names containing `\r` or `\n` are rejected either way.
+  pub validate_channels: bool,
+}
+
+impl Default for Validation {
+  fn default() -> Self {
+    Self {
+      line_breaks: LineBreaks::Reject,
+      split_long_messages: true,
+      validate_channels: false,
+    }
+  }
+}
+
+/// What to do with `\r` and `\n` in message text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineBreaks {
+  /// Fail with [`SendError::LineBreak`].
+  Reject,
+  /// Replace each run of line breaks with a single space.
+  Strip,
+}
+
+impl Validation {
+  /// Check `text` according to [`Validation::line_breaks`].
+  pub(super) fn sanitize<'a>(&self, text: &'a str) -> Result<Cow<'a, str>, SendError> {
+    if !text.contains(['\r', '\n']) {
+      return Ok(Cow::Borrowed(text));
+    }
+    match self.line_breaks {
+      LineBreaks::Reject => Err(SendError::LineBreak),
+      LineBreaks::Strip => Ok(Cow::Owned(
+        text
+          .split(['\r', '\n'])
+          .filter(|line| !line.is_empty())
+          .collect::<Vec<_>>()
+          .join(" "),
+      )),
+    }
+  }
+
+  /// Check `channel` according to [`Validation::validate_channels`].
+  ///
+  /// Line breaks are always rejected, because they would end the command early.
+  pub(super) fn channel(&self, channel: &str) -> Result<(), SendError> {
+    if channel.contains(['\r', '\n']) || (self.validate_channels && !is_valid_channel(channel)) {
+      return Err(SendError::InvalidChannel(channel.to_owned()));
+    }
+    Ok(())
+  }
+
+  /// Split `text` into parts of at most `max_len` characters,
+  /// according to [`Validation::split_long_messages`].
+  pub(super) fn split<'a>(&self, text: &'a str, max_len: usize) -> Result<Vec<&'a str>, SendError> {
+    let len = text.chars().count();
+    if len <= max_len {
+      Ok(vec![text])
+    } else if self.split_long_messages {
+      let parts = split_message(text, max_len);
+      if parts.is_empty() {
+        return Err(SendError::Blank(len));
+      }
+      Ok(parts)
+    } else {
+      Err(SendError::TooLong(len))
+    }
+  }
+}
+
+/// Whether `channel` is a valid Twitch login, optionally prefixed by `#`.
+///
+/// Logins consist of 1 to 25 lowercase ASCII letters, digits, or underscores,
+/// and may not start with an underscore.
+///
+/// ```rust
+/// use tmi::client::validate::is_valid_channel;
+///
+/// assert!(is_valid_channel("#forsen"));
+/// assert!(is_valid_channel("pajlada"));
+/// assert!(!is_valid_channel("#Forsen"));
+/// assert!(!is_valid_channel("#a b"));
+/// ```
+pub fn is_valid_channel(channel: &str) -> bool {
+  let login = channel.strip_prefix('#').unwrap_or(channel);
+  (1..=25).contains(&login.len())
+    && !login.starts_with('_')
+    && login
+      .bytes()
+      .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+}
+
+/// Split `text` into parts of at most `max_len` characters.
+///
+/// Splits on whitespace where possible, and only splits words
+/// which are longer than `max_len` on their own.
+///
+/// ```rust
+/// use tmi::client::validate::split_message;
+///
+/// assert_eq!(split_message("aaa bbb ccc", 7), ["aaa bbb", "ccc"]);
+/// assert_eq!(split_message("aaaaaaaaa b", 4), ["aaaa", "aaaa", "a b"]);
+/// ```
+pub fn split_message(text: &str, max_len: usize) -> Vec<&str> {
+  assert!(max_len > 0, "max_len must be greater than zero");
+
+  let mut parts = Vec::new();
+  let mut rest = text.trim();
+  while !rest.is_empty() {
+    // byte offset of the character after the first `max_len` characters
+    let Some((limit, _)) = rest.char_indices().nth(max_len) else {
+      parts.push(rest);
+      break;
+    };
+    let end = if rest[limit..].starts_with(char::is_whitespace) {
+      limit
+    } else {
+      match rest[..limit].rfind(char::is_whitespace) {
+        Some(space) if !rest[..space].trim().is_empty() => space,
+        _ => limit,
+      }
+    };
+    parts.push(rest[..end].trim_end());
+    rest = rest[end..].trim_start();
+  }
+  parts
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn split() {
+    assert_eq!(split_message("", 5), Vec::<&str>::new());
+    assert_eq!(split_message("  a  ", 5), ["a"]);
+    assert_eq!(split_message("aaaaa", 5), ["aaaaa"]);
+    assert_eq!(split_message("aaaaa bbbbb", 5), ["aaaaa", "bbbbb"]);
+    assert_eq!(split_message("aa bb cc dd", 5), ["aa bb", "cc dd"]);
+    assert_eq!(split_message("aaaaaaaaaaaa", 5), ["aaaaa", "aaaaa", "aa"]);
+    assert_eq!(split_message("ääää öööö", 4), ["ääää", "öööö"]);
+
+    let text = "lorem ipsum ".repeat(100);
+    let parts = split_message(&text, MAX_MESSAGE_LENGTH);
+    assert!(parts
+      .iter()
+      .all(|p| p.chars().count() <= MAX_MESSAGE_LENGTH));
+    assert_eq!(parts.join(" "), text.trim());
+  }
+
+  #[test]
+  fn sanitize() {
+    let reject = Validation::default();
+    assert!(matches!(reject.sanitize("a"), Ok(Cow::Borrowed("a"))));
+    assert!(matches!(
+      reject.sanitize("a\r\nb"),
+      Err(SendError::LineBreak)
+    ));
+
+    let strip = Validation {
+      line_breaks: LineBreaks::Strip,
+      ..Default::default()
+    };
+    assert_eq!(strip.sanitize("a\r\nb\nc\r").unwrap(), "a b c");
+  }
+
+  #[test]
+  fn channel() {
+    let validation = Validation {
+      validate_channels: true,
+      ..Default::default()
+    };
+    assert!(validation.channel("#forsen_123").is_ok());
+    assert!(matches!(
+      validation.channel("#_forsen"),
+      Err(SendError::InvalidChannel(_))
+    ));
+    assert!(matches!(
+      validation.channel("#"),
+      Err(SendError::InvalidChannel(_))
+    ));
+    assert!(matches!(
+      validation.channel(&format!("#{}", "a".repeat(26))),
+      Err(SendError::InvalidChannel(_))
+    ));
+    assert!(Validation::default().channel("#Forsen").is_ok());
+    assert!(matches!(
+      Validation::default().channel("#a\r\nPRIVMSG #b :hi"),
+      Err(SendError::InvalidChannel(_))
+    ));
+  }
+
+  #[test]
+  fn split_blank() {
+    let validation = Validation::default();
+    assert_eq!(validation.split("   ", 5).unwrap(), ["   "]);
+    assert!(matches!(
+      validation.split(&" ".repeat(6), 5),
+      Err(SendError::Blank(6))
+    ));
+  }
+}
@@ -1,18 +1,98 @@
-use super::{conn, Client};
+use super::validate::{Validation, MAX_MESSAGE_LENGTH};
+use super::{conn, Client, Config};
 use crate::common::JoinIter;
+use queue::{Item, Queue};
+use std::collections::HashMap;
 use std::convert::Infallible;
-use std::fmt::Display;
+use std::fmt::{Display, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io;
-use tokio::io::{AsyncWriteExt, WriteHalf};
+use tokio::io::WriteHalf;
+use tokio::sync::oneshot;
+
+mod queue;
+
+pub use queue::{Overflow, Priority, QueueConfig, QueueMetrics, RateLimit};
 
 pub type WriteStream = WriteHalf<conn::Stream>;
 
+/// The sending half of a [`Client`].
+///
+/// This is a cheaply cloneable handle to a background task which owns the
+/// underlying stream. Messages are queued by [`Priority`], and messages with
+/// the same priority are sent in turns per channel, see [`QueueConfig`].
+/// When the [`ClientReader`][super::ClientReader] reconnects, the task switches
+/// to the new connection.
+///
+/// Created by [`Client::split`].
+#[derive(Clone)]
+pub struct ClientWriter {
+  queue: Arc<QueueHandle>,
+  same_message_bypass: bool,
+  validation: Validation,
+}
+
+/// Stops the writer task once the last [`ClientWriter`] is dropped.
+struct QueueHandle(Arc<Queue>);
+
+impl Drop for QueueHandle {
+  fn drop(&mut self) {
+    self.0.close();
+  }
+}
+
+impl ClientWriter {
+  pub(super) fn spawn(stream: WriteStream, config: &Config) -> Self {
+    let queue = Queue::new(config.queue);
+    let same_message = config.same_message_bypass.then(SameMessageTracker::default);
+    tokio::spawn(queue::run(queue.clone(), stream, same_message));
+    Self {
+      queue: Arc::new(QueueHandle(queue)),
+      same_message_bypass: config.same_message_bypass,
+      validation: config.validation,
+    }
+  }
+
+  /// Switch the writer task to a new connection.
+  pub(super) fn swap(&self, stream: WriteStream) {
+    self.queue.0.swap(stream);
+  }
+
+  /// Queue `data` and wait until it has been written to the stream.
+  ///
+  /// `text` is the text of a `PRIVMSG`, which the same-message bypass is applied to.
+  async fn enqueue(
+    &self,
+    data: String,
+    channel: &str,
+    text: Option<&str>,
+    priority: Priority,
+  ) -> Result<(), SendError> {
+    let (result, rx) = oneshot::channel();
+    let item = Item {
+      data,
+      privmsg: text.map(|text| (channel.to_owned(), text.to_owned())),
+      result,
+    };
+    let channel = channel.trim_start_matches('#').to_ascii_lowercase();
+    self.queue.0.push(&channel, priority, item);
+    rx.await.map_err(|_| SendError::StreamClosed)?
+  }
+
+  /// Get a snapshot of the send queue.
+  pub fn queue_metrics(&self) -> QueueMetrics {
+    self.queue.0.metrics()
+  }
+}
+
 pub struct Privmsg<'a> {
-  client: &'a mut Client,
+  writer: &'a ClientWriter,
   channel: &'a str,
   text: &'a str,
   reply_parent_msg_id: Option<&'a str>,
   client_nonce: Option<&'a str>,
+  priority: Priority,
 }
 
 struct Tag<'a> {
@@ -29,63 +109,108 @@ impl<'a> std::fmt::Display for Tag<'a> {
 }
 
 impl<'a> Privmsg<'a> {
+  /// Send this message as a reply to the message with the given ID.
+  ///
+  /// If the message is split, every part is a reply to the same message.
   pub fn reply_to(mut self, reply_parent_msg_id: &'a str) -> Self {
     self.reply_parent_msg_id = Some(reply_parent_msg_id);
     self
   }
 
+  /// Set the `client-nonce` tag, which identifies the message in any resulting `NOTICE`.
+  ///
+  /// If the message is split, every part carries the same nonce.
   pub fn client_nonce(mut self, value: &'a str) -> Self {
     self.client_nonce = Some(value);
     self
   }
 
+  /// Set the [`Priority`] of this message in the send queue.
+  pub fn priority(mut self, priority: Priority) -> Self {
+    self.priority = priority;
+    self
+  }
+
   pub async fn send(self) -> Result<(), SendError> {
     let Self {
-      client,
+      writer,
       channel,
       text,
       reply_parent_msg_id,
       client_nonce,
+      priority,
     } = self;
 
-    with_scratch!(client, |f| {
-      let has_tags = reply_parent_msg_id.is_some() || client_nonce.is_some();
-      if has_tags {
-        let reply_parent_msg_id = reply_parent_msg_id.map(|value| Tag {
-          key: "reply-parent-msg-id",
-          value,
-        });
-        let client_nonce = client_nonce.map(|value| Tag {
-          key: "client-nonce",
-          value,
-        });
-        let tags = reply_parent_msg_id
-          .iter()
-          .chain(client_nonce.iter())
-          .join(';');
-        let _ = write!(f, "@{tags} ");
-      }
-      let _ = write!(f, "PRIVMSG {channel} :{text}\r\n");
-      client.send_raw(f.as_str()).await
-    })
+    let validation = &writer.validation;
+    validation.channel(channel)?;
+    let mut tag_values = reply_parent_msg_id.iter().chain(client_nonce.iter());
+    if tag_values.any(|value| value.contains(['\r', '\n'])) {
+      return Err(SendError::LineBreak);
+    }
+    let text = validation.sanitize(text)?;
+    let max_len = match writer.same_message_bypass {
+      true => MAX_MESSAGE_LENGTH - SAME_MESSAGE_BYPASS.chars().count(),
+      false => MAX_MESSAGE_LENGTH,
+    };
+
+    let mut tags = String::new();
+    let has_tags = reply_parent_msg_id.is_some() || client_nonce.is_some();
+    if has_tags {
+      let reply_parent_msg_id = reply_parent_msg_id.map(|value| Tag {
+        key: "reply-parent-msg-id",
+        value,
+      });
+      let client_nonce = client_nonce.map(|value| Tag {
+        key: "client-nonce",
+        value,
+      });
+      let tags_iter = reply_parent_msg_id
+        .iter()
+        .chain(client_nonce.iter())
+        .join(';');
+      let _ = write!(tags, "@{tags_iter} ");
+    }
+
+    for text in validation.split(&text, max_len)? {
+      // the same-message bypass is appended once the message is actually written
+      let data = format!("{tags}PRIVMSG {channel} :{text}\r\n");
+      writer.enqueue(data, channel, Some(text), priority).await?;
+    }
+    Ok(())
   }
 }
 
-impl Client {
+impl ClientWriter {
   /// Send a raw string through the TCP socket.
   ///
-  /// ⚠ This call is not rate limited in any way.
+  /// Resolves once the message has been written to the stream.
+  /// The message is queued with [`Priority::Normal`].
+  ///
+  /// ⚠ This call is only rate limited if [`QueueConfig::rate_limit`] is set.
   ///
   /// ⚠ The string MUST be terminated by `\r\n`.
-  pub async fn send_raw<'a, S>(&mut self, s: S) -> Result<(), SendError>
+  pub async fn send_raw<'a, S>(&self, s: S) -> Result<(), SendError>
+  where
+    S: TryInto<RawMessage<'a>>,
+    SendError: From<S::Error>,
+  {
+    self.send_raw_with_priority(s, Priority::Normal).await
+  }
+
+  /// Send a raw string through the TCP socket with the given [`Priority`].
+  ///
+  /// See [`ClientWriter::send_raw`].
+  pub async fn send_raw_with_priority<'a, S>(
+    &self,
+    s: S,
+    priority: Priority,
+  ) -> Result<(), SendError>
   where
     S: TryInto<RawMessage<'a>>,
     SendError: From<S::Error>,
   {
     let RawMessage { data } = s.try_into()?;
-    trace!(data, "sending message");
-    self.writer.write_all(data.as_bytes()).await?;
-    Ok(())
+    self.enqueue(data.to_owned(), "", None, priority).await
   }
 
   /// Create a `privmsg` from a `channel` and `text`.
@@ -93,8 +218,8 @@ impl Client {
   /// ```rust,no_run
   /// # async fn _test() -> anyhow::Result<()> {
   /// # let msg: tmi::Privmsg<'_> = todo!();
-  /// # let mut client: tmi::Client = todo!();
-  /// client
+  /// # let writer: tmi::client::ClientWriter = todo!();
+  /// writer
   ///   .privmsg(msg.channel(), "yo")
   ///   .reply_to(msg.id())
   ///   .send()
@@ -106,72 +231,127 @@ impl Client {
   /// You can specify additional properties using the builder methods:
   /// - `reply_to`: to specify a `reply-parent-msg-id` tag, which makes this privmsg a reply to another message.
   /// - `client_nonce`: to identify the message in the `Notice` which Twitch may send as a response to this message.
-  pub fn privmsg<'a>(&'a mut self, channel: &'a str, text: &'a str) -> Privmsg<'a> {
+  /// - `priority`: to move the message ahead of (or behind) other queued messages.
+  pub fn privmsg<'a>(&'a self, channel: &'a str, text: &'a str) -> Privmsg<'a> {
     Privmsg {
-      client: self,
+      writer: self,
       channel,
       text,
       reply_parent_msg_id: None,
       client_nonce: None,
+      priority: Priority::Normal,
     }
   }
 
   /// Send a `PING` command with an optional `nonce` argument.
-  pub async fn ping(&mut self, nonce: &str) -> Result<(), SendError> {
-    with_scratch!(self, |f| {
-      let _ = write!(f, "PING :{nonce}\r\n");
-      self.send_raw(f.as_str()).await
-    })
+  pub async fn ping(&self, nonce: &str) -> Result<(), SendError> {
+    self.send_raw(format!("PING :{nonce}\r\n").as_str()).await
   }
 
   /// Send a `PONG` command in response to a `PING`.
-  pub async fn pong(&mut self, ping: &crate::Ping<'_>) -> Result<(), SendError> {
-    with_scratch!(self, |f| {
-      if let Some(nonce) = ping.nonce() {
-        let _ = write!(f, "PONG :{nonce}\r\n");
-      } else {
-        let _ = write!(f, "PONG\r\n");
-      }
-      self.send_raw(f.as_str()).await
-    })
+  ///
+  /// This is queued with [`Priority::High`].
+  pub async fn pong(&self, ping: &crate::Ping<'_>) -> Result<(), SendError> {
+    let data = match ping.nonce() {
+      Some(nonce) => format!("PONG :{nonce}\r\n"),
+      None => "PONG\r\n".to_owned(),
+    };
+    self
+      .send_raw_with_priority(data.as_str(), Priority::High)
+      .await
   }
 
   /// Send a `JOIN` command.
   ///
-  /// ⚠ This call is not rate limited in any way.
+  /// ⚠ This call is only rate limited if [`QueueConfig::rate_limit`] is set.
   ///
   /// ⚠ `channel` MUST be a valid channel name prefixed by `#`.
-  pub async fn join(&mut self, channel: impl AsRef<str>) -> Result<(), SendError> {
-    with_scratch!(self, |f| {
-      let channel = Channel(channel);
-      let _ = write!(f, "JOIN {channel}\r\n");
-      Ok(self.send_raw(f.as_str()).await?)
-    })
+  pub async fn join(&self, channel: impl AsRef<str>) -> Result<(), SendError> {
+    self.validation.channel(channel.as_ref())?;
+    let channel = Channel(channel);
+    self.send_raw(format!("JOIN {channel}\r\n").as_str()).await
   }
 
   /// Send a `JOIN` command.
   ///
-  /// ⚠ This call is not rate limited in any way.
+  /// ⚠ This call is only rate limited if [`QueueConfig::rate_limit`] is set.
   ///
   /// ⚠ Each channel in `channels` MUST be a valid channel name
   /// prefixed by `#`.
-  pub async fn join_all<I, C>(&mut self, channels: I) -> Result<(), SendError>
+  pub async fn join_all<I, C>(&self, channels: I) -> Result<(), SendError>
   where
     I: IntoIterator<Item = C>,
     C: AsRef<str>,
   {
-    with_scratch!(self, |f| {
-      let _ = f.write_str("JOIN ");
-      let mut channels = channels.into_iter().map(Channel);
-      if let Some(channel) = channels.next() {
-        let _ = write!(f, "{channel}");
-      }
-      for channel in channels {
-        let _ = write!(f, ",{channel}");
-      }
-      let _ = f.write_str("\r\n");
-      self.send_raw(f.as_str()).await
-    })
+    let mut f = String::from("JOIN ");
+    let channels = channels.into_iter().collect::<Vec<_>>();
+    for channel in &channels {
+      self.validation.channel(channel.as_ref())?;
+    }
+    let mut channels = channels.into_iter().map(Channel);
+    if let Some(channel) = channels.next() {
+      let _ = write!(f, "{channel}");
+    }
+    for channel in channels {
+      let _ = write!(f, ",{channel}");
+    }
+    f.push_str("\r\n");
+    self.send_raw(f.as_str()).await
+  }
+}
+
+impl Client {
+  /// Send a raw string through the TCP socket.
+  ///
+  /// See [`ClientWriter::send_raw`].
+  pub async fn send_raw<'a, S>(&self, s: S) -> Result<(), SendError>
+  where
+    S: TryInto<RawMessage<'a>>,
+    SendError: From<S::Error>,
+  {
+    self.writer().send_raw(s).await
+  }
+
+  /// Create a `privmsg` from a `channel` and `text`.
+  ///
+  /// See [`ClientWriter::privmsg`].
+  pub fn privmsg<'a>(&'a self, channel: &'a str, text: &'a str) -> Privmsg<'a> {
+    self.writer().privmsg(channel, text)
+  }
+
+  /// Send a `PING` command with an optional `nonce` argument.
+  pub async fn ping(&self, nonce: &str) -> Result<(), SendError> {
+    self.writer().ping(nonce).await
+  }
+
+  /// Send a `PONG` command in response to a `PING`.
+  pub async fn pong(&self, ping: &crate::Ping<'_>) -> Result<(), SendError> {
+    self.writer().pong(ping).await
+  }
+
+  /// Get a snapshot of the send queue.
+  ///
+  /// See [`ClientWriter::queue_metrics`].
+  pub fn queue_metrics(&self) -> QueueMetrics {
+    self.writer().queue_metrics()
+  }
+
+  /// Send a `JOIN` command.
+  ///
+  /// See [`ClientWriter::join`].
+  pub async fn join(&self, channel: impl AsRef<str>) -> Result<(), SendError> {
+    self.writer().join(channel).await
+  }
+
+  /// Send a `JOIN` command for each channel in `channels`.
+  ///
+  /// See [`ClientWriter::join_all`].
+  pub async fn join_all<I, C>(&self, channels: I) -> Result<(), SendError>
+  where
+    I: IntoIterator<Item = C>,
+    C: AsRef<str>,
+  {
+    self.writer().join_all(channels).await
   }
 }
 
@@ -189,6 +369,7 @@ impl<S: AsRef<str>> Display for Channel<S> {
 
 /// Failed to send a message.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum SendError {
   /// The underlying I/O operation failed.
   Io(io::Error),
@@ -198,6 +379,31 @@ pub enum SendError {
 
   /// Attempted to send an invalid message.
   InvalidMessage(InvalidMessage),
+
+  /// The message text or one of its tags contains `\r` or `\n`.
+  ///
+  /// For the text, see [`Validation::line_breaks`].
+  LineBreak,
+
+  /// The message text is longer than [`MAX_MESSAGE_LENGTH`] characters.
+  ///
+  /// See [`Validation::split_long_messages`].
+  TooLong(usize),
+
+  /// The message text is longer than [`MAX_MESSAGE_LENGTH`] characters,
+  /// but only consists of whitespace, so there is nothing to split it into.
+  Blank(usize),
+
+  /// The channel name is not a valid Twitch login.
+  ///
+  /// See [`Validation::validate_channels`].
+  InvalidChannel(String),
+
+  /// The send queue is full, see [`Overflow::Reject`].
+  QueueFull,
+
+  /// The message was dropped from the send queue, see [`Overflow::DropOldest`].
+  Dropped,
 }
 
 impl From<io::Error> for SendError {
@@ -227,13 +433,78 @@ impl Display for SendError {
         f,
         "failed to write message: message was incorrectly formatted, {inner}"
       ),
+      SendError::LineBreak => write!(f, "failed to write message: message contains a line break"),
+      SendError::TooLong(len) => write!(
+        f,
+        "failed to write message: text is {len} characters long, the limit is {MAX_MESSAGE_LENGTH}"
+      ),
+      SendError::Blank(len) => write!(
+        f,
+        "failed to write message: text is {len} characters of whitespace"
+      ),
+      SendError::InvalidChannel(channel) => write!(
+        f,
+        "failed to write message: invalid channel name `{channel}`"
+      ),
+      SendError::QueueFull => write!(f, "failed to write message: send queue is full"),
+      SendError::Dropped => write!(f, "failed to write message: dropped from the send queue"),
     }
   }
 }
 
 impl std::error::Error for SendError {}
 
+/// Appended to a message to make it different from the previous one.
+const SAME_MESSAGE_BYPASS: &str = concat!(" ", "⠀");
+
+/// How long Twitch remembers the last message sent to a channel.
+const SAME_MESSAGE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Tracks the last message sent to each channel, see [`ClientBuilder::same_message_bypass`][super::ClientBuilder::same_message_bypass].
+///
+/// Owned by the writer task, so that only messages which are actually written are tracked.
+#[derive(Default)]
+struct SameMessageTracker {
+  last: HashMap<String, LastMessage>,
+}
+
+struct LastMessage {
+  text: String,
+  sent_at: Instant,
+  bypassed: bool,
+}
+
+impl SameMessageTracker {
+  /// Record `text` as sent to `channel` at `now`, and return what to append to it.
+  fn apply(&mut self, channel: &str, text: &str, now: Instant) -> &'static str {
+    let channel = channel.trim_start_matches('#').to_ascii_lowercase();
+    let last = &mut self.last;
+    let bypass = match last.get(&channel) {
+      Some(prev) if prev.text == text && now.duration_since(prev.sent_at) < SAME_MESSAGE_WINDOW => {
+        !prev.bypassed
+      }
+      _ => false,
+    };
+    last.retain(|_, prev| now.duration_since(prev.sent_at) < SAME_MESSAGE_WINDOW);
+    last.insert(
+      channel,
+      LastMessage {
+        text: text.to_owned(),
+        sent_at: now,
+        bypassed: bypass,
+      },
+    );
+    match bypass {
+      true => SAME_MESSAGE_BYPASS,
+      false => "",
+    }
+  }
+}
+
 /// Bypass the same-message slow mode requirement.
+///
+/// See also [`ClientBuilder::same_message_bypass`][super::ClientBuilder::same_message_bypass],
+/// which does this automatically.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct SameMessageBypass {
   append: bool,
@@ -246,9 +517,7 @@ impl SameMessageBypass {
   pub fn get(&mut self) -> &'static str {
     let out = match self.append {
       false => "",
-      true => {
-        concat!(" ", "⠀")
-      }
+      true => SAME_MESSAGE_BYPASS,
     };
     self.append = !self.append;
     out
@@ -286,3 +555,27 @@ impl<'a> TryFrom<&'a str> for RawMessage<'a> {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn same_message_tracker() {
+    let mut tracker = SameMessageTracker::default();
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+
+    assert_eq!(tracker.apply("#a", "yo", at(0)), "");
+    assert_eq!(tracker.apply("#a", "yo", at(1)), SAME_MESSAGE_BYPASS);
+    assert_eq!(tracker.apply("#A", "yo", at(2)), "");
+    assert_eq!(tracker.apply("a", "yo", at(3)), SAME_MESSAGE_BYPASS);
+    // other channels are tracked separately
+    assert_eq!(tracker.apply("#b", "yo", at(4)), "");
+    // a different message resets the sequence
+    assert_eq!(tracker.apply("#a", "hi", at(5)), "");
+    assert_eq!(tracker.apply("#a", "yo", at(6)), "");
+    // outside of the window, duplicates are allowed
+    assert_eq!(tracker.apply("#a", "yo", at(40)), "");
+  }
+}
@@ -0,0 +1,543 @@
+use super::{SameMessageTracker, SendError, WriteStream};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{oneshot, Notify};
+use tokio::time::Instant;
+
+/// Priority of an outgoing message.
+///
+/// Messages with a higher priority are always sent before messages with a lower one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+  /// Bulk messages, such as announcements.
+  Low,
+  /// Most messages.
+  #[default]
+  Normal,
+  /// Urgent messages, such as moderation actions, replies, or `PONG`s.
+  High,
+}
+
+impl Priority {
+  const ALL: [Priority; 3] = [Priority::Low, Priority::Normal, Priority::High];
+
+  fn index(self) -> usize {
+    self as usize
+  }
+}
+
+/// Send queue configuration.
+///
+/// The [`Default`] impl creates an unbounded queue without rate limiting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueConfig {
+  /// Maximum number of messages waiting to be sent.
+  ///
+  /// `None` means the queue is unbounded.
+  pub max_len: Option<usize>,
+
+  /// What to do when a message is sent while the queue is full.
+  pub overflow: Overflow,
+
+  /// Maximum rate at which messages are written to the stream.
+  pub rate_limit: Option<RateLimit>,
+}
+
+/// What to do when a message is sent while the queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+  /// Fail the new message with [`SendError::QueueFull`].
+  #[default]
+  Reject,
+
+  /// Drop the oldest message with the lowest priority, which fails with [`SendError::Dropped`].
+  ///
+  /// Messages are never dropped in favor of a message with a lower priority.
+  /// If there is no such message, the new message is rejected.
+  DropOldest,
+}
+
+/// Allow at most `messages` per `per`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+  pub messages: u32,
+  pub per: Duration,
+}
+
+impl RateLimit {
+  /// The limit for regular users, 20 messages per 30 seconds.
+  pub const USER: RateLimit = RateLimit {
+    messages: 20,
+    per: Duration::from_secs(30),
+  };
+
+  /// The limit for moderators and the broadcaster, 100 messages per 30 seconds.
+  pub const MODERATOR: RateLimit = RateLimit {
+    messages: 100,
+    per: Duration::from_secs(30),
+  };
+}
+
+/// A snapshot of the send queue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueMetrics {
+  /// Number of messages waiting to be sent, per [`Priority`], from low to high.
+  pub depth: [usize; 3],
+
+  /// The highest total number of messages which were waiting at the same time.
+  pub max_depth: usize,
+
+  /// Number of messages which failed with [`SendError::QueueFull`].
+  pub rejected: u64,
+
+  /// Number of messages which failed with [`SendError::Dropped`].
+  pub dropped: u64,
+}
+
+impl QueueMetrics {
+  /// Total number of messages waiting to be sent.
+  pub fn len(&self) -> usize {
+    self.depth.iter().sum()
+  }
+
+  /// Whether there are no messages waiting to be sent.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Number of messages with `priority` waiting to be sent.
+  pub fn depth(&self, priority: Priority) -> usize {
+    self.depth[priority.index()]
+  }
+}
+
+pub(super) struct Item {
+  pub(super) data: String,
+  /// Channel and text of a `PRIVMSG`, see [`SameMessageTracker`].
+  pub(super) privmsg: Option<(String, String)>,
+  pub(super) result: oneshot::Sender<Result<(), SendError>>,
+}
+
+/// Messages with the same priority, queued per channel.
+///
+/// Channels take turns, so that one busy channel cannot starve the others.
+///
+/// Items are numbered in the order they were pushed, to find the oldest one.
+#[derive(Default)]
+struct Level {
+  channels: VecDeque<(String, VecDeque<(u64, Item)>)>,
+}
+
+impl Level {
+  fn push(&mut self, channel: &str, seq: u64, item: Item) {
+    match self.channels.iter_mut().find(|(c, _)| c == channel) {
+      Some((_, items)) => items.push_back((seq, item)),
+      None => self
+        .channels
+        .push_back((channel.to_owned(), VecDeque::from([(seq, item)]))),
+    }
+  }
+
+  fn pop(&mut self) -> Option<Item> {
+    let (channel, mut items) = self.channels.pop_front()?;
+    let item = items.pop_front();
+    if !items.is_empty() {
+      self.channels.push_back((channel, items));
+    }
+    item.map(|(_, item)| item)
+  }
+
+  fn pop_oldest(&mut self) -> Option<Item> {
+    let (index, _) = self
+      .channels
+      .iter()
+      .enumerate()
+      .filter_map(|(i, (_, items))| Some((i, items.front()?.0)))
+      .min_by_key(|(_, seq)| *seq)?;
+    let (_, items) = &mut self.channels[index];
+    let item = items.pop_front();
+    if items.is_empty() {
+      self.channels.remove(index);
+    }
+    item.map(|(_, item)| item)
+  }
+}
+
+#[derive(Default)]
+struct State {
+  levels: [Level; 3],
+  seq: u64,
+  metrics: QueueMetrics,
+  swap: Option<WriteStream>,
+  closed: bool,
+}
+
+/// The queue shared between the [`ClientWriter`][super::ClientWriter] handles and the writer task.
+pub(super) struct Queue {
+  config: QueueConfig,
+  state: Mutex<State>,
+  notify: Notify,
+}
+
+impl Queue {
+  pub(super) fn new(config: QueueConfig) -> Arc<Self> {
+    Arc::new(Self {
+      config,
+      state: Mutex::new(State::default()),
+      notify: Notify::new(),
+    })
+  }
+
+  pub(super) fn push(&self, channel: &str, priority: Priority, item: Item) {
+    let mut state = self.state.lock().unwrap();
+    if state.closed {
+      let _ = item.result.send(Err(SendError::StreamClosed));
+      return;
+    }
+
+    if let Some(max_len) = self.config.max_len {
+      if state.metrics.len() >= max_len {
+        let victim = match self.config.overflow {
+          Overflow::Reject => None,
+          Overflow::DropOldest => Priority::ALL
+            .into_iter()
+            .take_while(|p| *p <= priority)
+            .find_map(|p| Some((p, state.levels[p.index()].pop_oldest()?))),
+        };
+        match victim {
+          Some((p, victim)) => {
+            state.metrics.depth[p.index()] -= 1;
+            state.metrics.dropped += 1;
+            let _ = victim.result.send(Err(SendError::Dropped));
+          }
+          None => {
+            state.metrics.rejected += 1;
+            let _ = item.result.send(Err(SendError::QueueFull));
+            return;
+          }
+        }
+      }
+    }
+
+    let seq = state.seq;
+    state.seq += 1;
+    state.levels[priority.index()].push(channel, seq, item);
+    state.metrics.depth[priority.index()] += 1;
+    state.metrics.max_depth = state.metrics.max_depth.max(state.metrics.len());
+    drop(state);
+    self.notify.notify_one();
+  }
+
+  pub(super) fn swap(&self, stream: WriteStream) {
+    self.state.lock().unwrap().swap = Some(stream);
+    self.notify.notify_one();
+  }
+
+  /// Stop the writer task, and fail all queued messages.
+  pub(super) fn close(&self) {
+    let mut state = self.state.lock().unwrap();
+    state.closed = true;
+    for level in &mut state.levels {
+      while let Some(item) = level.pop() {
+        let _ = item.result.send(Err(SendError::StreamClosed));
+      }
+    }
+    state.metrics.depth = [0; 3];
+    drop(state);
+    self.notify.notify_one();
+  }
+
+  pub(super) fn metrics(&self) -> QueueMetrics {
+    self.state.lock().unwrap().metrics
+  }
+
+  fn pop(&self, stream: &mut WriteStream) -> Result<Option<Item>, Closed> {
+    let mut state = self.state.lock().unwrap();
+    if state.closed {
+      return Err(Closed);
+    }
+    if let Some(new) = state.swap.take() {
+      trace!("switching to new connection");
+      *stream = new;
+    }
+    for priority in Priority::ALL.into_iter().rev() {
+      if let Some(item) = state.levels[priority.index()].pop() {
+        state.metrics.depth[priority.index()] -= 1;
+        return Ok(Some(item));
+      }
+    }
+    Ok(None)
+  }
+}
+
+struct Closed;
+
+pub(super) async fn run(
+  queue: Arc<Queue>,
+  mut stream: WriteStream,
+  mut same_message: Option<SameMessageTracker>,
+) {
+  let mut sent = VecDeque::<Instant>::new();
+  loop {
+    if let Some(limit) = queue.config.rate_limit {
+      while let Some(oldest) = sent.front() {
+        if oldest.elapsed() >= limit.per {
+          sent.pop_front();
+        } else if sent.len() >= limit.messages as usize {
+          tokio::time::sleep_until(*oldest + limit.per).await;
+        } else {
+          break;
+        }
+      }
+    }
+
+    let item = match queue.pop(&mut stream) {
+      Err(Closed) => break,
+      Ok(Some(item)) => item,
+      Ok(None) => {
+        // `notify_one` stores a permit, so a push since `pop` is not missed
+        queue.notify.notified().await;
+        continue;
+      }
+    };
+    let mut data = item.data;
+    if let (Some(tracker), Some((channel, text))) = (&mut same_message, &item.privmsg) {
+      // the bypass goes right before the `\r\n`
+      let bypass = tracker.apply(channel, text, std::time::Instant::now());
+      data.insert_str(data.len() - 2, bypass);
+    }
+    trace!(data, "sending message");
+    let result = write(&mut stream, &data).await;
+    if queue.config.rate_limit.is_some() {
+      sent.push_back(Instant::now());
+    }
+    let _ = item.result.send(result);
+  }
+  trace!("all writers dropped, stopping writer task");
+}
+
+async fn write(stream: &mut WriteStream, data: &str) -> Result<(), SendError> {
+  stream.write_all(data.as_bytes()).await?;
+  stream.flush().await?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn item(data: &str) -> (Item, oneshot::Receiver<Result<(), SendError>>) {
+    let (result, rx) = oneshot::channel();
+    let item = Item {
+      data: data.to_owned(),
+      privmsg: None,
+      result,
+    };
+    (item, rx)
+  }
+
+  fn drain(queue: &Queue) -> Vec<String> {
+    let (_, mut stream) =
+      tokio::io::split(Box::new(tokio::io::empty()) as crate::client::conn::Stream);
+    let mut out = Vec::new();
+    while let Ok(Some(item)) = queue.pop(&mut stream) {
+      out.push(item.data);
+    }
+    out
+  }
+
+  #[test]
+  fn priority_and_fairness() {
+    let queue = Queue::new(QueueConfig::default());
+    let mut receivers = Vec::new();
+    for (channel, priority, data) in [
+      ("#a", Priority::Low, "a-low"),
+      ("#a", Priority::Normal, "a-1"),
+      ("#a", Priority::Normal, "a-2"),
+      ("#a", Priority::Normal, "a-3"),
+      ("#b", Priority::Normal, "b-1"),
+      ("#b", Priority::Normal, "b-2"),
+      ("#c", Priority::High, "c-high"),
+    ] {
+      let (item, rx) = item(data);
+      queue.push(channel, priority, item);
+      receivers.push(rx);
+    }
+    assert_eq!(queue.metrics().len(), 7);
+    assert_eq!(queue.metrics().depth(Priority::Normal), 5);
+
+    assert_eq!(
+      drain(&queue),
+      ["c-high", "a-1", "b-1", "a-2", "b-2", "a-3", "a-low"]
+    );
+    assert_eq!(queue.metrics().len(), 0);
+    assert_eq!(queue.metrics().max_depth, 7);
+  }
+
+  #[test]
+  fn overflow_reject() {
+    let queue = Queue::new(QueueConfig {
+      max_len: Some(1),
+      overflow: Overflow::Reject,
+      rate_limit: None,
+    });
+    let (a, _a) = item("a");
+    let (b, mut b_rx) = item("b");
+    queue.push("#a", Priority::Normal, a);
+    queue.push("#a", Priority::High, b);
+    assert!(matches!(b_rx.try_recv(), Ok(Err(SendError::QueueFull))));
+    assert_eq!(queue.metrics().rejected, 1);
+    assert_eq!(drain(&queue), ["a"]);
+  }
+
+  #[test]
+  fn overflow_drop_oldest() {
+    let queue = Queue::new(QueueConfig {
+      max_len: Some(2),
+      overflow: Overflow::DropOldest,
+      rate_limit: None,
+    });
+    let (a, mut a_rx) = item("a");
+    let (b, _b) = item("b");
+    let (c, _c) = item("c");
+    let (d, mut d_rx) = item("d");
+    queue.push("#a", Priority::Low, a);
+    queue.push("#a", Priority::High, b);
+    // drops `a`, which has a lower priority
+    queue.push("#a", Priority::Normal, c);
+    assert!(matches!(a_rx.try_recv(), Ok(Err(SendError::Dropped))));
+    // nothing with a lower or equal priority to drop
+    queue.push("#a", Priority::Low, d);
+    assert!(matches!(d_rx.try_recv(), Ok(Err(SendError::QueueFull))));
+
+    let metrics = queue.metrics();
+    assert_eq!((metrics.dropped, metrics.rejected), (1, 1));
+    assert_eq!(drain(&queue), ["b", "c"]);
+  }
+
+  #[test]
+  fn close() {
+    let queue = Queue::new(QueueConfig::default());
+    let (a, mut a_rx) = item("a");
+    queue.push("#a", Priority::Normal, a);
+    queue.close();
+    assert!(matches!(a_rx.try_recv(), Ok(Err(SendError::StreamClosed))));
+    let (b, mut b_rx) = item("b");
+    queue.push("#a", Priority::Normal, b);
+    assert!(matches!(b_rx.try_recv(), Ok(Err(SendError::StreamClosed))));
+  }
+
+  #[test]
+  fn drop_oldest_across_channels() {
+    let queue = Queue::new(QueueConfig {
+      max_len: Some(3),
+      overflow: Overflow::DropOldest,
+      rate_limit: None,
+    });
+    let mut receivers = Vec::new();
+    for (channel, data) in [("#a", "a-1"), ("#b", "b-1"), ("#a", "a-2"), ("#b", "b-2")] {
+      let (item, rx) = item(data);
+      queue.push(channel, Priority::Normal, item);
+      receivers.push(rx);
+    }
+    assert!(matches!(
+      receivers[0].try_recv(),
+      Ok(Err(SendError::Dropped))
+    ));
+    assert_eq!(drain(&queue), ["a-2", "b-1", "b-2"]);
+  }
+
+  #[tokio::test]
+  async fn rate_limit() {
+    use tokio::io::AsyncBufReadExt;
+
+    let per = Duration::from_millis(200);
+    let queue = Queue::new(QueueConfig {
+      rate_limit: Some(RateLimit { messages: 2, per }),
+      ..Default::default()
+    });
+    let (client, server) = tokio::io::duplex(1024);
+    let (_, stream) = tokio::io::split(Box::new(client) as crate::client::conn::Stream);
+    tokio::spawn(run(queue.clone(), stream, None));
+
+    let start = Instant::now();
+    let mut receivers = Vec::new();
+    for data in ["a\r\n", "b\r\n", "c\r\n"] {
+      let (item, rx) = item(data);
+      queue.push("#a", Priority::Normal, item);
+      receivers.push(rx);
+    }
+
+    let mut lines = tokio::io::BufReader::new(server).lines();
+    for expected in ["a", "b"] {
+      assert_eq!(lines.next_line().await.unwrap().unwrap(), expected);
+    }
+    assert!(start.elapsed() < per);
+    assert_eq!(lines.next_line().await.unwrap().unwrap(), "c");
+    assert!(start.elapsed() >= per);
+    for rx in receivers {
+      assert!(matches!(rx.await, Ok(Ok(()))));
+    }
+
+    queue.close();
+  }
+
+  #[tokio::test]
+  async fn same_message_bypass_on_write() {
+    use super::super::SAME_MESSAGE_BYPASS;
+    use tokio::io::AsyncBufReadExt;
+
+    let queue = Queue::new(QueueConfig {
+      max_len: Some(2),
+      ..Default::default()
+    });
+    let (client, server) = tokio::io::duplex(1024);
+    let (_, stream) = tokio::io::split(Box::new(client) as crate::client::conn::Stream);
+    tokio::spawn(run(
+      queue.clone(),
+      stream,
+      Some(SameMessageTracker::default()),
+    ));
+    let privmsg = |tags: &str, text: &str| {
+      let (mut item, rx) = item(&format!("{tags}PRIVMSG #a :{text}\r\n"));
+      item.privmsg = Some(("#a".into(), text.into()));
+      (item, rx)
+    };
+    let mut lines = tokio::io::BufReader::new(server).lines();
+
+    // the bypass follows the order in which messages are written, not queued
+    let (low, _low) = privmsg("@client-nonce=low ", "hi");
+    let (high, _high) = privmsg("@client-nonce=high ", "hi");
+    queue.push("a", Priority::Low, low);
+    queue.push("a", Priority::High, high);
+    assert_eq!(
+      lines.next_line().await.unwrap().unwrap(),
+      "@client-nonce=high PRIVMSG #a :hi"
+    );
+    assert_eq!(
+      lines.next_line().await.unwrap().unwrap(),
+      format!("@client-nonce=low PRIVMSG #a :hi{SAME_MESSAGE_BYPASS}")
+    );
+
+    // rejected messages are never written, so they don't count
+    let (a, _a) = privmsg("", "yo");
+    let (b, _b) = privmsg("", "yo");
+    let (c, mut c_rx) = privmsg("", "yo");
+    queue.push("a", Priority::Normal, a);
+    queue.push("a", Priority::Normal, b);
+    queue.push("a", Priority::Normal, c);
+    assert!(matches!(c_rx.try_recv(), Ok(Err(SendError::QueueFull))));
+    assert_eq!(lines.next_line().await.unwrap().unwrap(), "PRIVMSG #a :yo");
+    assert_eq!(
+      lines.next_line().await.unwrap().unwrap(),
+      format!("PRIVMSG #a :yo{SAME_MESSAGE_BYPASS}")
+    );
+    let (d, _d) = privmsg("", "yo");
+    queue.push("a", Priority::Normal, d);
+    assert_eq!(lines.next_line().await.unwrap().unwrap(), "PRIVMSG #a :yo");
+
+    queue.close();
+  }
+}
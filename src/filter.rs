@@ -0,0 +1,720 @@
+//! ## Message filters
+//!
+//! A [`Filter`] is a predicate over messages, built from field comparisons combined with
+//! `and`, `or`, and `!`. Filters can be constructed in code:
+//!
+//! ```rust
+//! use tmi::filter::Filter;
+//!
+//! let filter = Filter::command("PRIVMSG")
+//!   .and(Filter::channel("#a").or(Filter::channel("#b")))
+//!   .and(Filter::badge("subscriber"))
+//!   .and(Filter::text(r"https?://").unwrap());
+//! ```
+//!
+//! Or parsed from an expression, for example one read from a config file:
+//!
+//! ```rust
+//! use tmi::filter::Filter;
+//!
+//! let filter: Filter = "command = PRIVMSG and channel in (#a, #b) and badge = subscriber and text ~ 'https?://'"
+//!   .parse()
+//!   .unwrap();
+//!
+//! let msg = tmi::IrcMessageRef::parse("@badges=subscriber/12 :a!a@a.tmi.twitch.tv PRIVMSG #b :https://twitch.tv").unwrap();
+//! assert!(filter.matches(&msg));
+//! ```
+//!
+//! Most predicates are evaluated against the raw tags and params of an [`IrcMessageRef`].
+//! Only [`Filter::typed`] predicates require parsing the message into a [`Message`],
+//! which happens at most once per call to [`Filter::matches`].
+//!
+//! ### Expressions
+//!
+//! An expression is a comparison of a field against a value:
+//!
+//! | Field      | Value                                                    |
+//! |------------|----------------------------------------------------------|
+//! | `command`  | the IRC command, e.g. `PRIVMSG`                          |
+//! | `channel`  | the channel, with or without the `#` prefix              |
+//! | `sender`   | the sender's login                                       |
+//! | `badge`    | a badge name such as `moderator`, or `name/version`      |
+//! | `text`     | the message text                                         |
+//! | `bits`     | the number of bits cheered, `0` if none                  |
+//! | `tag.NAME` | the value of the tag `NAME`, e.g. `tag.msg-param-sub-plan` |
+//!
+//! The operators are `=` and `!=` for exact comparisons (case-insensitive, except for
+//! `text` and tags), `~` for a regex match, `<`, `<=`, `>`, `>=` for numeric comparisons,
+//! and `in (a, b, ...)` for a list of values. A `tag.NAME` field on its own matches
+//! if the tag is present.
+//!
+//! Values are either bare words, or quoted with `"` or `'`, with `\` escaping the quote.
+//! Comparisons are combined with `and` (`&&`), `or` (`||`), `not` (`!`), and parentheses.
+//!
+//! ```text
+//! command = USERNOTICE and tag.msg-id in (sub, resub) and tag.msg-param-sub-plan > 1000
+//! ```
+
+use crate::irc::{IrcMessageRef, Tag};
+use crate::msg::{FromIrc, Message};
+use regex::Regex;
+use std::cell::OnceCell;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// A predicate over messages.
+///
+/// See the [module documentation][self].
+#[derive(Clone)]
+pub struct Filter(Node);
+
+#[derive(Clone)]
+enum Node {
+  Always,
+  Compare(Field, Op),
+  Typed(Arc<dyn Fn(&Message<'_>) -> bool + Send + Sync>),
+  And(Vec<Node>),
+  Or(Vec<Node>),
+  Not(Box<Node>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Field {
+  Command,
+  Channel,
+  Sender,
+  Badge,
+  Text,
+  Bits,
+  Tag(String),
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+  Present,
+  Eq(String),
+  Ne(String),
+  Matches(Regex),
+  Cmp(Cmp, i64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Cmp {
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+impl Filter {
+  /// Matches every message.
+  pub fn always() -> Self {
+    Self(Node::Always)
+  }
+
+  /// Matches messages with the given command, e.g. `PRIVMSG`.
+  pub fn command(command: impl Into<String>) -> Self {
+    Self(Node::Compare(Field::Command, Op::Eq(command.into())))
+  }
+
+  /// Matches messages sent to `channel`, with or without the `#` prefix.
+  pub fn channel(channel: impl Into<String>) -> Self {
+    Self(Node::Compare(Field::Channel, Op::Eq(channel.into())))
+  }
+
+  /// Matches messages sent by the user with the given `login`.
+  pub fn sender(login: impl Into<String>) -> Self {
+    Self(Node::Compare(Field::Sender, Op::Eq(login.into())))
+  }
+
+  /// Matches messages whose sender has the given badge.
+  ///
+  /// `badge` is either a name, such as `subscriber`, or a name and version, such as `subscriber/12`.
+  pub fn badge(badge: impl Into<String>) -> Self {
+    Self(Node::Compare(Field::Badge, Op::Eq(badge.into())))
+  }
+
+  /// Matches messages which have the tag `name`.
+  pub fn has_tag(name: impl Into<String>) -> Self {
+    Self(Node::Compare(Field::Tag(name.into()), Op::Present))
+  }
+
+  /// Matches messages where the tag `name` is equal to `value`.
+  pub fn tag(name: impl Into<String>, value: impl Into<String>) -> Self {
+    Self(Node::Compare(Field::Tag(name.into()), Op::Eq(value.into())))
+  }
+
+  /// Matches messages whose text matches the regex `pattern`.
+  pub fn text(pattern: &str) -> Result<Self, regex::Error> {
+    Ok(Self(Node::Compare(
+      Field::Text,
+      Op::Matches(Regex::new(pattern)?),
+    )))
+  }
+
+  /// Matches messages with at least `min` bits.
+  pub fn bits(min: u64) -> Self {
+    Self(Node::Compare(
+      Field::Bits,
+      Op::Cmp(Cmp::Ge, i64::try_from(min).unwrap_or(i64::MAX)),
+    ))
+  }
+
+  /// Matches messages for which `f` returns `true`.
+  ///
+  /// This parses the message into a [`Message`], and never matches messages which fail to parse.
+  pub fn typed<F>(f: F) -> Self
+  where
+    F: Fn(&Message<'_>) -> bool + Send + Sync + 'static,
+  {
+    Self(Node::Typed(Arc::new(f)))
+  }
+
+  /// Matches messages matched by both `self` and `other`.
+  pub fn and(self, other: Filter) -> Self {
+    Self(match (self.0, other.0) {
+      (Node::And(mut a), Node::And(b)) => {
+        a.extend(b);
+        Node::And(a)
+      }
+      (Node::And(mut a), b) => {
+        a.push(b);
+        Node::And(a)
+      }
+      (a, b) => Node::And(vec![a, b]),
+    })
+  }
+
+  /// Matches messages matched by either `self` or `other`.
+  pub fn or(self, other: Filter) -> Self {
+    Self(match (self.0, other.0) {
+      (Node::Or(mut a), Node::Or(b)) => {
+        a.extend(b);
+        Node::Or(a)
+      }
+      (Node::Or(mut a), b) => {
+        a.push(b);
+        Node::Or(a)
+      }
+      (a, b) => Node::Or(vec![a, b]),
+    })
+  }
+
+  /// Whether `message` matches this filter.
+  pub fn matches(&self, message: &IrcMessageRef<'_>) -> bool {
+    let typed = OnceCell::new();
+    self.0.eval(message, &typed)
+  }
+}
+
+/// Matches messages not matched by the filter.
+impl std::ops::Not for Filter {
+  type Output = Filter;
+
+  fn not(self) -> Self::Output {
+    Self(Node::Not(Box::new(self.0)))
+  }
+}
+
+impl Node {
+  fn eval<'src>(
+    &self,
+    message: &IrcMessageRef<'src>,
+    typed: &OnceCell<Option<Message<'src>>>,
+  ) -> bool {
+    match self {
+      Node::Always => true,
+      Node::Compare(field, op) => compare(field, op, message),
+      Node::Typed(f) => typed
+        .get_or_init(|| Message::from_irc(message.clone()).ok())
+        .as_ref()
+        .is_some_and(|typed| f(typed)),
+      Node::And(nodes) => nodes.iter().all(|node| node.eval(message, typed)),
+      Node::Or(nodes) => nodes.iter().any(|node| node.eval(message, typed)),
+      Node::Not(node) => !node.eval(message, typed),
+    }
+  }
+}
+
+fn compare(field: &Field, op: &Op, message: &IrcMessageRef<'_>) -> bool {
+  if let Field::Badge = field {
+    let badges = message.tag(Tag::Badges).unwrap_or_default();
+    let mut badges = badges.split(',').filter(|badge| !badge.is_empty());
+    let has_badge = |expected: &str| {
+      let expected = expected.to_ascii_lowercase();
+      move |badge: &str| match expected.contains('/') {
+        true => badge == expected,
+        false => badge.split('/').next() == Some(&expected),
+      }
+    };
+    return match op {
+      Op::Present => badges.next().is_some(),
+      Op::Eq(expected) => badges.any(has_badge(expected)),
+      Op::Ne(expected) => !badges.any(has_badge(expected)),
+      Op::Matches(regex) => badges.any(|badge| regex.is_match(badge)),
+      Op::Cmp(..) => false,
+    };
+  }
+
+  let value = match field {
+    Field::Command => Some(message.command().as_str()),
+    Field::Channel => message.channel(),
+    Field::Sender => message
+      .tag(Tag::Login)
+      .or_else(|| message.prefix().and_then(|prefix| prefix.nick)),
+    Field::Text => message.text(),
+    Field::Bits => Some(message.tag(Tag::Bits).unwrap_or("0")),
+    Field::Tag(name) => message.tag(name.as_str()),
+    Field::Badge => unreachable!(),
+  };
+  let value = match field {
+    Field::Tag(_) => value.map(crate::maybe_unescape),
+    _ => value.map(Into::into),
+  };
+
+  let eq = |value: &str, expected: &str| match field {
+    Field::Channel => value
+      .trim_start_matches('#')
+      .eq_ignore_ascii_case(expected.trim_start_matches('#')),
+    Field::Command | Field::Sender => value.eq_ignore_ascii_case(expected),
+    _ => value == expected,
+  };
+  match op {
+    Op::Present => value.is_some(),
+    Op::Eq(expected) => value.is_some_and(|value| eq(&value, expected)),
+    Op::Ne(expected) => !value.is_some_and(|value| eq(&value, expected)),
+    Op::Matches(regex) => value.is_some_and(|value| regex.is_match(&value)),
+    Op::Cmp(cmp, expected) => {
+      let Some(value) = value.and_then(|value| value.parse::<i64>().ok()) else {
+        return false;
+      };
+      match cmp {
+        Cmp::Lt => value < *expected,
+        Cmp::Le => value <= *expected,
+        Cmp::Gt => value > *expected,
+        Cmp::Ge => value >= *expected,
+      }
+    }
+  }
+}
+
+impl Debug for Filter {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    self.0.fmt(f)
+  }
+}
+
+impl Debug for Node {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Node::Always => f.write_str("Always"),
+      Node::Compare(field, op) => f.debug_tuple("Compare").field(field).field(op).finish(),
+      Node::Typed(_) => f.write_str("Typed(..)"),
+      Node::And(nodes) => f.debug_tuple("And").field(nodes).finish(),
+      Node::Or(nodes) => f.debug_tuple("Or").field(nodes).finish(),
+      Node::Not(node) => f.debug_tuple("Not").field(node).finish(),
+    }
+  }
+}
+
+static_assert_send!(Filter);
+static_assert_sync!(Filter);
+
+impl FromStr for Filter {
+  type Err = FilterError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut parser = Parser::new(s)?;
+    let node = parser.or()?;
+    match parser.peek() {
+      None => Ok(Filter(node)),
+      Some((offset, _)) => Err(FilterError::new(offset, "expected end of expression")),
+    }
+  }
+}
+
+/// Failed to parse a filter expression.
+#[derive(Clone, Debug)]
+pub struct FilterError {
+  offset: usize,
+  message: String,
+}
+
+impl FilterError {
+  fn new(offset: usize, message: impl Into<String>) -> Self {
+    Self {
+      offset,
+      message: message.into(),
+    }
+  }
+
+  /// Byte offset in the expression at which the error occurred.
+  #[inline]
+  pub fn offset(&self) -> usize {
+    self.offset
+  }
+}
+
+impl Display for FilterError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "failed to parse filter at offset {}: {}",
+      self.offset, self.message
+    )
+  }
+}
+
+impl std::error::Error for FilterError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+  Word(String),
+  Str(String),
+  Op(&'static str),
+  LParen,
+  RParen,
+  Comma,
+}
+
+const OPS: &[&str] = &["&&", "||", "!=", "<=", ">=", "=", "~", "<", ">", "!"];
+
+fn tokenize(src: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+  let mut tokens = Vec::new();
+  let mut chars = src.char_indices().peekable();
+  while let Some(&(offset, c)) = chars.peek() {
+    let rest = &src[offset..];
+    if c.is_whitespace() {
+      chars.next();
+      continue;
+    }
+    let token = match c {
+      '(' => Token::LParen,
+      ')' => Token::RParen,
+      ',' => Token::Comma,
+      '"' | '\'' => {
+        chars.next();
+        let mut value = String::new();
+        loop {
+          match chars.next() {
+            Some((_, '\\')) => match chars.next() {
+              Some((_, c)) => value.push(c),
+              None => return Err(FilterError::new(offset, "unterminated string")),
+            },
+            Some((_, end)) if end == c => break,
+            Some((_, c)) => value.push(c),
+            None => return Err(FilterError::new(offset, "unterminated string")),
+          }
+        }
+        tokens.push((offset, Token::Str(value)));
+        continue;
+      }
+      _ => {
+        if let Some(op) = OPS.iter().find(|op| rest.starts_with(**op)) {
+          for _ in 0..op.len() {
+            chars.next();
+          }
+          tokens.push((offset, Token::Op(op)));
+          continue;
+        }
+        let len = rest
+          .find(|c: char| c.is_whitespace() || "()=,!~<>&|\"'".contains(c))
+          .unwrap_or(rest.len());
+        if len == 0 {
+          return Err(FilterError::new(offset, format!("unexpected `{c}`")));
+        }
+        while chars.peek().is_some_and(|(i, _)| *i < offset + len) {
+          chars.next();
+        }
+        tokens.push((offset, Token::Word(rest[..len].to_owned())));
+        continue;
+      }
+    };
+    chars.next();
+    tokens.push((offset, token));
+  }
+  Ok(tokens)
+}
+
+/// Maximum nesting of parentheses and `not`, which bounds the recursion of parsing and matching.
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+  tokens: std::iter::Peekable<std::vec::IntoIter<(usize, Token)>>,
+  end: usize,
+  depth: usize,
+}
+
+impl Parser {
+  fn new(src: &str) -> Result<Self, FilterError> {
+    Ok(Self {
+      tokens: tokenize(src)?.into_iter().peekable(),
+      end: src.len(),
+      depth: 0,
+    })
+  }
+
+  fn peek(&mut self) -> Option<(usize, &Token)> {
+    self.tokens.peek().map(|(offset, token)| (*offset, token))
+  }
+
+  fn next(&mut self, expected: &str) -> Result<(usize, Token), FilterError> {
+    self
+      .tokens
+      .next()
+      .ok_or_else(|| FilterError::new(self.end, format!("expected {expected}")))
+  }
+
+  fn eat_keyword(&mut self, keyword: &str, op: &str) -> bool {
+    let found = match self.peek() {
+      Some((_, Token::Word(word))) => word.eq_ignore_ascii_case(keyword),
+      Some((_, Token::Op(found))) => *found == op,
+      _ => false,
+    };
+    if found {
+      self.tokens.next();
+    }
+    found
+  }
+
+  fn or(&mut self) -> Result<Node, FilterError> {
+    let mut nodes = vec![self.and()?];
+    while self.eat_keyword("or", "||") {
+      nodes.push(self.and()?);
+    }
+    Ok(match nodes.len() {
+      1 => nodes.pop().unwrap(),
+      _ => Node::Or(nodes),
+    })
+  }
+
+  fn and(&mut self) -> Result<Node, FilterError> {
+    let mut nodes = vec![self.unary()?];
+    while self.eat_keyword("and", "&&") {
+      nodes.push(self.unary()?);
+    }
+    Ok(match nodes.len() {
+      1 => nodes.pop().unwrap(),
+      _ => Node::And(nodes),
+    })
+  }
+
+  fn unary(&mut self) -> Result<Node, FilterError> {
+    let end = self.end;
+    let offset = self.peek().map_or(end, |(offset, _)| offset);
+    if self.eat_keyword("not", "!") {
+      self.descend(offset)?;
+      let node = self.unary()?;
+      self.depth -= 1;
+      return Ok(Node::Not(Box::new(node)));
+    }
+    if let Some((_, Token::LParen)) = self.peek() {
+      self.tokens.next();
+      self.descend(offset)?;
+      let node = self.or()?;
+      self.depth -= 1;
+      return match self.next("`)`")? {
+        (_, Token::RParen) => Ok(node),
+        (offset, _) => Err(FilterError::new(offset, "expected `)`")),
+      };
+    }
+    self.comparison()
+  }
+
+  fn descend(&mut self, offset: usize) -> Result<(), FilterError> {
+    if self.depth == MAX_DEPTH {
+      return Err(FilterError::new(
+        offset,
+        format!("expression is nested more than {MAX_DEPTH} levels deep"),
+      ));
+    }
+    self.depth += 1;
+    Ok(())
+  }
+
+  fn comparison(&mut self) -> Result<Node, FilterError> {
+    let (offset, field) = match self.next("a field")? {
+      (offset, Token::Word(word)) => (offset, word),
+      (offset, _) => return Err(FilterError::new(offset, "expected a field")),
+    };
+    let field = match field.to_ascii_lowercase().as_str() {
+      "command" => Field::Command,
+      "channel" => Field::Channel,
+      "sender" => Field::Sender,
+      "badge" => Field::Badge,
+      "text" => Field::Text,
+      "bits" => Field::Bits,
+      _ => match field.strip_prefix("tag.") {
+        Some(name) if !name.is_empty() => Field::Tag(name.to_owned()),
+        _ => return Err(FilterError::new(offset, format!("unknown field `{field}`"))),
+      },
+    };
+
+    if self.eat_keyword("in", "") {
+      return self.list(field);
+    }
+    let op = match self.peek() {
+      Some((_, Token::Op(op))) if !matches!(*op, "&&" | "||" | "!") => *op,
+      _ if matches!(field, Field::Tag(_)) => return Ok(Node::Compare(field, Op::Present)),
+      _ => return Err(FilterError::new(offset, "expected an operator")),
+    };
+    let (op_offset, _) = self.tokens.next().unwrap();
+    let (value_offset, value) = self.value()?;
+    let op = match op {
+      "=" => Op::Eq(value),
+      "!=" => Op::Ne(value),
+      "~" => {
+        Op::Matches(Regex::new(&value).map_err(|e| FilterError::new(value_offset, e.to_string()))?)
+      }
+      _ => {
+        let cmp = match op {
+          "<" => Cmp::Lt,
+          "<=" => Cmp::Le,
+          ">" => Cmp::Gt,
+          _ => Cmp::Ge,
+        };
+        if field == Field::Badge {
+          return Err(FilterError::new(op_offset, "badges can not be compared"));
+        }
+        let value = value
+          .parse()
+          .map_err(|_| FilterError::new(value_offset, "expected a number"))?;
+        Op::Cmp(cmp, value)
+      }
+    };
+    Ok(Node::Compare(field, op))
+  }
+
+  fn list(&mut self, field: Field) -> Result<Node, FilterError> {
+    match self.next("`(`")? {
+      (_, Token::LParen) => {}
+      (offset, _) => return Err(FilterError::new(offset, "expected `(`")),
+    }
+    let mut nodes = Vec::new();
+    loop {
+      let (_, value) = self.value()?;
+      nodes.push(Node::Compare(field.clone(), Op::Eq(value)));
+      match self.next("`,` or `)`")? {
+        (_, Token::Comma) => {}
+        (_, Token::RParen) => break,
+        (offset, _) => return Err(FilterError::new(offset, "expected `,` or `)`")),
+      }
+    }
+    Ok(Node::Or(nodes))
+  }
+
+  fn value(&mut self) -> Result<(usize, String), FilterError> {
+    match self.next("a value")? {
+      (offset, Token::Word(value) | Token::Str(value)) => Ok((offset, value)),
+      (offset, _) => Err(FilterError::new(offset, "expected a value")),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const PRIVMSG: &str = "@badge-info=subscriber/22;badges=moderator/1,subscriber/12;bits=100;color=#19E6E6;display-name=randers;emotes=;flags=;id=d831d848-b7c7-4559-ae3a-2cb88f4dbfed;mod=1;room-id=11148817;subscriber=1;tmi-sent-ts=1594555275886;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :check https://example.com";
+  const USERNOTICE: &str = "@badge-info=subscriber/3;badges=subscriber/0;color=#0000FF;display-name=SevenTest1;emotes=;id=37feed0f-b9c7-4c3a-b475-21c6c6d21c3d;login=seventest1;mod=0;msg-id=resub;msg-param-cumulative-months=3;msg-param-streak-months=0;msg-param-should-share-streak=0;msg-param-sub-plan-name=Channel\\sSubscription;msg-param-sub-plan=2000;room-id=11148817;subscriber=1;system-msg=SevenTest1\\ssubscribed.;tmi-sent-ts=1594583782376;user-id=131225357;user-type= :tmi.twitch.tv USERNOTICE #pajlada :HeyGuys";
+
+  fn matches(filter: &str, line: &str) -> bool {
+    let filter = filter
+      .parse::<Filter>()
+      .unwrap_or_else(|e| panic!("{filter}: {e}"));
+    filter.matches(&IrcMessageRef::parse(line).unwrap())
+  }
+
+  #[test]
+  fn parse_and_match() {
+    let cases = [
+      ("command = privmsg", true, false),
+      ("channel in (forsen, Pajlada)", true, true),
+      ("sender = RANDERS", true, false),
+      ("sender = seventest1", false, true),
+      ("badge = moderator && badge = subscriber/12", true, false),
+      ("badge != moderator", false, true),
+      ("text ~ 'https?://' and not text ~ \"\\\"\"", true, false),
+      ("bits >= 100", true, false),
+      ("bits > 0 || tag.msg-param-sub-plan > 1000", true, true),
+      (
+        "tag.msg-param-sub-plan-name = 'Channel Subscription'",
+        false,
+        true,
+      ),
+      ("tag.msg-id", false, true),
+      ("!(command = PRIVMSG or command = USERNOTICE)", false, false),
+      (
+        "command = USERNOTICE and tag.msg-id in (sub, resub) and tag.msg-param-sub-plan > 1000",
+        false,
+        true,
+      ),
+    ];
+    for (filter, privmsg, usernotice) in cases {
+      assert_eq!(matches(filter, PRIVMSG), privmsg, "{filter}");
+      assert_eq!(matches(filter, USERNOTICE), usernotice, "{filter}");
+    }
+  }
+
+  #[test]
+  fn parse_errors() {
+    for (filter, offset) in [
+      ("", 0),
+      ("command", 0),
+      ("user = a", 0),
+      ("command = a and", 15),
+      ("(command = a", 12),
+      ("bits > many", 7),
+      ("badge < 1", 6),
+      ("text ~ '('", 7),
+      ("channel in (a b)", 14),
+      ("command = 'a", 10),
+    ] {
+      let e = filter.parse::<Filter>().unwrap_err();
+      assert_eq!(e.offset(), offset, "{filter}: {e}");
+    }
+  }
+
+  #[test]
+  fn nesting_limit() {
+    let nested = |depth: usize| {
+      format!(
+        "{}command = privmsg{}",
+        "(".repeat(depth),
+        ")".repeat(depth)
+      )
+    };
+    assert!(matches(&nested(MAX_DEPTH), PRIVMSG));
+    let e = nested(MAX_DEPTH + 1).parse::<Filter>().unwrap_err();
+    assert_eq!(e.offset(), MAX_DEPTH);
+
+    let e = "!".repeat(100_000).parse::<Filter>().unwrap_err();
+    assert_eq!(e.offset(), MAX_DEPTH);
+    let e = "(".repeat(100_000).parse::<Filter>().unwrap_err();
+    assert_eq!(e.offset(), MAX_DEPTH);
+  }
+
+  #[test]
+  fn combinators() {
+    let msg = IrcMessageRef::parse(PRIVMSG).unwrap();
+    let filter = Filter::command("PRIVMSG")
+      .and(Filter::channel("#a").or(Filter::channel("#pajlada")))
+      .and(Filter::badge("subscriber"))
+      .and(Filter::text(r"https?://").unwrap())
+      .and(Filter::bits(100));
+    assert!(filter.matches(&msg));
+    assert!(!(!filter.clone()).matches(&msg));
+    assert!(Filter::always().matches(&msg));
+
+    let typed = Filter::has_tag("bits").and(Filter::typed(|msg| match msg {
+      Message::Privmsg(msg) => msg.sender().name() == "randers",
+      _ => false,
+    }));
+    assert!(typed.matches(&msg));
+    assert!(!typed.matches(&IrcMessageRef::parse(USERNOTICE).unwrap()));
+    assert!(Filter::tag("msg-param-sub-plan", "2000")
+      .and(Filter::sender("seventest1"))
+      .matches(&IrcMessageRef::parse(USERNOTICE).unwrap()));
+  }
+}
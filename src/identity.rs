@@ -0,0 +1,264 @@
+//! ## Identity cache
+//!
+//! Most messages identify their sender with a full [`User`], but some only carry a login,
+//! such as [`ClearMsg::sender`][crate::ClearMsg::sender], or a user ID, such as
+//! [`ClearChat`][crate::ClearChat] with the `target-user-id` tag.
+//!
+//! [`IdentityCache`] learns the ID, login, and display name of users from typed messages,
+//! and resolves one to the others. It holds a bounded number of users, evicting the one
+//! which was least recently seen in a message.
+//!
+//! ```rust,no_run
+//! # async fn run(mut client: tmi::Client) -> anyhow::Result<()> {
+//! use tmi::identity::IdentityCache;
+//!
+//! let mut cache = IdentityCache::new(10_000);
+//! loop {
+//!   let message = client.recv().await?;
+//!   let message = message.as_typed()?;
+//!   cache.learn(&message);
+//!   if let tmi::Message::ClearChat(msg) = &message {
+//!     if let tmi::Action::Ban(ban) = msg.action() {
+//!       if let Some(user) = cache.by_id(ban.id()) {
+//!         println!("{} was banned", user.name());
+//!       }
+//!     }
+//!   }
+//! }
+//! # }
+//! ```
+
+use crate::msg::{Action, Message, User};
+use std::collections::{BTreeMap, HashMap};
+
+/// A user known to an [`IdentityCache`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+  id: String,
+  login: String,
+  name: Option<String>,
+}
+
+impl Identity {
+  /// ID of the user.
+  #[inline]
+  pub fn id(&self) -> &str {
+    &self.id
+  }
+
+  /// Login of the user.
+  #[inline]
+  pub fn login(&self) -> &str {
+    &self.login
+  }
+
+  /// Display name of the user, falling back to the login if it has not been seen yet.
+  #[inline]
+  pub fn name(&self) -> &str {
+    self.name.as_deref().unwrap_or(&self.login)
+  }
+}
+
+#[derive(Debug)]
+struct Slot {
+  identity: Identity,
+  /// Position of this user in [`IdentityCache::recency`].
+  tick: u64,
+}
+
+/// Bounded least-recently-seen cache of user identities.
+///
+/// Recency is only updated when a user is seen in a message or [inserted][IdentityCache::insert],
+/// lookups do not count as a use.
+///
+/// See the [module documentation][self].
+#[derive(Debug)]
+pub struct IdentityCache {
+  capacity: usize,
+  by_id: HashMap<String, Slot>,
+  by_login: HashMap<String, String>,
+  recency: BTreeMap<u64, String>,
+  tick: u64,
+}
+
+impl IdentityCache {
+  /// Create a cache holding up to `capacity` users.
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      by_id: HashMap::new(),
+      by_login: HashMap::new(),
+      recency: BTreeMap::new(),
+      tick: 0,
+    }
+  }
+
+  /// Learn the identities of all users present in `message`.
+  pub fn learn(&mut self, message: &Message<'_>) {
+    match message {
+      Message::Privmsg(msg) => {
+        if let Some(reply) = msg.reply_to() {
+          self.learn_user(reply.sender());
+        }
+        self.learn_user(msg.sender());
+      }
+      Message::UserNotice(msg) => {
+        if let Some(sender) = msg.sender() {
+          self.learn_user(sender);
+        }
+      }
+      Message::Whisper(msg) => self.learn_user(&msg.sender()),
+      Message::ClearChat(msg) => match msg.action() {
+        Action::Ban(ban) => self.insert(ban.id(), ban.user(), None),
+        Action::TimeOut(timeout) => self.insert(timeout.id(), timeout.user(), None),
+        Action::Clear => {}
+      },
+      _ => {}
+    }
+  }
+
+  /// Record a user, marking them as the most recently seen.
+  ///
+  /// If `name` is [`None`], a previously learned display name is kept.
+  pub fn insert(&mut self, id: &str, login: &str, name: Option<&str>) {
+    if self.capacity == 0 {
+      return;
+    }
+    let login = login.to_ascii_lowercase();
+
+    // The login may have belonged to another account, or this account may have been renamed.
+    if let Some(previous) = self.by_login.get(&login).filter(|prev| *prev != id) {
+      let previous = previous.clone();
+      self.remove(&previous);
+    }
+
+    self.tick += 1;
+    let tick = self.tick;
+    if let Some(slot) = self.by_id.get_mut(id) {
+      self.recency.remove(&slot.tick);
+      slot.tick = tick;
+      if slot.identity.login != login {
+        self.by_login.remove(&slot.identity.login);
+        slot.identity.login.clone_from(&login);
+      }
+      if let Some(name) = name {
+        slot.identity.name = Some(name.to_owned());
+      }
+    } else {
+      if self.by_id.len() == self.capacity {
+        self.evict();
+      }
+      let identity = Identity {
+        id: id.to_owned(),
+        login: login.clone(),
+        name: name.map(str::to_owned),
+      };
+      self.by_id.insert(id.to_owned(), Slot { identity, tick });
+    }
+    self.recency.insert(tick, id.to_owned());
+    self.by_login.insert(login, id.to_owned());
+  }
+
+  /// Get the user with the given `id`.
+  pub fn by_id(&self, id: &str) -> Option<&Identity> {
+    self.by_id.get(id).map(|slot| &slot.identity)
+  }
+
+  /// Get the user with the given `login`.
+  pub fn by_login(&self, login: &str) -> Option<&Identity> {
+    let id = match self.by_login.get(login) {
+      Some(id) => id,
+      None => self.by_login.get(&login.to_ascii_lowercase())?,
+    };
+    self.by_id(id)
+  }
+
+  /// Forget the user with the given `id`.
+  pub fn remove(&mut self, id: &str) -> Option<Identity> {
+    let slot = self.by_id.remove(id)?;
+    self.recency.remove(&slot.tick);
+    self.by_login.remove(&slot.identity.login);
+    Some(slot.identity)
+  }
+
+  /// Maximum number of users held by the cache.
+  #[inline]
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  /// Number of users currently in the cache.
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.by_id.len()
+  }
+
+  /// Whether the cache holds no users.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.by_id.is_empty()
+  }
+
+  fn learn_user(&mut self, user: &User<'_>) {
+    self.insert(user.id(), user.login(), Some(&user.name()));
+  }
+
+  fn evict(&mut self) {
+    if let Some((_, id)) = self.recency.pop_first() {
+      if let Some(slot) = self.by_id.remove(&id) {
+        self.by_login.remove(&slot.identity.login);
+      }
+    }
+  }
+}
+
+static_assert_send!(IdentityCache);
+static_assert_sync!(IdentityCache);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn learn(cache: &mut IdentityCache, line: &str) {
+    cache.learn(&Message::parse(line).unwrap());
+  }
+
+  #[test]
+  fn learn_and_resolve() {
+    let mut cache = IdentityCache::new(10);
+    learn(&mut cache, "@badge-info=;badges=;client-nonce=cd56193132f934ac71b4d5ac488d4bd6;color=;display-name=LeftSwing;emotes=;first-msg=0;flags=;id=5b4f63a9-776f-4fce-bf3c-d9707f52e32d;mod=0;reply-parent-display-name=Retoon;reply-parent-msg-body=hello;reply-parent-msg-id=6b13e51b-7ecb-43b5-ba5b-2bb5288df696;reply-parent-user-id=37940952;reply-parent-user-login=retoon;reply-thread-parent-msg-id=6b13e51b-7ecb-43b5-ba5b-2bb5288df696;reply-thread-parent-user-login=retoon;returning-chatter=0;room-id=37940952;subscriber=0;tmi-sent-ts=1673925983585;turbo=0;user-id=133651738;user-type= :leftswing!leftswing@leftswing.tmi.twitch.tv PRIVMSG #retoon :@Retoon yes");
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.by_id("37940952").unwrap().name(), "Retoon");
+    assert_eq!(cache.by_login("LeftSwing").unwrap().id(), "133651738");
+
+    learn(&mut cache, "@ban-duration=600;room-id=37940952;target-user-id=133651738;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #retoon :leftswing");
+    assert_eq!(cache.by_id("133651738").unwrap().name(), "LeftSwing");
+
+    learn(&mut cache, "@room-id=37940952;target-user-id=1;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #retoon :someone");
+    assert_eq!(cache.by_id("1").unwrap().name(), "someone");
+    assert_eq!(cache.len(), 3);
+  }
+
+  #[test]
+  fn renames_and_eviction() {
+    let mut cache = IdentityCache::new(2);
+    cache.insert("1", "a", Some("A"));
+    cache.insert("1", "b", None);
+    assert!(cache.by_login("a").is_none());
+    assert_eq!(cache.by_login("b").unwrap().name(), "A");
+
+    cache.insert("2", "a", None);
+    cache.insert("1", "b", None);
+    cache.insert("3", "c", None);
+    assert!(cache.by_id("2").is_none());
+    assert!(cache.by_login("a").is_none());
+    assert_eq!(cache.len(), 2);
+
+    // the login `c` now belongs to a different account
+    cache.insert("4", "c", None);
+    assert!(cache.by_id("3").is_none());
+    assert_eq!(cache.by_login("c").unwrap().id(), "4");
+    assert_eq!(cache.remove("4").unwrap().login(), "c");
+    assert_eq!(cache.len(), 1);
+  }
+}
@@ -179,6 +179,7 @@ impl<'src> Debug for IrcMessageRef<'src> {
 /// A base IRC message.
 ///
 /// This variants owns the input message.
+#[derive(Clone)]
 pub struct IrcMessage {
   src: String,
   parts: IrcMessageParts,
@@ -169,6 +169,12 @@ tags_def! {
   b"msg-param-anon-gift"; "msg-param-anon-gift" = MsgParamAnonGift,
   b"custom-reward-id"; "custom-reward-id" = CustomRewardId,
 
+  /// Set on messages served by a recent-messages service, rather than received live.
+  b"historical"; "historical" = Historical,
+
+  /// Time at which a recent-messages service received the message, in milliseconds.
+  b"rm-received-ts"; "rm-received-ts" = RmReceivedTs,
+
   /// The value of the Hype Chat sent by the user.
   b"pinned-chat-paid-amount"; "pinned-chat-paid-amount" = PinnedChatPaidAmount,
 
@@ -69,6 +69,8 @@ impl Vector {
   ///
   /// `data[offset..].len()` must be greater than 16 bytes.
   /// The data must be 16-byte aligned.
+  // `usize::is_multiple_of` requires Rust 1.87.
+  #[allow(clippy::manual_is_multiple_of)]
   #[inline(always)]
   pub fn load_aligned(data: &[u8], offset: usize) -> Self {
     unsafe {
@@ -35,6 +35,8 @@ impl Vector {
   ///
   /// `data[offset..].len()` must be greater than 32 bytes.
   /// The data must be 32-byte aligned.
+  // `usize::is_multiple_of` requires Rust 1.87.
+  #[allow(clippy::manual_is_multiple_of)]
   #[inline(always)]
   pub fn load_aligned(data: &[u8], offset: usize) -> Self {
     unsafe {
@@ -66,7 +68,7 @@ impl Vector {
   #[inline(always)]
   pub fn movemask(self) -> Mask {
     unsafe {
-      let value = std::mem::transmute::<i32, u32>(_mm256_movemask_epi8(self.0));
+      let value = _mm256_movemask_epi8(self.0) as u32;
       Mask(value)
     }
   }
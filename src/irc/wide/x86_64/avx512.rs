@@ -35,6 +35,8 @@ impl Vector {
   ///
   /// `data[offset..].len()` must be greater than 64 bytes.
   /// The data must be 64-byte aligned.
+  // `usize::is_multiple_of` requires Rust 1.87.
+  #[allow(clippy::manual_is_multiple_of)]
   #[inline(always)]
   pub fn load_aligned(data: &[u8], offset: usize) -> Self {
     unsafe {
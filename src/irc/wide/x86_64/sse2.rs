@@ -33,6 +33,8 @@ impl Vector {
   ///
   /// `data[offset..].len()` must be greater than 16 bytes.
   /// The data must be 16-byte aligned.
+  // `usize::is_multiple_of` requires Rust 1.87.
+  #[allow(clippy::manual_is_multiple_of)]
   #[inline(always)]
   pub fn load_aligned(data: &[u8], offset: usize) -> Self {
     unsafe {
@@ -63,7 +65,7 @@ impl Vector {
   #[inline(always)]
   pub fn movemask(self) -> Mask {
     unsafe {
-      let value = std::mem::transmute::<i32, u32>(_mm_movemask_epi8(self.0));
+      let value = _mm_movemask_epi8(self.0) as u32;
       Mask(value)
     }
   }
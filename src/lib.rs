@@ -29,11 +29,35 @@ pub mod client;
 #[cfg(feature = "client")]
 pub use client::{Client, Credentials};
 
+#[cfg(feature = "testing")]
+pub mod testing;
+
 #[cfg(feature = "message-types")]
 pub mod msg;
 #[cfg(feature = "message-types")]
 pub use msg::*;
 
+#[cfg(feature = "message-types")]
+pub mod buffer;
+
+#[cfg(feature = "filter")]
+pub mod filter;
+
+#[cfg(feature = "message-types")]
+pub mod identity;
+
+#[cfg(feature = "message-types")]
+pub mod logs;
+
+#[cfg(feature = "message-types")]
+pub mod moderation;
+
+#[cfg(feature = "message-types")]
+pub mod presence;
+
+#[cfg(all(feature = "message-types", feature = "serde"))]
+pub mod recent;
+
 pub mod irc;
 pub use irc::*;
 
@@ -0,0 +1,637 @@
+//! ## Chat logs
+//!
+//! [`LogWriter`] appends chat messages to log files, and [`LogReader`] parses them back.
+//!
+//! Two formats are supported, see [`Format`]:
+//! - [`Format::Text`]: the plain-text format used by community log viewers such as
+//!   [justlog](https://github.com/gempir/justlog), one `[timestamp] #channel user: text` per line.
+//! - [`Format::Jsonl`]: JSON Lines, with the raw IRC message and the typed message.
+//!   Requires the `serde` feature.
+//!
+//! Only `PRIVMSG`, `USERNOTICE` and `CLEARCHAT` messages are logged.
+//!
+//! ```rust,no_run
+//! # fn run(messages: Vec<tmi::OwnedMessage>) -> Result<(), tmi::logs::LogError> {
+//! use tmi::logs::{Format, LogReader, LogWriter, Rotation};
+//!
+//! let mut writer = LogWriter::new("logs", Format::Text, Rotation::ChannelAndDay);
+//! for message in &messages {
+//!   writer.write(message)?;
+//! }
+//! writer.flush()?;
+//!
+//! for message in LogReader::open("logs/forsen/2024-01-01.txt", Format::Text)? {
+//!   println!("{:?}", message?.typed());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::irc::IrcMessage;
+use crate::msg::{Action, ClearChat, Message, OwnedMessage, Privmsg, UserNotice};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use std::collections::HashMap;
+use std::fmt::{Display, Write as _};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const TEXT_TIMESTAMP: &str = "%Y-%m-%d %H:%M:%S";
+
+/// The format of a log file.
+///
+/// Some formats depend on crate features, so this may gain variants without a major version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+  /// `[2006-01-02 15:04:05] #channel user: text`, with timestamps in UTC.
+  ///
+  /// Some information is lost, so messages read back from this format
+  /// only have a timestamp, channel, sender login and text.
+  Text,
+
+  /// One JSON object per line, with the fields
+  /// `timestamp`, `channel`, `kind`, `raw`, and `message`.
+  #[cfg(feature = "serde")]
+  Jsonl,
+}
+
+impl Format {
+  /// The file extension used by this format.
+  pub fn extension(&self) -> &'static str {
+    match self {
+      Format::Text => "txt",
+      #[cfg(feature = "serde")]
+      Format::Jsonl => "jsonl",
+    }
+  }
+}
+
+/// How messages are split across log files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+  /// One file per channel: `{dir}/{channel}.{ext}`.
+  Channel,
+
+  /// One file per day: `{dir}/{yyyy-mm-dd}.{ext}`.
+  Day,
+
+  /// One file per channel per day: `{dir}/{channel}/{yyyy-mm-dd}.{ext}`.
+  ChannelAndDay,
+}
+
+/// The default for [`LogWriter::with_max_open_files`].
+pub const DEFAULT_MAX_OPEN_FILES: usize = 64;
+
+/// Appends messages to log files in a directory.
+///
+/// Files are opened in append mode, and kept open until the next file for the same
+/// channel (or day, with [`Rotation::Day`]) is opened. Once the
+/// [limit][LogWriter::with_max_open_files] of open files is reached, the least recently
+/// written one is closed to make room for another.
+pub struct LogWriter {
+  dir: PathBuf,
+  format: Format,
+  rotation: Rotation,
+  max_open_files: usize,
+  files: HashMap<String, OpenFile>,
+  tick: u64,
+}
+
+struct OpenFile {
+  path: PathBuf,
+  writer: BufWriter<File>,
+  last_used: u64,
+}
+
+impl LogWriter {
+  /// Create a writer which logs to files in `dir`.
+  pub fn new(dir: impl Into<PathBuf>, format: Format, rotation: Rotation) -> Self {
+    Self {
+      dir: dir.into(),
+      format,
+      rotation,
+      max_open_files: DEFAULT_MAX_OPEN_FILES,
+      files: HashMap::new(),
+      tick: 0,
+    }
+  }
+
+  /// Keep at most `max` files open at once.
+  ///
+  /// Panics if `max` is zero.
+  pub fn with_max_open_files(mut self, max: usize) -> Self {
+    assert!(max > 0, "max_open_files must be greater than zero");
+    self.max_open_files = max;
+    self
+  }
+
+  /// Append `message` to the log file it belongs to.
+  ///
+  /// Returns `false` if the message was not logged, because it is not
+  /// a `PRIVMSG`, `USERNOTICE`, or `CLEARCHAT`.
+  pub fn write(&mut self, message: &OwnedMessage) -> Result<bool, LogError> {
+    let Some((channel, timestamp)) = channel_and_timestamp(message.typed()) else {
+      return Ok(false);
+    };
+    let line = match self.format {
+      Format::Text => match text_line(message.typed()) {
+        Some(line) => line,
+        None => return Ok(false),
+      },
+      #[cfg(feature = "serde")]
+      Format::Jsonl => json::line(message, channel, timestamp)?,
+    };
+
+    let file = self.file(channel, timestamp)?;
+    writeln!(file, "{line}")?;
+    Ok(true)
+  }
+
+  /// Flush all open files.
+  pub fn flush(&mut self) -> io::Result<()> {
+    for file in self.files.values_mut() {
+      file.writer.flush()?;
+    }
+    Ok(())
+  }
+
+  /// The path of the log file for `channel` at `timestamp`.
+  pub fn path(&self, channel: &str, timestamp: DateTime<Utc>) -> PathBuf {
+    let channel = file_name(channel);
+    let date = timestamp.format("%Y-%m-%d");
+    let ext = self.format.extension();
+    match self.rotation {
+      Rotation::Channel => self.dir.join(format!("{channel}.{ext}")),
+      Rotation::Day => self.dir.join(format!("{date}.{ext}")),
+      Rotation::ChannelAndDay => self.dir.join(channel).join(format!("{date}.{ext}")),
+    }
+  }
+
+  fn file(&mut self, channel: &str, timestamp: DateTime<Utc>) -> io::Result<&mut BufWriter<File>> {
+    let path = self.path(channel, timestamp);
+    let key = match self.rotation {
+      Rotation::Day => String::new(),
+      Rotation::Channel | Rotation::ChannelAndDay => file_name(channel),
+    };
+    self.tick += 1;
+
+    let is_open = matches!(self.files.get(&key), Some(open) if open.path == path);
+    if !is_open {
+      if let Some(mut previous) = self.files.remove(&key) {
+        previous.writer.flush()?;
+      } else if self.files.len() >= self.max_open_files {
+        self.close_least_recent()?;
+      }
+      if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+      }
+      let file = OpenOptions::new().create(true).append(true).open(&path)?;
+      let file = OpenFile {
+        path,
+        writer: BufWriter::new(file),
+        last_used: 0,
+      };
+      self.files.insert(key.clone(), file);
+    }
+    let file = self.files.get_mut(&key).unwrap();
+    file.last_used = self.tick;
+    Ok(&mut file.writer)
+  }
+
+  fn close_least_recent(&mut self) -> io::Result<()> {
+    let Some(key) = self
+      .files
+      .iter()
+      .min_by_key(|(_, file)| file.last_used)
+      .map(|(key, _)| key.clone())
+    else {
+      return Ok(());
+    };
+    let mut file = self.files.remove(&key).unwrap();
+    file.writer.flush()
+  }
+}
+
+/// Reads messages from a log file.
+///
+/// Yields one [`OwnedMessage`] per line.
+pub struct LogReader<R> {
+  lines: io::Lines<R>,
+  format: Format,
+}
+
+impl LogReader<BufReader<File>> {
+  /// Open the log file at `path`.
+  pub fn open(path: impl AsRef<Path>, format: Format) -> io::Result<Self> {
+    Ok(Self::new(BufReader::new(File::open(path)?), format))
+  }
+}
+
+impl<R: BufRead> LogReader<R> {
+  /// Read messages in `format` from `reader`.
+  pub fn new(reader: R, format: Format) -> Self {
+    Self {
+      lines: reader.lines(),
+      format,
+    }
+  }
+
+  fn parse(&self, line: String) -> Result<OwnedMessage, LogError> {
+    let raw = match self.format {
+      Format::Text => parse_text_line(&line).ok_or(LogError::Invalid(line))?,
+      #[cfg(feature = "serde")]
+      Format::Jsonl => json::parse(&line)?,
+    };
+    IrcMessage::parse(&raw)
+      .and_then(|message| message.into_typed().ok())
+      .ok_or(LogError::Invalid(raw))
+  }
+}
+
+impl<R: BufRead> Iterator for LogReader<R> {
+  type Item = Result<OwnedMessage, LogError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let line = loop {
+      match self.lines.next()? {
+        Ok(line) if line.is_empty() => continue,
+        Ok(line) => break line,
+        Err(e) => return Some(Err(e.into())),
+      }
+    };
+    Some(self.parse(line))
+  }
+}
+
+fn channel_and_timestamp<'a>(message: &'a Message<'_>) -> Option<(&'a str, DateTime<Utc>)> {
+  match message {
+    Message::Privmsg(msg) => Some((msg.channel(), msg.timestamp())),
+    Message::UserNotice(msg) => Some((msg.channel(), msg.timestamp())),
+    Message::ClearChat(msg) => Some((msg.channel(), msg.timestamp())),
+    _ => None,
+  }
+}
+
+/// `channel` without the `#`, with anything which is not a valid login character replaced.
+fn file_name(channel: &str) -> String {
+  channel
+    .trim_start_matches('#')
+    .chars()
+    .map(|c| match c {
+      'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => c.to_ascii_lowercase(),
+      _ => '_',
+    })
+    .collect()
+}
+
+fn text_line(message: &Message<'_>) -> Option<String> {
+  let (channel, timestamp) = channel_and_timestamp(message)?;
+  let body = match message {
+    Message::Privmsg(msg) => privmsg_text(msg),
+    Message::UserNotice(msg) => user_notice_text(msg)?,
+    Message::ClearChat(msg) => clear_chat_text(msg),
+    _ => return None,
+  };
+  Some(format!(
+    "[{}] {channel} {body}",
+    timestamp.format(TEXT_TIMESTAMP)
+  ))
+}
+
+fn privmsg_text(msg: &Privmsg<'_>) -> String {
+  format!("{}: {}", msg.sender().login(), msg.text())
+}
+
+fn user_notice_text(msg: &UserNotice<'_>) -> Option<String> {
+  match (msg.system_message(), msg.text()) {
+    (Some(system), Some(text)) => Some(format!("{system} {text}")),
+    (Some(system), None) => Some(system.into_owned()),
+    (None, Some(text)) => Some(text.to_owned()),
+    (None, None) => None,
+  }
+}
+
+fn clear_chat_text(msg: &ClearChat<'_>) -> String {
+  match msg.action() {
+    Action::Clear => "chat has been cleared".into(),
+    Action::Ban(ban) => format!("{} has been banned", ban.user()),
+    Action::TimeOut(timeout) => format!(
+      "{} has been timed out for {} seconds",
+      timeout.user(),
+      timeout.duration().as_secs()
+    ),
+  }
+}
+
+/// Turn a line in the text format back into a raw IRC message.
+fn parse_text_line(line: &str) -> Option<String> {
+  let rest = line.strip_prefix('[')?;
+  let (timestamp, rest) = rest.split_once("] ")?;
+  let timestamp = NaiveDateTime::parse_from_str(timestamp, TEXT_TIMESTAMP)
+    .ok()?
+    .and_utc()
+    .timestamp_millis();
+  let (channel, body) = rest.split_once(' ')?;
+  if !channel.starts_with('#') {
+    return None;
+  }
+
+  let mut raw = String::new();
+  if body == "chat has been cleared" {
+    let _ = write!(
+      raw,
+      "@room-id=;tmi-sent-ts={timestamp} :tmi.twitch.tv CLEARCHAT {channel}"
+    );
+  } else if let Some((login, text)) = body.split_once(": ").filter(|(login, _)| is_login(login)) {
+    let _ = write!(
+      raw,
+      "@badge-info=;badges=;display-name={login};emotes=;id=;room-id=;tmi-sent-ts={timestamp};user-id= :{login}!{login}@{login}.tmi.twitch.tv PRIVMSG {channel} :{text}"
+    );
+  } else if let Some(user) = body
+    .strip_suffix(" has been banned")
+    .filter(|user| is_login(user))
+  {
+    let _ = write!(
+      raw,
+      "@room-id=;target-user-id=;tmi-sent-ts={timestamp} :tmi.twitch.tv CLEARCHAT {channel} :{user}"
+    );
+  } else if let Some((user, seconds)) = body
+    .strip_suffix(" seconds")
+    .and_then(|body| body.split_once(" has been timed out for "))
+    .filter(|(user, seconds)| is_login(user) && seconds.parse::<u64>().is_ok())
+  {
+    let _ = write!(
+      raw,
+      "@ban-duration={seconds};room-id=;target-user-id=;tmi-sent-ts={timestamp} :tmi.twitch.tv CLEARCHAT {channel} :{user}"
+    );
+  } else {
+    let _ = write!(
+      raw,
+      "@badge-info=;badges=;emotes=;id=;msg-id=;room-id=;system-msg={};tmi-sent-ts={timestamp} :tmi.twitch.tv USERNOTICE {channel}",
+      escape(body)
+    );
+  }
+  Some(raw)
+}
+
+fn is_login(s: &str) -> bool {
+  !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Escape a tag value, the inverse of [`unescape`][crate::irc::unescape].
+fn escape(value: &str) -> String {
+  let mut out = String::with_capacity(value.len());
+  for c in value.chars() {
+    match c {
+      ';' => out.push_str("\\:"),
+      ' ' => out.push_str("\\s"),
+      '\\' => out.push_str("\\\\"),
+      '\r' => out.push_str("\\r"),
+      '\n' => out.push_str("\\n"),
+      c => out.push(c),
+    }
+  }
+  out
+}
+
+#[cfg(feature = "serde")]
+mod json {
+  use super::*;
+
+  #[derive(serde::Serialize)]
+  struct Entry<'a> {
+    timestamp: DateTime<Utc>,
+    channel: &'a str,
+    kind: &'a str,
+    raw: &'a str,
+    message: serde_json::Value,
+  }
+
+  #[derive(serde::Deserialize)]
+  struct Raw {
+    raw: String,
+  }
+
+  pub(super) fn line(
+    message: &OwnedMessage,
+    channel: &str,
+    timestamp: DateTime<Utc>,
+  ) -> Result<String, LogError> {
+    let (kind, typed) = match message.typed() {
+      Message::Privmsg(msg) => ("privmsg", serde_json::to_value(msg)?),
+      Message::UserNotice(msg) => ("usernotice", serde_json::to_value(msg)?),
+      Message::ClearChat(msg) => ("clearchat", serde_json::to_value(msg)?),
+      _ => ("other", serde_json::Value::Null),
+    };
+    let entry = Entry {
+      timestamp,
+      channel,
+      kind,
+      raw: message.raw().raw(),
+      message: typed,
+    };
+    Ok(serde_json::to_string(&entry)?)
+  }
+
+  pub(super) fn parse(line: &str) -> Result<String, LogError> {
+    let entry: Raw = serde_json::from_str(line)?;
+    Ok(entry.raw)
+  }
+}
+
+/// Failed to write or read a log file.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LogError {
+  /// The underlying I/O operation failed.
+  Io(io::Error),
+
+  /// Failed to serialize or deserialize a JSON line.
+  #[cfg(feature = "serde")]
+  Json(serde_json::Error),
+
+  /// A line could not be parsed back into a message.
+  Invalid(String),
+}
+
+impl From<io::Error> for LogError {
+  fn from(value: io::Error) -> Self {
+    Self::Io(value)
+  }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for LogError {
+  fn from(value: serde_json::Error) -> Self {
+    Self::Json(value)
+  }
+}
+
+impl Display for LogError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      LogError::Io(e) => write!(f, "failed to access log file: {e}"),
+      #[cfg(feature = "serde")]
+      LogError::Json(e) => write!(f, "failed to process log line: {e}"),
+      LogError::Invalid(line) => write!(f, "failed to parse log line: `{line}`"),
+    }
+  }
+}
+
+impl std::error::Error for LogError {}
+
+/// The date of a log file written with [`Rotation::Day`] or [`Rotation::ChannelAndDay`].
+pub fn file_date(path: impl AsRef<Path>) -> Option<NaiveDate> {
+  let stem = path.as_ref().file_stem()?.to_str()?;
+  NaiveDate::parse_from_str(stem, "%Y-%m-%d").ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const LINES: &[&str] = &[
+    "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam",
+    "@ban-duration=1;room-id=11148817;target-user-id=148973258;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada :fabzeef",
+    "@room-id=11148817;target-user-id=70948394;tmi-sent-ts=1594561360331 :tmi.twitch.tv CLEARCHAT #pajlada :weeb123",
+    "@room-id=40286300;tmi-sent-ts=1594561392337 :tmi.twitch.tv CLEARCHAT #randers",
+    "@badge-info=subscriber/3;badges=subscriber/0,bits-charity/1;color=#0000FF;display-name=SevenTest1;emotes=30259:0-6;id=37feed0f-b9c7-4c3a-b475-21c6c6d21c3d;login=seventest1;mod=0;msg-id=resub;msg-param-cumulative-months=3;msg-param-streak-months=0;msg-param-should-share-streak=0;msg-param-sub-plan-name=Channel\\sSubscription\\s(Sevens_Test);msg-param-sub-plan=Prime;room-id=11148817;subscriber=1;system-msg=SevenTest1\\ssubscribed\\swith\\sTwitch\\sPrime.\\sThey've\\ssubscribed\\sfor\\s3\\smonths!;tmi-sent-ts=1594583782376;user-id=131225357;user-type= :tmi.twitch.tv USERNOTICE #pajlada :HeyGuys",
+    ":tmi.twitch.tv PING :tmi.twitch.tv",
+  ];
+
+  fn messages() -> Vec<OwnedMessage> {
+    LINES
+      .iter()
+      .map(|line| IrcMessage::parse(line).unwrap().into_typed().unwrap())
+      .collect()
+  }
+
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tmi-logs-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+  }
+
+  #[test]
+  fn text_format() {
+    let lines = messages()
+      .iter()
+      .filter_map(|message| text_line(message.typed()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      lines,
+      [
+        "[2020-07-12 09:12:35] #pajlada jun1orrrr: dank cam",
+        "[2020-07-12 11:37:08] #pajlada fabzeef has been timed out for 1 seconds",
+        "[2020-07-12 13:42:40] #pajlada weeb123 has been banned",
+        "[2020-07-12 13:43:12] #randers chat has been cleared",
+        "[2020-07-12 19:56:22] #pajlada SevenTest1 subscribed with Twitch Prime. They've subscribed for 3 months! HeyGuys",
+      ]
+    );
+
+    // every line is parsed back into a message which produces the same line
+    let log = lines.join("\n");
+    let reader = LogReader::new(log.as_bytes(), Format::Text);
+    let read = reader
+      .map(|message| text_line(message.unwrap().typed()).unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(read, lines);
+
+    // messages which look like a ban or timeout are still messages
+    let log = "[2020-07-12 09:12:35] #pajlada weeb123: lol has been banned\n\
+      [2020-07-12 09:12:35] #pajlada weeb123: has been timed out for 5 seconds";
+    let read = LogReader::new(log.as_bytes(), Format::Text)
+      .map(|message| message.unwrap())
+      .collect::<Vec<_>>();
+    for (message, text) in read
+      .iter()
+      .zip(["lol has been banned", "has been timed out for 5 seconds"])
+    {
+      let Message::Privmsg(msg) = message.typed() else {
+        panic!("expected a privmsg, got {:?}", message.typed());
+      };
+      assert_eq!(msg.sender().login(), "weeb123");
+      assert_eq!(msg.text(), text);
+    }
+
+    let invalid = LogReader::new(&b"not a log line"[..], Format::Text).next();
+    assert!(matches!(invalid, Some(Err(LogError::Invalid(_)))));
+  }
+
+  #[test]
+  fn write_and_rotate() {
+    let dir = temp_dir("rotate");
+    let mut writer = LogWriter::new(&dir, Format::Text, Rotation::ChannelAndDay);
+    let logged = messages()
+      .iter()
+      .map(|message| writer.write(message).unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(logged, [true, true, true, true, true, false]);
+    writer.flush().unwrap();
+
+    let path = dir.join("pajlada").join("2020-07-12.txt");
+    assert_eq!(file_date(&path), NaiveDate::from_ymd_opt(2020, 7, 12));
+    let read = LogReader::open(&path, Format::Text)
+      .unwrap()
+      .collect::<Result<Vec<_>, _>>()
+      .unwrap();
+    assert_eq!(read.len(), 4);
+    assert!(dir.join("randers").join("2020-07-12.txt").exists());
+
+    let writer = LogWriter::new(&dir, Format::Text, Rotation::Day);
+    let timestamp = DateTime::from_timestamp(1594545155, 0).unwrap();
+    assert_eq!(
+      writer.path("#pajlada", timestamp),
+      dir.join("2020-07-12.txt")
+    );
+    let writer = LogWriter::new(&dir, Format::Text, Rotation::Channel);
+    assert_eq!(writer.path("#../x", timestamp), dir.join("___x.txt"));
+
+    // channels are keyed by their file name, and only one file is kept open
+    let mut writer = LogWriter::new(&dir, Format::Text, Rotation::Channel).with_max_open_files(1);
+    let message = messages().remove(0);
+    let other = IrcMessage::parse(LINES[0].replace("#pajlada", "#PAJLADA"))
+      .unwrap()
+      .into_typed()
+      .unwrap();
+    for message in [&message, &other, &messages()[3], &message] {
+      writer.write(message).unwrap();
+      assert_eq!(writer.files.len(), 1);
+    }
+    writer.flush().unwrap();
+    let read = LogReader::open(dir.join("pajlada.txt"), Format::Text)
+      .unwrap()
+      .count();
+    assert_eq!(read, 3);
+    assert!(dir.join("randers.txt").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn jsonl_format() {
+    let dir = temp_dir("jsonl");
+    let mut writer = LogWriter::new(&dir, Format::Jsonl, Rotation::Channel);
+    for message in &messages() {
+      writer.write(message).unwrap();
+    }
+    writer.flush().unwrap();
+
+    let path = dir.join("pajlada.jsonl");
+    let contents = fs::read_to_string(&path).unwrap();
+    let first: serde_json::Value = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+    assert_eq!(first["kind"], "privmsg");
+    assert_eq!(first["channel"], "#pajlada");
+    assert_eq!(first["message"]["text"], "dank cam");
+
+    let read = LogReader::open(&path, Format::Jsonl)
+      .unwrap()
+      .map(|message| message.unwrap().raw().raw().to_owned())
+      .collect::<Vec<_>>();
+    assert_eq!(read, [LINES[0], LINES[1], LINES[2], LINES[4]]);
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,433 @@
+//! ## Moderation tracking
+//!
+//! Twitch reports moderation actions as [`ClearChat`] and [`ClearMsg`] messages,
+//! which only identify the affected user or message. [`ModerationTracker`] keeps a bounded
+//! buffer of recent [`Privmsg`]s per channel, and links each action back to the messages
+//! it affected, producing a [`ModerationEvent`].
+//!
+//! ```rust,no_run
+//! # async fn run(mut client: tmi::Client) -> anyhow::Result<()> {
+//! use tmi::moderation::ModerationTracker;
+//!
+//! let mut tracker = ModerationTracker::default();
+//! loop {
+//!   let message = client.recv().await?;
+//!   if let Some(event) = tracker.handle(&message.as_typed()?) {
+//!     // "#forsen: user timed out for 600s; their last 2 messages were: ..."
+//!     println!("{event}");
+//!   }
+//! }
+//! # }
+//! ```
+
+use crate::msg::{Action, ClearChat, ClearMsg, Message, Privmsg};
+use chrono::{DateTime, Utc};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+
+/// Moderation tracker configuration.
+#[derive(Clone, Debug)]
+pub struct ModerationConfig {
+  /// Number of recent messages kept per channel.
+  pub messages_per_channel: usize,
+
+  /// Number of past events kept per channel.
+  pub events_per_channel: usize,
+
+  /// Maximum number of messages linked to a single ban or timeout.
+  pub messages_per_event: usize,
+}
+
+impl Default for ModerationConfig {
+  fn default() -> Self {
+    Self {
+      messages_per_channel: 500,
+      events_per_channel: 100,
+      messages_per_event: 5,
+    }
+  }
+}
+
+/// The moderation action behind a [`ModerationEvent`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ModerationAction {
+  /// The chat was cleared, or a user was banned or timed out.
+  ClearChat(ClearChat<'static>),
+
+  /// A single message was deleted.
+  ClearMsg(ClearMsg<'static>),
+}
+
+/// A moderation action, together with the messages it affected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModerationEvent {
+  action: ModerationAction,
+  messages: Vec<Privmsg<'static>>,
+}
+
+impl ModerationEvent {
+  /// The underlying moderation action.
+  #[inline]
+  pub fn action(&self) -> &ModerationAction {
+    &self.action
+  }
+
+  /// The affected messages which were still in the buffer, oldest first.
+  ///
+  /// For a ban or timeout, these are the user's last messages, up to
+  /// [`ModerationConfig::messages_per_event`]. For a deletion, this is the deleted message.
+  /// It is always empty for a chat clear.
+  #[inline]
+  pub fn messages(&self) -> &[Privmsg<'static>] {
+    &self.messages
+  }
+
+  /// Channel in which the action was taken.
+  pub fn channel(&self) -> &str {
+    match &self.action {
+      ModerationAction::ClearChat(msg) => msg.channel(),
+      ModerationAction::ClearMsg(msg) => msg.channel(),
+    }
+  }
+
+  /// Time at which the action was taken.
+  pub fn timestamp(&self) -> DateTime<Utc> {
+    match &self.action {
+      ModerationAction::ClearChat(msg) => msg.timestamp(),
+      ModerationAction::ClearMsg(msg) => msg.timestamp(),
+    }
+  }
+
+  /// Login of the affected user, if the action targeted a single user.
+  pub fn user(&self) -> Option<&str> {
+    match &self.action {
+      ModerationAction::ClearChat(msg) => msg.target(),
+      ModerationAction::ClearMsg(msg) => Some(msg.sender()),
+    }
+  }
+}
+
+impl Display for ModerationEvent {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}: ", self.channel())?;
+    match &self.action {
+      ModerationAction::ClearChat(msg) => match msg.action() {
+        Action::Clear => return f.write_str("chat has been cleared"),
+        Action::Ban(ban) => write!(f, "{} banned", ban.user())?,
+        Action::TimeOut(timeout) => write!(
+          f,
+          "{} timed out for {}s",
+          timeout.user(),
+          timeout.duration().as_secs()
+        )?,
+      },
+      ModerationAction::ClearMsg(msg) => {
+        return write!(f, "message from {} deleted: {}", msg.sender(), msg.text())
+      }
+    }
+
+    match self.messages.as_slice() {
+      [] => Ok(()),
+      [message] => write!(f, "; their last message was: {}", message.text()),
+      messages => {
+        write!(f, "; their last {} messages were: ", messages.len())?;
+        for (i, message) in messages.iter().enumerate() {
+          if i > 0 {
+            f.write_str(" | ")?;
+          }
+          f.write_str(message.text())?;
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+#[derive(Debug)]
+struct Entry {
+  message: Privmsg<'static>,
+  deleted: bool,
+}
+
+#[derive(Debug, Default)]
+struct ChannelState {
+  messages: VecDeque<Entry>,
+  events: VecDeque<ModerationEvent>,
+}
+
+/// Links moderation actions to the messages they affected.
+///
+/// See the [module documentation][self].
+#[derive(Debug, Default)]
+pub struct ModerationTracker {
+  config: ModerationConfig,
+  channels: HashMap<String, ChannelState>,
+}
+
+impl ModerationTracker {
+  /// Create an empty tracker.
+  pub fn new(config: ModerationConfig) -> Self {
+    Self {
+      config,
+      channels: HashMap::new(),
+    }
+  }
+
+  /// Process a message.
+  ///
+  /// [`Privmsg`]s are added to the buffer of their channel. [`ClearChat`] and [`ClearMsg`]
+  /// produce a [`ModerationEvent`], which is also added to the channel's history.
+  /// Other messages are ignored.
+  pub fn handle(&mut self, message: &Message<'_>) -> Option<ModerationEvent> {
+    match message {
+      Message::Privmsg(msg) => {
+        let capacity = self.config.messages_per_channel;
+        let state = self.channel_mut(msg.channel());
+        let entry = Entry {
+          message: msg.clone().into_owned(),
+          deleted: false,
+        };
+        push_bounded(&mut state.messages, entry, capacity);
+        None
+      }
+      Message::ClearChat(msg) => {
+        let limit = self.config.messages_per_event;
+        let state = self.channel_mut(msg.channel());
+        let messages = match msg.target() {
+          Some(user) => last_messages_of(&state.messages, user, limit),
+          None => Vec::new(),
+        };
+        let action = ModerationAction::ClearChat(msg.clone().into_owned());
+        Some(self.record(ModerationEvent { action, messages }))
+      }
+      Message::ClearMsg(msg) => {
+        let state = self.channel_mut(msg.channel());
+        // Keep the deleted message in the buffer, so that a later ban or timeout
+        // of the same user still links it.
+        let messages = state
+          .messages
+          .iter_mut()
+          .find(|entry| !entry.deleted && entry.message.id() == msg.target_message_id())
+          .map(|entry| {
+            entry.deleted = true;
+            entry.message.clone()
+          })
+          .into_iter()
+          .collect();
+        let action = ModerationAction::ClearMsg(msg.clone().into_owned());
+        Some(self.record(ModerationEvent { action, messages }))
+      }
+      _ => None,
+    }
+  }
+
+  /// Past events in `channel`, oldest first.
+  pub fn history(&self, channel: &str) -> impl DoubleEndedIterator<Item = &ModerationEvent> + '_ {
+    self
+      .channels
+      .get(channel_key(channel).as_ref())
+      .into_iter()
+      .flat_map(|state| state.events.iter())
+  }
+
+  /// Past events in `channel` which targeted the user with the given `login`, oldest first.
+  pub fn user_history<'a>(
+    &'a self,
+    channel: &str,
+    login: &'a str,
+  ) -> impl DoubleEndedIterator<Item = &'a ModerationEvent> + 'a {
+    self
+      .history(channel)
+      .filter(move |event| event.user() == Some(login))
+  }
+
+  /// Recent messages in `channel` which have not been deleted, oldest first.
+  pub fn messages(&self, channel: &str) -> impl DoubleEndedIterator<Item = &Privmsg<'static>> + '_ {
+    self
+      .channels
+      .get(channel_key(channel).as_ref())
+      .into_iter()
+      .flat_map(|state| state.messages.iter())
+      .filter(|entry| !entry.deleted)
+      .map(|entry| &entry.message)
+  }
+
+  /// Drop all messages and events for `channel`, e.g. after leaving it.
+  pub fn remove(&mut self, channel: &str) {
+    self.channels.remove(channel_key(channel).as_ref());
+  }
+
+  /// The configuration this tracker was created with.
+  #[inline]
+  pub fn config(&self) -> &ModerationConfig {
+    &self.config
+  }
+
+  fn channel_mut(&mut self, channel: &str) -> &mut ChannelState {
+    let channel = channel_key(channel);
+    if !self.channels.contains_key(channel.as_ref()) {
+      self
+        .channels
+        .insert(channel.to_string(), ChannelState::default());
+    }
+    self.channels.get_mut(channel.as_ref()).unwrap()
+  }
+
+  fn record(&mut self, event: ModerationEvent) -> ModerationEvent {
+    let capacity = self.config.events_per_channel;
+    let state = self.channel_mut(event.channel());
+    push_bounded(&mut state.events, event.clone(), capacity);
+    event
+  }
+}
+
+static_assert_send!(ModerationTracker);
+static_assert_sync!(ModerationTracker);
+
+fn push_bounded<T>(queue: &mut VecDeque<T>, value: T, capacity: usize) {
+  if capacity == 0 {
+    return;
+  }
+  if queue.len() == capacity {
+    queue.pop_front();
+  }
+  queue.push_back(value);
+}
+
+fn last_messages_of(
+  messages: &VecDeque<Entry>,
+  login: &str,
+  limit: usize,
+) -> Vec<Privmsg<'static>> {
+  let mut found = messages
+    .iter()
+    .rev()
+    .map(|entry| &entry.message)
+    .filter(|message| message.sender().login() == login)
+    .take(limit)
+    .cloned()
+    .collect::<Vec<_>>();
+  found.reverse();
+  found
+}
+
+fn channel_key(channel: &str) -> Cow<'_, str> {
+  if channel.starts_with('#') && !channel.bytes().any(|b| b.is_ascii_uppercase()) {
+    Cow::Borrowed(channel)
+  } else {
+    let channel = channel.trim_start_matches('#').to_ascii_lowercase();
+    Cow::Owned(format!("#{channel}"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn privmsg(id: &str, login: &str, text: &str) -> String {
+    format!("@badge-info=;badges=;color=;display-name={login};emotes=;flags=;id={id};mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=1;user-type= :{login}!{login}@{login}.tmi.twitch.tv PRIVMSG #pajlada :{text}")
+  }
+
+  fn handle(tracker: &mut ModerationTracker, line: &str) -> Option<ModerationEvent> {
+    tracker.handle(&Message::parse(line).unwrap())
+  }
+
+  #[test]
+  fn links_messages() {
+    let mut tracker = ModerationTracker::new(ModerationConfig {
+      messages_per_event: 2,
+      ..Default::default()
+    });
+    for (i, (login, text)) in [("a", "one"), ("b", "hi"), ("a", "two"), ("a", "three")]
+      .into_iter()
+      .enumerate()
+    {
+      assert!(handle(&mut tracker, &privmsg(&i.to_string(), login, text)).is_none());
+    }
+
+    let event = handle(&mut tracker, "@ban-duration=600;room-id=11148817;target-user-id=1;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada :a").unwrap();
+    assert_eq!(event.user(), Some("a"));
+    assert_eq!(
+      event.to_string(),
+      "#pajlada: a timed out for 600s; their last 2 messages were: two | three"
+    );
+
+    let event = handle(&mut tracker, "@login=b;room-id=;target-msg-id=1;tmi-sent-ts=1594561955611 :tmi.twitch.tv CLEARMSG #pajlada :hi").unwrap();
+    assert_eq!(event.messages().len(), 1);
+    assert_eq!(tracker.messages("#pajlada").count(), 3);
+
+    let event = handle(
+      &mut tracker,
+      "@room-id=11148817;tmi-sent-ts=1594561392337 :tmi.twitch.tv CLEARCHAT #pajlada",
+    )
+    .unwrap();
+    assert_eq!(event.to_string(), "#pajlada: chat has been cleared");
+
+    assert_eq!(tracker.history("#pajlada").count(), 3);
+    assert_eq!(tracker.user_history("#pajlada", "a").count(), 1);
+    assert_eq!(tracker.history("#forsen").count(), 0);
+  }
+
+  #[test]
+  fn bounded_buffers() {
+    let mut tracker = ModerationTracker::new(ModerationConfig {
+      messages_per_channel: 2,
+      events_per_channel: 1,
+      messages_per_event: 5,
+    });
+    for i in 0..3 {
+      handle(&mut tracker, &privmsg(&i.to_string(), "a", &i.to_string()));
+    }
+    let texts = tracker
+      .messages("#pajlada")
+      .map(|m| m.text().to_owned())
+      .collect::<Vec<_>>();
+    assert_eq!(texts, ["1", "2"]);
+
+    for _ in 0..2 {
+      handle(&mut tracker, "@room-id=11148817;target-user-id=1;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada :a");
+    }
+    assert_eq!(tracker.history("#pajlada").count(), 1);
+
+    tracker.remove("#pajlada");
+    assert_eq!(tracker.messages("#pajlada").count(), 0);
+  }
+
+  #[test]
+  fn normalizes_channels() {
+    let mut tracker = ModerationTracker::default();
+    handle(&mut tracker, &privmsg("0", "a", "hi"));
+    handle(&mut tracker, "@room-id=11148817;target-user-id=1;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada :a");
+
+    for channel in ["#pajlada", "pajlada", "#PajLada", "PAJLADA"] {
+      assert_eq!(tracker.messages(channel).count(), 1, "{channel}");
+      assert_eq!(tracker.history(channel).count(), 1, "{channel}");
+    }
+
+    tracker.remove("PajLada");
+    assert_eq!(tracker.messages("#pajlada").count(), 0);
+    assert_eq!(tracker.history("#pajlada").count(), 0);
+  }
+
+  #[test]
+  fn deleted_messages_are_kept_as_context() {
+    let mut tracker = ModerationTracker::default();
+    handle(&mut tracker, &privmsg("0", "a", "one"));
+    handle(&mut tracker, &privmsg("1", "a", "two"));
+
+    let event = handle(&mut tracker, "@login=a;room-id=;target-msg-id=1;tmi-sent-ts=1594561955611 :tmi.twitch.tv CLEARMSG #pajlada :two").unwrap();
+    assert_eq!(event.messages().len(), 1);
+    assert_eq!(tracker.messages("#pajlada").count(), 1);
+
+    // deleting the same message again does not link it twice
+    let event = handle(&mut tracker, "@login=a;room-id=;target-msg-id=1;tmi-sent-ts=1594561955611 :tmi.twitch.tv CLEARMSG #pajlada :two").unwrap();
+    assert!(event.messages().is_empty());
+
+    let event = handle(&mut tracker, "@ban-duration=600;room-id=11148817;target-user-id=1;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada :a").unwrap();
+    assert_eq!(
+      event.to_string(),
+      "#pajlada: a timed out for 600s; their last 2 messages were: one | two"
+    );
+  }
+}
@@ -18,6 +18,12 @@ impl IrcMessage {
   pub fn as_typed(&self) -> Result<Message<'_>, MessageParseError> {
     Message::from_irc(self.as_ref())
   }
+
+  /// Parses the base [`IrcMessage`] into a Twitch-specific [`Message`],
+  /// and bundles the two together in an [`OwnedMessage`].
+  pub fn into_typed(self) -> Result<OwnedMessage, MessageParseError> {
+    OwnedMessage::parse(self)
+  }
 }
 
 impl<'src> IrcMessageRef<'src> {
@@ -38,10 +44,13 @@ pub trait FromIrc<'src>: Sized + private::Sealed {
 /// Note that this one
 #[derive(Clone, Debug)]
 pub enum Message<'src> {
+  Capability(Capability<'src>),
   ClearChat(ClearChat<'src>),
   ClearMsg(ClearMsg<'src>),
+  EndOfNames(EndOfNames<'src>),
   GlobalUserState(GlobalUserState<'src>),
   Join(Join<'src>),
+  Names(Names<'src>),
   Notice(Notice<'src>),
   Part(Part<'src>),
   Ping(Ping<'src>),
@@ -51,8 +60,14 @@ pub enum Message<'src> {
   RoomState(RoomState<'src>),
   UserNotice(UserNotice<'src>),
   UserState(UserState<'src>),
+  Welcome(Welcome<'src>),
   Whisper(Whisper<'src>),
-  Other(IrcMessageRef<'src>),
+
+  /// A message which does not have a Twitch-specific representation.
+  ///
+  /// ⚠ Before `OtherMessage` was introduced, this variant held an [`IrcMessageRef`].
+  /// Use [`OtherMessage::as_ref`] to get it.
+  Other(OtherMessage<'src>),
 }
 
 impl<'src> Message<'src> {
@@ -64,6 +79,147 @@ impl<'src> Message<'src> {
       .ok_or(MessageParseError)
       .and_then(Message::from_irc)
   }
+
+  /// Clone data to give the value a `'static` lifetime.
+  pub fn into_owned(self) -> Message<'static> {
+    match self {
+      Message::Capability(msg) => Message::Capability(msg.into_owned()),
+      Message::ClearChat(msg) => Message::ClearChat(msg.into_owned()),
+      Message::ClearMsg(msg) => Message::ClearMsg(msg.into_owned()),
+      Message::EndOfNames(msg) => Message::EndOfNames(msg.into_owned()),
+      Message::GlobalUserState(msg) => Message::GlobalUserState(msg.into_owned()),
+      Message::Join(msg) => Message::Join(msg.into_owned()),
+      Message::Names(msg) => Message::Names(msg.into_owned()),
+      Message::Notice(msg) => Message::Notice(msg.into_owned()),
+      Message::Part(msg) => Message::Part(msg.into_owned()),
+      Message::Ping(msg) => Message::Ping(msg.into_owned()),
+      Message::Pong(msg) => Message::Pong(msg.into_owned()),
+      Message::Privmsg(msg) => Message::Privmsg(msg.into_owned()),
+      Message::Reconnect => Message::Reconnect,
+      Message::RoomState(msg) => Message::RoomState(msg.into_owned()),
+      Message::UserNotice(msg) => Message::UserNotice(msg.into_owned()),
+      Message::UserState(msg) => Message::UserState(msg.into_owned()),
+      Message::Welcome(msg) => Message::Welcome(msg.into_owned()),
+      Message::Whisper(msg) => Message::Whisper(msg.into_owned()),
+      Message::Other(msg) => Message::Other(msg.into_owned()),
+    }
+  }
+}
+
+/// A message which does not have a Twitch-specific representation.
+///
+/// This borrows the original message, unless it was turned into
+/// its owned variant using [`Message::into_owned`].
+#[derive(Clone, Debug)]
+pub enum OtherMessage<'src> {
+  Borrowed(IrcMessageRef<'src>),
+  Owned(IrcMessage),
+}
+
+impl<'src> OtherMessage<'src> {
+  /// Get the underlying [`IrcMessageRef`].
+  pub fn as_ref(&self) -> IrcMessageRef<'_> {
+    match self {
+      OtherMessage::Borrowed(msg) => msg.clone(),
+      OtherMessage::Owned(msg) => msg.as_ref(),
+    }
+  }
+
+  /// Clone data to give the value a `'static` lifetime.
+  pub fn into_owned(self) -> OtherMessage<'static> {
+    match self {
+      OtherMessage::Borrowed(msg) => OtherMessage::Owned(msg.into_owned()),
+      OtherMessage::Owned(msg) => OtherMessage::Owned(msg),
+    }
+  }
+}
+
+impl<'src> From<IrcMessageRef<'src>> for OtherMessage<'src> {
+  fn from(value: IrcMessageRef<'src>) -> Self {
+    OtherMessage::Borrowed(value)
+  }
+}
+
+impl From<IrcMessage> for OtherMessage<'static> {
+  fn from(value: IrcMessage) -> Self {
+    OtherMessage::Owned(value)
+  }
+}
+
+/// An [`IrcMessage`] bundled together with its parsed [`Message`].
+///
+/// The [`Message`] borrows from the [`IrcMessage`], so unlike [`Message::into_owned`],
+/// constructing this does not copy any data. It can be freely moved around,
+/// for example sent to another task, without having to parse the message again.
+///
+/// ```rust
+/// # fn run() -> anyhow::Result<()> {
+/// let raw = tmi::IrcMessage::parse(":tmi.twitch.tv PING :nonce").unwrap();
+/// let msg = raw.into_typed()?;
+/// assert!(matches!(msg.typed(), tmi::Message::Ping(_)));
+/// # Ok(())
+/// # }
+/// ```
+pub struct OwnedMessage {
+  // `typed` borrows from `raw`, so it must be dropped first,
+  // which is guaranteed by field declaration order.
+  typed: Message<'static>,
+  raw: IrcMessage,
+}
+
+impl OwnedMessage {
+  /// Parse `raw` into a [`Message`], and bundle the two together.
+  pub fn parse(raw: IrcMessage) -> Result<Self, MessageParseError> {
+    Self::try_parse(raw).map_err(|(e, _)| e)
+  }
+
+  /// Like [`OwnedMessage::parse`], but hands `raw` back if it fails to parse.
+  pub(crate) fn try_parse(raw: IrcMessage) -> Result<Self, (MessageParseError, IrcMessage)> {
+    let typed = match raw.as_typed() {
+      Ok(typed) => typed,
+      Err(e) => return Err((e, raw)),
+    };
+    // # Safety:
+    // - `typed` only borrows from the heap allocation owned by `raw`,
+    //   which does not move when `raw` is moved into `Self`.
+    // - `raw` is never exposed mutably, so the allocation is never
+    //   modified or freed while `typed` exists.
+    // - `typed` is never exposed with a `'static` lifetime, only with
+    //   a lifetime bound to `&self`.
+    let typed = unsafe { std::mem::transmute::<Message<'_>, Message<'static>>(typed) };
+    Ok(Self { typed, raw })
+  }
+
+  /// Get the parsed [`Message`].
+  #[inline]
+  pub fn typed(&self) -> &Message<'_> {
+    &self.typed
+  }
+
+  /// Get the [`IrcMessage`] from which the [`Message`] was parsed.
+  #[inline]
+  pub fn raw(&self) -> &IrcMessage {
+    &self.raw
+  }
+
+  /// Discard the parsed [`Message`] and return the underlying [`IrcMessage`].
+  pub fn into_raw(self) -> IrcMessage {
+    self.raw
+  }
+}
+
+impl TryFrom<IrcMessage> for OwnedMessage {
+  type Error = MessageParseError;
+
+  fn try_from(value: IrcMessage) -> Result<Self, Self::Error> {
+    OwnedMessage::parse(value)
+  }
+}
+
+impl std::fmt::Debug for OwnedMessage {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    self.typed().fmt(f)
+  }
 }
 
 /// Failed to parse a message.
@@ -87,11 +243,23 @@ impl<'src> TryFrom<IrcMessageRef<'src>> for Message<'src> {
 impl<'src> FromIrc<'src> for Message<'src> {
   fn from_irc(message: IrcMessageRef<'src>) -> Result<Self, MessageParseError> {
     use crate::irc::Command as C;
+    // These used to be `Message::Other`, so keep returning them as such if they can't be parsed.
+    fn or_other<'src, T: FromIrc<'src> + Into<Message<'src>>>(
+      message: IrcMessageRef<'src>,
+    ) -> Message<'src> {
+      match T::from_irc(message.clone()) {
+        Ok(msg) => msg.into(),
+        Err(_) => Message::Other(message.into()),
+      }
+    }
     Ok(match message.command() {
+      C::Capability => or_other::<Capability>(message),
       C::ClearChat => ClearChat::from_irc(message)?.into(),
       C::ClearMsg => ClearMsg::from_irc(message)?.into(),
+      C::RplEndOfNames => or_other::<EndOfNames>(message),
       C::GlobalUserState => GlobalUserState::from_irc(message)?.into(),
       C::Join => Join::from_irc(message)?.into(),
+      C::RplNames => or_other::<Names>(message),
       C::Notice => Notice::from_irc(message)?.into(),
       C::Part => Part::from_irc(message)?.into(),
       C::Ping => Ping::from_irc(message)?.into(),
@@ -101,8 +269,9 @@ impl<'src> FromIrc<'src> for Message<'src> {
       C::RoomState => RoomState::from_irc(message)?.into(),
       C::UserNotice => UserNotice::from_irc(message)?.into(),
       C::UserState => UserState::from_irc(message)?.into(),
+      C::RplWelcome => or_other::<Welcome>(message),
       C::Whisper => Whisper::from_irc(message)?.into(),
-      _ => Message::Other(message),
+      _ => Message::Other(message.into()),
     })
   }
 }
@@ -390,6 +559,8 @@ fn maybe_clone<T: ToOwned + ?Sized>(v: Cow<'_, T>) -> Cow<'static, T> {
   }
 }
 
+pub mod capability;
+pub use capability::*;
 pub mod clear_chat;
 pub use clear_chat::*;
 pub mod clear_msg;
@@ -398,6 +569,8 @@ pub mod global_user_state;
 pub use global_user_state::*;
 pub mod join;
 pub use join::*;
+pub mod names;
+pub use names::*;
 pub mod notice;
 pub use notice::*;
 pub mod part;
@@ -414,16 +587,21 @@ pub mod user_notice;
 pub use user_notice::*;
 pub mod user_state;
 pub use user_state::*;
+pub mod welcome;
+pub use welcome::*;
 pub mod whisper;
 pub use whisper::*;
 
 mod private {
   pub trait Sealed {}
 }
+impl private::Sealed for Capability<'_> {}
 impl private::Sealed for ClearChat<'_> {}
 impl private::Sealed for ClearMsg<'_> {}
+impl private::Sealed for EndOfNames<'_> {}
 impl private::Sealed for GlobalUserState<'_> {}
 impl private::Sealed for Join<'_> {}
+impl private::Sealed for Names<'_> {}
 impl private::Sealed for Notice<'_> {}
 impl private::Sealed for Part<'_> {}
 impl private::Sealed for Ping<'_> {}
@@ -432,12 +610,16 @@ impl private::Sealed for Privmsg<'_> {}
 impl private::Sealed for RoomState<'_> {}
 impl private::Sealed for UserNotice<'_> {}
 impl private::Sealed for UserState<'_> {}
+impl private::Sealed for Welcome<'_> {}
 impl private::Sealed for Whisper<'_> {}
 impl private::Sealed for Message<'_> {}
 
 static_assert_send!(Message<'_>);
 static_assert_sync!(Message<'_>);
 
+static_assert_send!(OwnedMessage);
+static_assert_sync!(OwnedMessage);
+
 #[cfg(feature = "serde")]
 mod _serde {
   use super::*;
@@ -465,3 +647,61 @@ mod _serde {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn message_into_owned() {
+    let src = String::from("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam");
+    let owned: Message<'static> = Message::parse(&src).unwrap().into_owned();
+    drop(src);
+    let Message::Privmsg(msg) = owned else {
+      panic!("expected privmsg, got {owned:?}");
+    };
+    assert_eq!(msg.text(), "dank cam");
+  }
+
+  #[test]
+  fn other_into_owned() {
+    let src = String::from(
+      ":tmi.twitch.tv 372 justinfan26682 :You are in a maze of twisty passages, all alike.",
+    );
+    let owned: Message<'static> = Message::parse(&src).unwrap().into_owned();
+    drop(src);
+    let Message::Other(msg) = owned else {
+      panic!("expected other, got {owned:?}");
+    };
+    assert_eq!(msg.as_ref().command(), crate::Command::RplMotd);
+  }
+
+  #[test]
+  fn unparsed_replies_are_other() {
+    for line in [
+      ":tmi.twitch.tv CAP * LS :twitch.tv/commands twitch.tv/tags",
+      ":tmi.twitch.tv CAP * NEW :twitch.tv/membership",
+      ":justinfan1.tmi.twitch.tv 353 justinfan1 =",
+    ] {
+      let msg = Message::parse(line).unwrap();
+      assert!(matches!(msg, Message::Other(_)), "{line}: {msg:?}");
+    }
+    assert!(matches!(
+      Message::parse(":tmi.twitch.tv CAP * ACK :twitch.tv/commands").unwrap(),
+      Message::Capability(_)
+    ));
+  }
+
+  #[test]
+  fn owned_message() {
+    let raw = IrcMessage::parse(":tmi.twitch.tv PING :nonce").unwrap();
+    let msg = std::thread::spawn(move || raw.into_typed().unwrap())
+      .join()
+      .unwrap();
+    let Message::Ping(ping) = msg.typed() else {
+      panic!("expected ping, got {msg:?}");
+    };
+    assert_eq!(ping.nonce(), Some("nonce"));
+    assert_eq!(msg.raw().command(), crate::Command::Ping);
+  }
+}
@@ -0,0 +1,136 @@
+//! Sent in response to a capability request (`CAP REQ`).
+
+use super::{maybe_clone, MessageParseError};
+use crate::irc::{Command, IrcMessageRef};
+use std::borrow::Cow;
+
+/// Sent in response to a capability request (`CAP REQ`).
+///
+/// Twitch either acknowledges (`ACK`) or rejects (`NAK`)
+/// the requested capabilities.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capability<'src> {
+  kind: CapabilityKind,
+
+  #[cfg_attr(feature = "serde", serde(borrow))]
+  capabilities: Vec<Cow<'src, str>>,
+}
+
+generate_getters! {
+  <'src> for Capability<'src> as self {
+    /// Whether the capabilities were acknowledged or rejected.
+    kind -> CapabilityKind,
+
+    /// Iterator over the acknowledged or rejected capabilities,
+    /// e.g. `twitch.tv/commands`.
+    capabilities -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator
+      = self.capabilities.iter().map(|v| v.as_ref()),
+  }
+}
+
+/// Whether the requested capabilities were acknowledged or rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(rename_all = "lowercase")
+)]
+pub enum CapabilityKind {
+  /// `CAP * ACK`
+  Ack,
+
+  /// `CAP * NAK`
+  Nak,
+}
+
+impl<'src> Capability<'src> {
+  /// Returns `true` if the capabilities were acknowledged.
+  #[inline]
+  pub fn is_ack(&self) -> bool {
+    matches!(self.kind, CapabilityKind::Ack)
+  }
+
+  /// Returns `true` if the capabilities were rejected.
+  #[inline]
+  pub fn is_nak(&self) -> bool {
+    matches!(self.kind, CapabilityKind::Nak)
+  }
+
+  fn parse(message: IrcMessageRef<'src>) -> Option<Self> {
+    if message.command() != Command::Capability {
+      return None;
+    }
+
+    // `* ACK :twitch.tv/commands twitch.tv/tags`
+    let kind = match message.params()?.split_whitespace().nth(1)? {
+      "ACK" => CapabilityKind::Ack,
+      "NAK" => CapabilityKind::Nak,
+      _ => return None,
+    };
+
+    Some(Capability {
+      kind,
+      capabilities: message
+        .text()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(Cow::Borrowed)
+        .collect(),
+    })
+  }
+
+  /// Clone data to give the value a `'static` lifetime.
+  pub fn into_owned(self) -> Capability<'static> {
+    Capability {
+      kind: self.kind,
+      capabilities: self.capabilities.into_iter().map(maybe_clone).collect(),
+    }
+  }
+}
+
+impl<'src> super::FromIrc<'src> for Capability<'src> {
+  #[inline]
+  fn from_irc(message: IrcMessageRef<'src>) -> Result<Self, MessageParseError> {
+    Self::parse(message).ok_or(MessageParseError)
+  }
+}
+
+impl<'src> From<Capability<'src>> for super::Message<'src> {
+  fn from(msg: Capability<'src>) -> Self {
+    super::Message::Capability(msg)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_capability_ack() {
+    assert_irc_snapshot!(
+      Capability,
+      ":tmi.twitch.tv CAP * ACK :twitch.tv/commands twitch.tv/tags twitch.tv/membership"
+    );
+  }
+
+  #[test]
+  fn parse_capability_nak() {
+    assert_irc_snapshot!(Capability, ":tmi.twitch.tv CAP * NAK :twitch.tv/invalid");
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn roundtrip_capability_ack() {
+    assert_irc_roundtrip!(
+      Capability,
+      ":tmi.twitch.tv CAP * ACK :twitch.tv/commands twitch.tv/tags twitch.tv/membership"
+    );
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn roundtrip_capability_nak() {
+    assert_irc_roundtrip!(Capability, ":tmi.twitch.tv CAP * NAK :twitch.tv/invalid");
+  }
+}
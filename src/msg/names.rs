@@ -0,0 +1,192 @@
+//! Sent after joining a channel, listing the users who are in it.
+//!
+//! This requires the `twitch.tv/membership` capability.
+
+use super::{maybe_clone, MessageParseError};
+use crate::irc::{Command, IrcMessageRef};
+use std::borrow::Cow;
+
+/// Sent after joining a channel, listing the users who are in it.
+///
+/// Twitch splits long lists into multiple [`Names`] messages,
+/// which are then followed by a single [`EndOfNames`] message.
+/// Use [`Names::merge`] to combine them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Names<'src> {
+  #[cfg_attr(feature = "serde", serde(borrow))]
+  channel: Cow<'src, str>,
+
+  #[cfg_attr(feature = "serde", serde(borrow))]
+  users: Vec<Cow<'src, str>>,
+}
+
+generate_getters! {
+  <'src> for Names<'src> as self {
+    /// Name of the channel.
+    channel -> &str = self.channel.as_ref(),
+
+    /// Iterator over the logins of users in the channel.
+    users -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator
+      = self.users.iter().map(|v| v.as_ref()),
+
+    /// Number of users in this list.
+    num_users -> usize = self.users.len(),
+  }
+}
+
+impl<'src> Names<'src> {
+  /// Append the users from `other` to this list.
+  ///
+  /// Returns `false` and leaves `self` unchanged if `other`
+  /// belongs to a different channel.
+  pub fn merge(&mut self, other: Names<'src>) -> bool {
+    if self.channel != other.channel {
+      return false;
+    }
+    self.users.extend(other.users);
+    true
+  }
+
+  fn parse(message: IrcMessageRef<'src>) -> Option<Self> {
+    if message.command() != Command::RplNames {
+      return None;
+    }
+
+    Some(Names {
+      channel: message.channel()?.into(),
+      users: message
+        .text()?
+        .split_whitespace()
+        .map(Cow::Borrowed)
+        .collect(),
+    })
+  }
+
+  /// Clone data to give the value a `'static` lifetime.
+  pub fn into_owned(self) -> Names<'static> {
+    Names {
+      channel: maybe_clone(self.channel),
+      users: self.users.into_iter().map(maybe_clone).collect(),
+    }
+  }
+}
+
+impl<'src> super::FromIrc<'src> for Names<'src> {
+  #[inline]
+  fn from_irc(message: IrcMessageRef<'src>) -> Result<Self, MessageParseError> {
+    Self::parse(message).ok_or(MessageParseError)
+  }
+}
+
+impl<'src> From<Names<'src>> for super::Message<'src> {
+  fn from(msg: Names<'src>) -> Self {
+    super::Message::Names(msg)
+  }
+}
+
+/// Sent after the last [`Names`] message for a channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EndOfNames<'src> {
+  #[cfg_attr(feature = "serde", serde(borrow))]
+  channel: Cow<'src, str>,
+}
+
+generate_getters! {
+  <'src> for EndOfNames<'src> as self {
+    /// Name of the channel.
+    channel -> &str = self.channel.as_ref(),
+  }
+}
+
+impl<'src> EndOfNames<'src> {
+  fn parse(message: IrcMessageRef<'src>) -> Option<Self> {
+    if message.command() != Command::RplEndOfNames {
+      return None;
+    }
+
+    Some(EndOfNames {
+      channel: message.channel()?.into(),
+    })
+  }
+
+  /// Clone data to give the value a `'static` lifetime.
+  pub fn into_owned(self) -> EndOfNames<'static> {
+    EndOfNames {
+      channel: maybe_clone(self.channel),
+    }
+  }
+}
+
+impl<'src> super::FromIrc<'src> for EndOfNames<'src> {
+  #[inline]
+  fn from_irc(message: IrcMessageRef<'src>) -> Result<Self, MessageParseError> {
+    Self::parse(message).ok_or(MessageParseError)
+  }
+}
+
+impl<'src> From<EndOfNames<'src>> for super::Message<'src> {
+  fn from(msg: EndOfNames<'src>) -> Self {
+    super::Message::EndOfNames(msg)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_names() {
+    assert_irc_snapshot!(
+      Names,
+      ":justinfan26682.tmi.twitch.tv 353 justinfan26682 = #pajlada :localaniki pajlada supibot"
+    );
+  }
+
+  #[test]
+  fn parse_end_of_names() {
+    assert_irc_snapshot!(
+      EndOfNames,
+      ":justinfan26682.tmi.twitch.tv 366 justinfan26682 #pajlada :End of /NAMES list"
+    );
+  }
+
+  #[test]
+  fn merge_names() {
+    let mut names = crate::msg::macros::_parse_irc::<Names>(
+      ":justinfan26682.tmi.twitch.tv 353 justinfan26682 = #pajlada :localaniki pajlada supibot",
+    );
+    let more = crate::msg::macros::_parse_irc::<Names>(
+      ":justinfan26682.tmi.twitch.tv 353 justinfan26682 = #pajlada :justinfan26682",
+    );
+    let other = crate::msg::macros::_parse_irc::<Names>(
+      ":justinfan26682.tmi.twitch.tv 353 justinfan26682 = #forsen :forsen",
+    );
+
+    assert!(names.merge(more));
+    assert!(!names.merge(other));
+    assert_eq!(
+      names.users().collect::<Vec<_>>(),
+      ["localaniki", "pajlada", "supibot", "justinfan26682"]
+    );
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn roundtrip_names() {
+    assert_irc_roundtrip!(
+      Names,
+      ":justinfan26682.tmi.twitch.tv 353 justinfan26682 = #pajlada :localaniki pajlada supibot"
+    );
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn roundtrip_end_of_names() {
+    assert_irc_roundtrip!(
+      EndOfNames,
+      ":justinfan26682.tmi.twitch.tv 366 justinfan26682 #pajlada :End of /NAMES list"
+    );
+  }
+}
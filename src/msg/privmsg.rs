@@ -53,6 +53,12 @@ pub struct Privmsg<'src> {
   emotes: Cow<'src, str>,
 
   timestamp: DateTime<Utc>,
+
+  #[cfg_attr(feature = "serde", serde(default))]
+  is_historical: bool,
+
+  #[cfg_attr(feature = "serde", serde(default))]
+  received_timestamp: Option<DateTime<Utc>>,
 }
 
 generate_getters! {
@@ -122,6 +128,15 @@ generate_getters! {
 
     /// The time at which the message was sent.
     timestamp -> DateTime<Utc>,
+
+    /// Whether the message was served by a recent-messages service,
+    /// instead of being received live.
+    is_historical -> bool,
+
+    /// The time at which a recent-messages service received the message.
+    ///
+    /// This is only set for [historical][`Privmsg::is_historical`] messages.
+    received_timestamp -> Option<DateTime<Utc>>,
   }
 }
 
@@ -400,6 +415,8 @@ impl<'src> Privmsg<'src> {
     let bits = message.tag(Tag::Bits).and_then(|bits| bits.parse().ok());
     let emotes = message.tag(Tag::Emotes).unwrap_or_default().into();
     let timestamp = parse_timestamp(message.tag(Tag::TmiSentTs)?)?;
+    let is_historical = message.tag(Tag::Historical).is_some_and(parse_bool);
+    let received_timestamp = message.tag(Tag::RmReceivedTs).and_then(parse_timestamp);
 
     Some(Privmsg {
       channel,
@@ -417,6 +434,8 @@ impl<'src> Privmsg<'src> {
       bits,
       emotes,
       timestamp,
+      is_historical,
+      received_timestamp,
     })
   }
 
@@ -438,6 +457,8 @@ impl<'src> Privmsg<'src> {
       bits: self.bits,
       emotes: maybe_clone(self.emotes),
       timestamp: self.timestamp,
+      is_historical: self.is_historical,
+      received_timestamp: self.received_timestamp,
     }
   }
 }
@@ -608,6 +629,21 @@ mod tests {
     assert_irc_roundtrip!(Privmsg, "@badge-info=;badges=glhf-pledge/1;color=;display-name=pajlada;emotes=;first-msg=0;flags=;id=f6fb34f8-562f-4b4d-b628-32113d0ef4b0;mod=0;pinned-chat-paid-amount=200;pinned-chat-paid-canonical-amount=200;pinned-chat-paid-currency=USD;pinned-chat-paid-exponent=2;pinned-chat-paid-is-system-message=0;pinned-chat-paid-level=ONE;returning-chatter=0;room-id=12345678;subscriber=0;tmi-sent-ts=1687471984306;turbo=0;user-id=12345678;user-type= :pajlada!pajlada@pajlada.tmi.twitch.tv PRIVMSG #channel :This is a pinned message");
   }
 
+  #[cfg(feature = "serde")]
+  #[test]
+  fn deserialize_privmsg_without_history_fields() {
+    let original = Privmsg::parse(IrcMessageRef::parse("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam").unwrap()).unwrap();
+    // serialized before `is_historical` and `received_timestamp` were added
+    let mut value = serde_json::to_value(&original).unwrap();
+    let fields = value.as_object_mut().unwrap();
+    fields.remove("is_historical").unwrap();
+    fields.remove("received_timestamp").unwrap();
+    let json = value.to_string();
+
+    let deserialized: Privmsg<'_> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, original);
+  }
+
   #[test]
   fn regression_invalid_prefix_span_overread() {
     Privmsg::parse(IrcMessageRef::parse("@badge-info=;badges=moments/1;color=;display-name=kovacicdusko2001;emotes=;first-msg=0;flags=;id=97798b78-b5c7-4a0a-bcd4-e9ec12de926a;mod=0;returning-chatter=0;room-id=71092938;subscriber=0;tmi-sent-ts=1663858872621;turbo=0;user-id=251524724;user-type= :kovacicdusko2001!kovacicdusko2001@kovacicdusko2001.tmi.twitch.tv PRIVMSG #xqc :!play").unwrap()).unwrap();
@@ -0,0 +1,78 @@
+//! Sent once upon successful login to Twitch IRC.
+
+use super::{maybe_clone, MessageParseError};
+use crate::irc::{Command, IrcMessageRef};
+use std::borrow::Cow;
+
+/// Sent once upon successful login to Twitch IRC.
+///
+/// This is the `001` numeric reply.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Welcome<'src> {
+  #[cfg_attr(feature = "serde", serde(borrow))]
+  user: Cow<'src, str>,
+
+  #[cfg_attr(feature = "serde", serde(borrow))]
+  text: Cow<'src, str>,
+}
+
+generate_getters! {
+  <'src> for Welcome<'src> as self {
+    /// Login of the logged in user.
+    user -> &str = self.user.as_ref(),
+
+    /// Welcome message.
+    text -> &str = self.text.as_ref(),
+  }
+}
+
+impl<'src> Welcome<'src> {
+  fn parse(message: IrcMessageRef<'src>) -> Option<Self> {
+    if message.command() != Command::RplWelcome {
+      return None;
+    }
+
+    Some(Welcome {
+      user: message.params()?.split_whitespace().next()?.into(),
+      text: message.text()?.into(),
+    })
+  }
+
+  /// Clone data to give the value a `'static` lifetime.
+  pub fn into_owned(self) -> Welcome<'static> {
+    Welcome {
+      user: maybe_clone(self.user),
+      text: maybe_clone(self.text),
+    }
+  }
+}
+
+impl<'src> super::FromIrc<'src> for Welcome<'src> {
+  #[inline]
+  fn from_irc(message: IrcMessageRef<'src>) -> Result<Self, MessageParseError> {
+    Self::parse(message).ok_or(MessageParseError)
+  }
+}
+
+impl<'src> From<Welcome<'src>> for super::Message<'src> {
+  fn from(msg: Welcome<'src>) -> Self {
+    super::Message::Welcome(msg)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_welcome() {
+    assert_irc_snapshot!(Welcome, ":tmi.twitch.tv 001 justinfan26682 :Welcome, GLHF!");
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn roundtrip_welcome() {
+    assert_irc_roundtrip!(Welcome, ":tmi.twitch.tv 001 justinfan26682 :Welcome, GLHF!");
+  }
+}
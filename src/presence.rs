@@ -0,0 +1,310 @@
+//! ## Presence tracking
+//!
+//! With the `twitch.tv/membership` capability, Twitch reports who is in a channel:
+//! - a [`Names`] list, split across multiple messages and terminated by [`EndOfNames`],
+//!   is sent after joining a channel,
+//! - [`Join`][crate::Join] and [`Part`][crate::Part] messages are sent as users come and go,
+//!   in batches every few seconds.
+//!
+//! [`PresenceTracker`] combines these into a set of chatters per channel. Anyone who sends a
+//! message is also considered present, because the membership messages are delayed, and not
+//! sent at all in channels with many chatters.
+//!
+//! ```rust,no_run
+//! # async fn run(mut client: tmi::Client) -> anyhow::Result<()> {
+//! use tmi::presence::{PresenceEvent, PresenceTracker};
+//!
+//! let mut tracker = PresenceTracker::new();
+//! loop {
+//!   let message = client.recv().await?;
+//!   for event in tracker.handle(&message.as_typed()?) {
+//!     match event {
+//!       PresenceEvent::Joined { channel, user } => println!("{user} joined {channel}"),
+//!       PresenceEvent::Left { channel, user } => println!("{user} left {channel}"),
+//!     }
+//!   }
+//!   println!("{} chatters in #forsen", tracker.chatter_count("#forsen"));
+//! }
+//! # }
+//! ```
+
+use crate::msg::{EndOfNames, Message, Names};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+/// A change in the presence of a user, produced by [`PresenceTracker::handle`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PresenceEvent {
+  /// The user is now present in the channel.
+  Joined { channel: String, user: String },
+
+  /// The user is no longer present in the channel.
+  Left { channel: String, user: String },
+}
+
+impl PresenceEvent {
+  /// Name of the channel, prefixed with `#`.
+  pub fn channel(&self) -> &str {
+    match self {
+      PresenceEvent::Joined { channel, .. } | PresenceEvent::Left { channel, .. } => channel,
+    }
+  }
+
+  /// Login of the user.
+  pub fn user(&self) -> &str {
+    match self {
+      PresenceEvent::Joined { user, .. } | PresenceEvent::Left { user, .. } => user,
+    }
+  }
+}
+
+/// Tracks which users are present in each channel.
+///
+/// See the [module documentation][self].
+#[derive(Clone, Debug, Default)]
+pub struct PresenceTracker {
+  channels: HashMap<String, HashSet<String>>,
+  /// [`Names`] lists which have not been terminated by an [`EndOfNames`] yet.
+  names: HashMap<String, Names<'static>>,
+}
+
+impl PresenceTracker {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Process a message, and return the resulting changes.
+  ///
+  /// Once a [`Names`] list is complete, the channel's chatters are replaced by the list.
+  pub fn handle(&mut self, message: &Message<'_>) -> Vec<PresenceEvent> {
+    match message {
+      Message::Join(msg) => self.join(msg.channel(), msg.user()).into_iter().collect(),
+      Message::Part(msg) => self.part(msg.channel(), msg.user()).into_iter().collect(),
+      Message::Privmsg(msg) => self
+        .join(msg.channel(), msg.sender().login())
+        .into_iter()
+        .collect(),
+      Message::UserNotice(msg) => msg
+        .sender()
+        .and_then(|sender| self.join(msg.channel(), sender.login()))
+        .into_iter()
+        .collect(),
+      Message::Names(msg) => {
+        self.names(msg);
+        Vec::new()
+      }
+      Message::EndOfNames(msg) => self.end_of_names(msg),
+      _ => Vec::new(),
+    }
+  }
+
+  /// Whether the user with the given `login` is present in `channel`.
+  pub fn is_present(&self, channel: &str, login: &str) -> bool {
+    self
+      .channels
+      .get(channel_key(channel).as_ref())
+      .is_some_and(|chatters| chatters.contains(&login.to_ascii_lowercase()))
+  }
+
+  /// Number of users present in `channel`.
+  pub fn chatter_count(&self, channel: &str) -> usize {
+    self
+      .channels
+      .get(channel_key(channel).as_ref())
+      .map_or(0, HashSet::len)
+  }
+
+  /// Iterator over the logins of users present in `channel`, in no particular order.
+  pub fn chatters(&self, channel: &str) -> impl Iterator<Item = &str> + '_ {
+    self
+      .channels
+      .get(channel_key(channel).as_ref())
+      .into_iter()
+      .flatten()
+      .map(String::as_str)
+  }
+
+  /// Iterator over the tracked channels.
+  pub fn channels(&self) -> impl Iterator<Item = &str> + '_ {
+    self.channels.keys().map(String::as_str)
+  }
+
+  /// Stop tracking `channel`, e.g. after leaving it.
+  pub fn remove(&mut self, channel: &str) {
+    let channel = channel_key(channel);
+    self.channels.remove(channel.as_ref());
+    self.names.remove(channel.as_ref());
+  }
+
+  fn join(&mut self, channel: &str, user: &str) -> Option<PresenceEvent> {
+    let channel = channel_key(channel);
+    let user = user.to_ascii_lowercase();
+    let chatters = self.channels.entry(channel.to_string()).or_default();
+    if !chatters.insert(user.clone()) {
+      return None;
+    }
+    Some(PresenceEvent::Joined {
+      channel: channel.into_owned(),
+      user,
+    })
+  }
+
+  fn part(&mut self, channel: &str, user: &str) -> Option<PresenceEvent> {
+    let channel = channel_key(channel);
+    let user = user.to_ascii_lowercase();
+    if !self.channels.get_mut(channel.as_ref())?.remove(&user) {
+      return None;
+    }
+    Some(PresenceEvent::Left {
+      channel: channel.into_owned(),
+      user,
+    })
+  }
+
+  fn names(&mut self, msg: &Names<'_>) {
+    let channel = channel_key(msg.channel());
+    match self.names.get_mut(channel.as_ref()) {
+      Some(names) => {
+        names.merge(msg.clone().into_owned());
+      }
+      None => {
+        let names = msg.clone().into_owned();
+        self.names.insert(channel.into_owned(), names);
+      }
+    }
+  }
+
+  fn end_of_names(&mut self, msg: &EndOfNames<'_>) -> Vec<PresenceEvent> {
+    let channel = channel_key(msg.channel());
+    let listed = self
+      .names
+      .remove(channel.as_ref())
+      .map(|names| {
+        names
+          .users()
+          .map(str::to_ascii_lowercase)
+          .collect::<HashSet<_>>()
+      })
+      .unwrap_or_default();
+    let chatters = self.channels.entry(channel.to_string()).or_default();
+
+    let mut events = Vec::new();
+    for user in &listed {
+      if chatters.insert(user.clone()) {
+        events.push(PresenceEvent::Joined {
+          channel: channel.to_string(),
+          user: user.clone(),
+        });
+      }
+    }
+    chatters.retain(|user| {
+      if listed.contains(user) {
+        return true;
+      }
+      events.push(PresenceEvent::Left {
+        channel: channel.to_string(),
+        user: user.clone(),
+      });
+      false
+    });
+    events
+  }
+}
+
+static_assert_send!(PresenceTracker);
+static_assert_sync!(PresenceTracker);
+
+/// `#` followed by the lowercase channel name, like [`ChatBuffer::new`][crate::buffer::ChatBuffer::new].
+fn channel_key(channel: &str) -> Cow<'_, str> {
+  if channel.starts_with('#') && !channel.bytes().any(|b| b.is_ascii_uppercase()) {
+    Cow::Borrowed(channel)
+  } else {
+    let channel = channel.trim_start_matches('#').to_ascii_lowercase();
+    Cow::Owned(format!("#{channel}"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn handle(tracker: &mut PresenceTracker, line: &str) -> Vec<PresenceEvent> {
+    tracker.handle(&Message::parse(line).unwrap())
+  }
+
+  fn joined(user: &str) -> PresenceEvent {
+    PresenceEvent::Joined {
+      channel: "#pajlada".into(),
+      user: user.into(),
+    }
+  }
+
+  fn left(user: &str) -> PresenceEvent {
+    PresenceEvent::Left {
+      channel: "#pajlada".into(),
+      user: user.into(),
+    }
+  }
+
+  #[test]
+  fn names_join_part() {
+    let mut tracker = PresenceTracker::new();
+    assert_eq!(
+      handle(&mut tracker, ":a!a@a.tmi.twitch.tv JOIN #pajlada"),
+      [joined("a")]
+    );
+    assert!(handle(&mut tracker, ":a!a@a.tmi.twitch.tv JOIN #pajlada").is_empty());
+
+    assert!(handle(
+      &mut tracker,
+      ":justinfan1.tmi.twitch.tv 353 justinfan1 = #pajlada :b c"
+    )
+    .is_empty());
+    assert!(handle(
+      &mut tracker,
+      ":justinfan1.tmi.twitch.tv 353 justinfan1 = #pajlada :d"
+    )
+    .is_empty());
+    let mut events = handle(
+      &mut tracker,
+      ":justinfan1.tmi.twitch.tv 366 justinfan1 #pajlada :End of /NAMES list",
+    );
+    events.sort_by(|a, b| a.user().cmp(b.user()));
+    assert_eq!(events, [left("a"), joined("b"), joined("c"), joined("d")]);
+    assert_eq!(tracker.chatter_count("pajlada"), 3);
+
+    assert_eq!(
+      handle(&mut tracker, ":c!c@c.tmi.twitch.tv PART #pajlada"),
+      [left("c")]
+    );
+    assert!(handle(&mut tracker, ":c!c@c.tmi.twitch.tv PART #pajlada").is_empty());
+    assert!(!tracker.is_present("#pajlada", "c"));
+    assert!(tracker.is_present("#pajlada", "B"));
+  }
+
+  #[test]
+  fn chatters_are_present() {
+    let mut tracker = PresenceTracker::new();
+    let line = "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam";
+    assert_eq!(handle(&mut tracker, line), [joined("jun1orrrr")]);
+    assert!(handle(&mut tracker, line).is_empty());
+    assert_eq!(
+      tracker.chatters("#pajlada").collect::<Vec<_>>(),
+      ["jun1orrrr"]
+    );
+
+    // channels are case-insensitive
+    assert_eq!(
+      handle(&mut tracker, ":b!b@b.tmi.twitch.tv JOIN #PajLada"),
+      [joined("b")]
+    );
+    assert_eq!(tracker.chatter_count("#PAJLADA"), 2);
+    assert_eq!(
+      handle(&mut tracker, ":b!b@b.tmi.twitch.tv PART #pajlada"),
+      [left("b")]
+    );
+
+    tracker.remove("PajLada");
+    assert_eq!(tracker.channels().count(), 0);
+  }
+}
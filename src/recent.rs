@@ -0,0 +1,150 @@
+//! ## Recent messages
+//!
+//! Recent-messages services, such as [recent-messages.robotty.de](https://recent-messages.robotty.de),
+//! keep a backlog of chat messages which clients can use to fill in history when joining a channel.
+//! They serve the backlog as raw IRC lines, tagged with `historical=1` and `rm-received-ts`,
+//! see [`Privmsg::is_historical`][crate::Privmsg::is_historical] and
+//! [`Privmsg::received_timestamp`][crate::Privmsg::received_timestamp].
+//!
+//! [`RecentMessages`] parses such a response into typed messages, oldest first.
+//!
+//! ```rust
+//! # fn run() -> Result<(), tmi::recent::RecentError> {
+//! let body = r#"{"messages": [
+//!   "@historical=1;rm-received-ts=1594554085918;badge-info=;badges=;color=;display-name=CarvedTaleare;emotes=;flags=;id=c9b941d9-a0ab-4534-9903-971768fcdf10;mod=0;room-id=22484632;subscriber=0;tmi-sent-ts=1594554085753;turbo=0;user-id=467684514;user-type= :carvedtaleare!carvedtaleare@carvedtaleare.tmi.twitch.tv PRIVMSG #forsen :NaM"
+//! ]}"#;
+//!
+//! for message in tmi::recent::RecentMessages::from_json(body)? {
+//!   if let tmi::Message::Privmsg(msg) = message?.typed() {
+//!     assert!(msg.is_historical());
+//!     println!("{}: {}", msg.sender().name(), msg.text());
+//!   }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::irc::IrcMessage;
+use crate::msg::OwnedMessage;
+use std::fmt::Display;
+
+/// Iterator over the messages in a recent-messages response.
+pub struct RecentMessages {
+  lines: std::vec::IntoIter<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Response {
+  Lines(Vec<String>),
+  Object { messages: Vec<String> },
+}
+
+impl RecentMessages {
+  /// Parse a response body.
+  ///
+  /// The body may either be a JSON array of raw IRC lines,
+  /// or a JSON object with such an array in its `messages` field.
+  pub fn from_json(json: &str) -> Result<Self, RecentError> {
+    let lines = match serde_json::from_str(json)? {
+      Response::Lines(lines) => lines,
+      Response::Object { messages } => messages,
+    };
+    Ok(Self::from_lines(lines))
+  }
+
+  /// Iterate over already-extracted raw IRC lines.
+  pub fn from_lines(lines: impl IntoIterator<Item = String>) -> Self {
+    Self {
+      lines: lines.into_iter().collect::<Vec<_>>().into_iter(),
+    }
+  }
+}
+
+impl Iterator for RecentMessages {
+  type Item = Result<OwnedMessage, RecentError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let line = self.lines.next()?;
+    let Some(message) = IrcMessage::parse(&line) else {
+      return Some(Err(RecentError::Invalid(line)));
+    };
+    Some(message.into_typed().map_err(|_| RecentError::Invalid(line)))
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.lines.size_hint()
+  }
+}
+
+impl ExactSizeIterator for RecentMessages {}
+
+static_assert_send!(RecentMessages);
+static_assert_sync!(RecentMessages);
+
+/// Failed to parse a recent-messages response.
+#[derive(Debug)]
+pub enum RecentError {
+  /// The response body is not valid JSON, or has an unexpected shape.
+  Json(serde_json::Error),
+
+  /// A line could not be parsed as a message.
+  Invalid(String),
+}
+
+impl From<serde_json::Error> for RecentError {
+  fn from(value: serde_json::Error) -> Self {
+    Self::Json(value)
+  }
+}
+
+impl Display for RecentError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      RecentError::Json(e) => write!(f, "failed to parse recent messages: {e}"),
+      RecentError::Invalid(line) => write!(f, "failed to parse recent message: `{line}`"),
+    }
+  }
+}
+
+impl std::error::Error for RecentError {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::msg::Message;
+
+  const LINE: &str = "@room-id=11148817;tmi-sent-ts=1723702053033;color=#B7B6F9;reply-parent-msg-body=@RomeoGiggleToess\\shttps://www.youtube.com/watch?v=khMb3k-Wwvg;emotes=;flags=;reply-parent-user-id=53888434;id=96a5fb70-f54e-4640-979e-529a76ddf74b;reply-thread-parent-display-name=RomeoGiggleToess;reply-thread-parent-msg-id=fd2a5663-00cb-4e78-9c0d-aff6b66285bf;subscriber=0;historical=1;reply-parent-display-name=OGprodigy;mod=0;badges=twitch-dj/1;first-msg=0;user-id=86336791;reply-parent-user-login=ogprodigy;turbo=0;user-type=;reply-parent-msg-id=a504ba7e-d991-45d0-ab2f-c3045c6ae7b6;reply-thread-parent-user-login=romeogiggletoess;returning-chatter=0;display-name=RomeoGiggleToess;badge-info=;reply-thread-parent-user-id=86336791;rm-received-ts=1723702053240 :romeogiggletoess!romeogiggletoess@romeogiggletoess.tmi.twitch.tv PRIVMSG #pajlada :@OGprodigy klassiker";
+
+  #[test]
+  fn parse_response() {
+    let body = serde_json::json!({ "messages": [LINE, ":tmi.twitch.tv "], "error": null });
+    let mut messages = RecentMessages::from_json(&body.to_string()).unwrap();
+    assert_eq!(messages.len(), 2);
+
+    let message = messages.next().unwrap().unwrap();
+    let Message::Privmsg(msg) = message.typed() else {
+      panic!("expected privmsg, got {message:?}");
+    };
+    assert!(msg.is_historical());
+    assert_eq!(
+      msg.received_timestamp().map(|ts| ts.timestamp_millis()),
+      Some(1723702053240)
+    );
+    assert!(matches!(
+      messages.next(),
+      Some(Err(RecentError::Invalid(_)))
+    ));
+    assert!(messages.next().is_none());
+
+    let body = serde_json::json!([LINE]);
+    assert_eq!(
+      RecentMessages::from_json(&body.to_string()).unwrap().len(),
+      1
+    );
+    assert!(matches!(
+      RecentMessages::from_json("{}"),
+      Err(RecentError::Json(_))
+    ));
+  }
+}
@@ -0,0 +1,492 @@
+//! ## Testing
+//!
+//! An in-process fake of Twitch IRC, for testing code which uses a [`Client`].
+//!
+//! [`FakeServer`] performs the handshake, answers `JOIN`, `PART` and `PING` the way Twitch does,
+//! records every line the client sends, and lets tests inject arbitrary server lines.
+//! Connections are opened through an [`Endpoint::custom`], so no network access is needed.
+//!
+//! ```rust
+//! # async fn run() -> anyhow::Result<()> {
+//! use tmi::testing::FakeServer;
+//!
+//! let mut server = FakeServer::new();
+//! let client = server.connect().await?;
+//!
+//! client.join("#forsen").await?;
+//! client.privmsg("#forsen", "yo").send().await?;
+//! assert_eq!(server.expect("PRIVMSG").await.line, "PRIVMSG #forsen :yo");
+//!
+//! server.send(":tmi.twitch.tv RECONNECT");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::conn::Endpoint;
+use crate::client::{Backoff, Client, ClientBuilder, ConnectError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::sync::{mpsc, watch};
+use tokio::task::AbortHandle;
+
+/// How long [`FakeServer::expect`] waits before panicking.
+pub const EXPECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A line sent by a client to a [`FakeServer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sent {
+  /// The connection the line was sent on, counting from `0`.
+  pub connection: usize,
+
+  /// The line, without the trailing `\r\n`.
+  pub line: String,
+}
+
+/// A scriptable fake of Twitch IRC.
+///
+/// See the [module documentation][self].
+///
+/// Cloning the server creates another handle to it, with its own [`FakeServer::next`] cursor.
+pub struct FakeServer {
+  shared: Arc<Shared>,
+  sent: watch::Receiver<usize>,
+  cursor: usize,
+}
+
+struct Shared {
+  state: Mutex<State>,
+  sent: watch::Sender<usize>,
+}
+
+#[derive(Default)]
+struct State {
+  connections: Vec<Connection>,
+  sent: Vec<Sent>,
+  rooms: Vec<String>,
+}
+
+struct Connection {
+  out: mpsc::UnboundedSender<String>,
+  nick: Option<String>,
+  /// Lines injected before the handshake completed.
+  pending: Option<Vec<String>>,
+  tasks: Vec<AbortHandle>,
+}
+
+impl FakeServer {
+  /// Create a server with no connections.
+  ///
+  /// Clients connect to it through [`FakeServer::endpoint`] or [`FakeServer::builder`].
+  pub fn new() -> Self {
+    let (sent, rx) = watch::channel(0);
+    Self {
+      shared: Arc::new(Shared {
+        state: Mutex::new(State::default()),
+        sent,
+      }),
+      sent: rx,
+      cursor: 0,
+    }
+  }
+
+  /// An endpoint which opens a new connection to this server.
+  pub fn endpoint(&self) -> Endpoint {
+    let shared = self.shared.clone();
+    Endpoint::custom(move || {
+      let (client, server) = tokio::io::duplex(64 * 1024);
+      shared.accept(server);
+      async move { Ok(client) }
+    })
+  }
+
+  /// A client builder which connects to this server, and reconnects without delay.
+  pub fn builder(&self) -> ClientBuilder {
+    Client::builder()
+      .endpoint(self.endpoint())
+      .backoff(Backoff {
+        initial_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(1),
+        ..Default::default()
+      })
+  }
+
+  /// Connect a client to this server, using anonymous credentials.
+  pub async fn connect(&self) -> Result<Client, ConnectError> {
+    self.builder().connect().await
+  }
+
+  /// Send `line` on the latest connection.
+  ///
+  /// `\r\n` is appended if it's missing. If the handshake has not completed yet,
+  /// the line is sent right after it.
+  ///
+  /// Panics if no client has connected yet.
+  pub fn send(&self, line: impl Into<String>) {
+    let mut state = self.shared.state.lock().unwrap();
+    let connection = state.connections.len().checked_sub(1);
+    let connection = connection.expect("no client has connected yet");
+    state.send(connection, line.into());
+  }
+
+  /// Send `line` on connection number `connection`, see [`FakeServer::send`].
+  pub fn send_to(&self, connection: usize, line: impl Into<String>) {
+    self
+      .shared
+      .state
+      .lock()
+      .unwrap()
+      .send(connection, line.into());
+  }
+
+  /// Close the latest connection.
+  pub fn disconnect(&self) {
+    let state = self.shared.state.lock().unwrap();
+    if let Some(connection) = state.connections.last() {
+      for task in &connection.tasks {
+        task.abort();
+      }
+    }
+  }
+
+  /// The number of connections opened so far.
+  pub fn connections(&self) -> usize {
+    self.shared.state.lock().unwrap().connections.len()
+  }
+
+  /// Every line sent by clients so far, in order.
+  pub fn sent(&self) -> Vec<Sent> {
+    self.shared.state.lock().unwrap().sent.clone()
+  }
+
+  /// Wait for the next line sent by a client.
+  pub async fn next(&mut self) -> Sent {
+    loop {
+      if let Some(sent) = self.shared.state.lock().unwrap().sent.get(self.cursor) {
+        self.cursor += 1;
+        return sent.clone();
+      }
+      // the sender lives in `shared`, so this can't fail
+      let _ = self.sent.changed().await;
+    }
+  }
+
+  /// Skip lines sent by clients until one starts with `prefix`.
+  ///
+  /// Panics if no such line is sent within [`EXPECT_TIMEOUT`].
+  pub async fn expect(&mut self, prefix: &str) -> Sent {
+    let result = tokio::time::timeout(EXPECT_TIMEOUT, async {
+      loop {
+        let sent = self.next().await;
+        if sent.line.starts_with(prefix) {
+          return sent;
+        }
+      }
+    })
+    .await;
+    match result {
+      Ok(sent) => sent,
+      Err(_) => panic!(
+        "expected a line starting with `{prefix}`, received: {:#?}",
+        self.sent()
+      ),
+    }
+  }
+}
+
+impl Default for FakeServer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Clone for FakeServer {
+  fn clone(&self) -> Self {
+    Self {
+      shared: self.shared.clone(),
+      sent: self.sent.clone(),
+      cursor: 0,
+    }
+  }
+}
+
+impl Shared {
+  fn accept(self: &Arc<Self>, stream: DuplexStream) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let (out, mut rx) = mpsc::unbounded_channel::<String>();
+
+    let mut state = self.state.lock().unwrap();
+    let id = state.connections.len();
+    let read = tokio::spawn({
+      let shared = self.clone();
+      async move {
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+          shared.receive(id, line);
+        }
+      }
+    });
+    let write = tokio::spawn(async move {
+      while let Some(line) = rx.recv().await {
+        if writer.write_all(line.as_bytes()).await.is_err() {
+          break;
+        }
+      }
+    });
+    state.connections.push(Connection {
+      out,
+      nick: None,
+      pending: Some(Vec::new()),
+      tasks: vec![read.abort_handle(), write.abort_handle()],
+    });
+  }
+
+  fn receive(&self, id: usize, line: String) {
+    let mut state = self.state.lock().unwrap();
+    state.respond(id, &line);
+    state.sent.push(Sent {
+      connection: id,
+      line,
+    });
+    let len = state.sent.len();
+    drop(state);
+    let _ = self.sent.send(len);
+  }
+}
+
+impl State {
+  fn send(&mut self, id: usize, mut line: String) {
+    if !line.ends_with("\r\n") {
+      line.push_str("\r\n");
+    }
+    let connection = &mut self.connections[id];
+    match &mut connection.pending {
+      Some(pending) => pending.push(line),
+      None => {
+        let _ = connection.out.send(line);
+      }
+    }
+  }
+
+  fn reply(&self, id: usize, line: String) {
+    let _ = self.connections[id].out.send(line + "\r\n");
+  }
+
+  /// Answer `line` the way Twitch would.
+  fn respond(&mut self, id: usize, line: &str) {
+    let (command, params) = line.split_once(' ').unwrap_or((line, ""));
+    match command {
+      "CAP" => {
+        if let Some(caps) = params.strip_prefix("REQ :") {
+          self.reply(id, format!(":tmi.twitch.tv CAP * ACK :{caps}"));
+        }
+      }
+      "NICK" => self.handshake(id, params),
+      "PING" => {
+        let nonce = params.strip_prefix(':').unwrap_or(params);
+        self.reply(id, format!(":tmi.twitch.tv PONG tmi.twitch.tv :{nonce}"));
+      }
+      "JOIN" => {
+        for channel in params.split(',') {
+          self.join(id, channel);
+        }
+      }
+      "PART" => {
+        let nick = self.nick(id);
+        for channel in params.split(',') {
+          self.reply(
+            id,
+            format!(":{nick}!{nick}@{nick}.tmi.twitch.tv PART {channel}"),
+          );
+        }
+      }
+      "PRIVMSG" => {
+        if let Some((channel, _)) = params.split_once(' ') {
+          self.user_state(id, channel);
+        }
+      }
+      _ if command.starts_with('@') => {
+        // tagged message, such as a reply
+        self.respond(id, params);
+      }
+      _ => {}
+    }
+  }
+
+  fn nick(&self, id: usize) -> &str {
+    self.connections[id].nick.as_deref().unwrap_or("justinfan")
+  }
+
+  fn handshake(&mut self, id: usize, nick: &str) {
+    self.connections[id].nick = Some(nick.to_owned());
+    self.reply(id, format!(":tmi.twitch.tv 001 {nick} :Welcome, GLHF!"));
+    if !nick.starts_with("justinfan") {
+      self.reply(
+        id,
+        format!(
+          "@badge-info=;badges=;color=;display-name={nick};emote-sets=0;user-id={};user-type= :tmi.twitch.tv GLOBALUSERSTATE",
+          user_id(nick)
+        ),
+      );
+    }
+
+    let connection = &mut self.connections[id];
+    for line in connection.pending.take().unwrap_or_default() {
+      let _ = connection.out.send(line);
+    }
+  }
+
+  fn join(&mut self, id: usize, channel: &str) {
+    let room_id = match self.rooms.iter().position(|c| c == channel) {
+      Some(index) => index + 1,
+      None => {
+        self.rooms.push(channel.to_owned());
+        self.rooms.len()
+      }
+    };
+    let nick = self.nick(id).to_owned();
+    for line in [
+      format!(":{nick}!{nick}@{nick}.tmi.twitch.tv JOIN {channel}"),
+      format!(":{nick}.tmi.twitch.tv 353 {nick} = {channel} :{nick}"),
+      format!(":{nick}.tmi.twitch.tv 366 {nick} {channel} :End of /NAMES list"),
+    ] {
+      self.reply(id, line);
+    }
+    self.user_state(id, channel);
+    self.reply(
+      id,
+      format!("@emote-only=0;followers-only=-1;r9k=0;room-id={room_id};slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE {channel}"),
+    );
+  }
+
+  fn user_state(&self, id: usize, channel: &str) {
+    let nick = self.nick(id);
+    self.reply(
+      id,
+      format!("@badge-info=;badges=;color=;display-name={nick};emote-sets=0;mod=0;subscriber=0;user-type= :tmi.twitch.tv USERSTATE {channel}"),
+    );
+  }
+}
+
+/// A stable fake user id for `nick`.
+fn user_id(nick: &str) -> u32 {
+  nick.bytes().fold(17u32, |hash, b| {
+    hash.wrapping_mul(31).wrapping_add(b as u32)
+  }) % 1_000_000_000
+}
+
+static_assert_send!(FakeServer);
+static_assert_sync!(FakeServer);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::client::Credentials;
+  use crate::msg::Message;
+
+  #[tokio::test]
+  async fn handshake_and_join() {
+    let mut server = FakeServer::new();
+    let mut client = server
+      .builder()
+      .credentials(Credentials::new("bot", "oauth:token"))
+      .connect()
+      .await
+      .unwrap();
+    assert!(server
+      .expect("CAP REQ")
+      .await
+      .line
+      .contains("twitch.tv/tags"));
+    assert_eq!(server.expect("PASS").await.line, "PASS oauth:token");
+    assert_eq!(server.expect("NICK").await.line, "NICK bot");
+
+    client.join("#forsen").await.unwrap();
+    assert_eq!(server.expect("JOIN").await.line, "JOIN #forsen");
+
+    let mut kinds = Vec::new();
+    while kinds.len() < 6 {
+      let message = client.recv().await.unwrap();
+      match message.as_typed().unwrap() {
+        Message::GlobalUserState(_) => kinds.push("GLOBALUSERSTATE"),
+        Message::Join(join) => {
+          assert_eq!(join.channel(), "#forsen");
+          kinds.push("JOIN");
+        }
+        Message::UserState(_) => kinds.push("USERSTATE"),
+        Message::RoomState(state) => {
+          assert_eq!(state.channel(), "#forsen");
+          kinds.push("ROOMSTATE");
+        }
+        _ => kinds.push("other"),
+      }
+    }
+    assert_eq!(
+      kinds,
+      [
+        "GLOBALUSERSTATE",
+        "JOIN",
+        "other",
+        "other",
+        "USERSTATE",
+        "ROOMSTATE"
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn inject_and_record() {
+    let mut server = FakeServer::new();
+    let mut client = server.connect().await.unwrap();
+
+    server.send(":tmi.twitch.tv PING :nonce");
+    let message = client.recv().await.unwrap();
+    let Ok(Message::Ping(ping)) = message.as_typed() else {
+      panic!("expected ping, got {message:?}");
+    };
+    client.pong(&ping).await.unwrap();
+    assert_eq!(server.expect("PONG").await.line, "PONG :nonce");
+
+    client
+      .privmsg("#forsen", "yo")
+      .reply_to("abc")
+      .send()
+      .await
+      .unwrap();
+    assert_eq!(
+      server.expect("@").await.line,
+      "@reply-parent-msg-id=abc PRIVMSG #forsen :yo"
+    );
+
+    // every line is recorded, including the handshake
+    let sent = server.sent();
+    assert!(sent[0].line.starts_with("CAP REQ"));
+    assert!(sent.iter().all(|s| s.connection == 0));
+  }
+
+  #[tokio::test]
+  async fn reconnect() {
+    let mut server = FakeServer::new();
+    let mut client = server.connect().await.unwrap();
+
+    server.send(":tmi.twitch.tv RECONNECT");
+    let message = client.recv().await.unwrap();
+    assert!(matches!(message.as_typed(), Ok(Message::Reconnect)));
+    client.reconnect().await.unwrap();
+    assert_eq!(server.connections(), 2);
+
+    client.join("#forsen").await.unwrap();
+    assert_eq!(server.expect("JOIN").await.connection, 1);
+
+    server.disconnect();
+    // drain the responses to the `JOIN`
+    let err = loop {
+      if let Err(e) = client.recv().await {
+        break e;
+      }
+    };
+    assert!(err.is_disconnect());
+    client.reconnect().await.unwrap();
+    assert_eq!(server.connections(), 3);
+  }
+}